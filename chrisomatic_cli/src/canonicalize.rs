@@ -29,16 +29,30 @@ fn guess_global_config(given: GivenGlobal) -> color_eyre::Result<GivenGlobal> {
 
 /// Try to get the [GivenGlobal] from the CUBE container running in Docker or Podman.
 fn infer_global_from_running_container() -> color_eyre::Result<GivenGlobal> {
-    let engines = ["podman", "docker"]
-        .iter()
-        .map(which::which)
-        .filter_map(|engine| engine.map(ContainerEngine::from).ok());
-    for engine in engines {
-        if let Some(cube) = get_chris_container(&engine)? {
-            return Ok(cube);
+    let global = find_cube_container()?
+        .map(|container| container.global)
+        .unwrap_or_default();
+    Ok(global)
+}
+
+/// A CUBE container running in Docker or Podman.
+pub(crate) struct CubeContainer {
+    pub(crate) engine: ContainerEngine,
+    pub(crate) id: String,
+    /// Configuration inferred from the container.
+    pub(crate) global: GivenGlobal,
+}
+
+/// Find the CUBE container running in Docker or Podman.
+///
+/// WARNING: does blocking I/O.
+pub(crate) fn find_cube_container() -> color_eyre::Result<Option<CubeContainer>> {
+    for engine in ContainerEngine::installed() {
+        if let Some(container) = get_chris_container(&engine)? {
+            return Ok(Some(container));
         }
     }
-    Ok(Default::default())
+    Ok(None)
 }
 
 /// A container is identified as _CUBE_ if all:
@@ -46,15 +60,19 @@ fn infer_global_from_running_container() -> color_eyre::Result<GivenGlobal> {
 /// - image contains [CUBE_IMAGE_CONTAINS]
 /// - container port [CUBE_CONTAINER_PORT] is bound to host
 /// - container has environment variable [CUBE_SUPERUSER_ENV]
-fn get_chris_container(engine: &ContainerEngine) -> color_eyre::Result<Option<GivenGlobal>> {
+fn get_chris_container(engine: &ContainerEngine) -> color_eyre::Result<Option<CubeContainer>> {
     for (container_id, image) in engine.running_images()? {
         if !image.contains(CUBE_IMAGE_CONTAINS) {
             continue;
         }
-        let details = engine.inspect(container_id)?;
-        let cube = get_cube_details(details);
-        if cube.is_some() {
-            return Ok(cube);
+        let details = engine.inspect(&container_id)?;
+        if let Some(global) = get_cube_details(details) {
+            let container = CubeContainer {
+                engine: engine.clone(),
+                id: container_id,
+                global,
+            };
+            return Ok(Some(container));
         }
     }
     Ok(None)
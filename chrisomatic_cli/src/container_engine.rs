@@ -12,6 +12,13 @@ use color_eyre::eyre::{Context, bail};
 pub struct ContainerEngine(pub OsString);
 
 impl ContainerEngine {
+    /// Find the container engines which are installed, preferring `podman`.
+    pub(crate) fn installed() -> impl Iterator<Item = Self> {
+        ["podman", "docker"]
+            .into_iter()
+            .filter_map(|engine| which::which(engine).map(Self::from).ok())
+    }
+
     /// List running container IDs and their images.
     pub(crate) fn running_images(&self) -> color_eyre::Result<HashMap<String, String>> {
         let args = ["ps", "--format", r#"{{ printf "%s %s" .ID .Image }}"#];
@@ -49,9 +56,38 @@ impl ContainerEngine {
         Ok(data.try_into()?)
     }
 
+    /// Run a command in a container. The environment variables `env` are
+    /// passed to the command without appearing in the arguments.
+    pub(crate) fn exec(
+        &self,
+        container_id: impl AsRef<str>,
+        env: &[(&str, &str)],
+        command: &[&str],
+    ) -> color_eyre::Result<String> {
+        let mut args = vec!["exec"];
+        for (name, _) in env {
+            args.extend(["-e", *name]);
+        }
+        args.push(container_id.as_ref());
+        args.extend(command);
+        self.cmd_with_env(&args, env)
+    }
+
+    /// Run a command in a new container of `image`, which is removed after.
+    pub(crate) fn run(&self, image: &str, command: &[&str]) -> color_eyre::Result<String> {
+        let mut args = vec!["run", "--rm", image];
+        args.extend(command);
+        self.cmd(&args)
+    }
+
     fn cmd(&self, args: &[&str]) -> color_eyre::Result<String> {
+        self.cmd_with_env(args, &[])
+    }
+
+    fn cmd_with_env(&self, args: &[&str], env: &[(&str, &str)]) -> color_eyre::Result<String> {
         let output = Command::new(&self.0)
             .args(args)
+            .envs(env.iter().copied())
             .output()
             .wrap_err_with(|| {
                 format!(
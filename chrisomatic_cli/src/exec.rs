@@ -1,23 +1,33 @@
 use std::{collections::HashMap, fmt::Display};
 
-use chrisomatic_core::{Counts, StepEffect, fully_exec_tree};
+use chrisomatic_core::{Counts, StepEffect, fully_exec_tree, warnings_of};
 use chrisomatic_spec::Manifest;
 use chrisomatic_step::Dependency;
 use indicatif::{ProgressBar, ProgressStyle};
 use owo_colors::OwoColorize;
 
+use crate::superuser::ensure_superuser;
+
 const USER_AGENT: &'static str = concat!(env!("CARGO_CRATE_NAME"), "/", env!("CARGO_PKG_VERSION"));
 
 pub(crate) async fn exec_with_progress(
     manifest: Manifest,
+    create_superuser: bool,
 ) -> color_eyre::Result<HashMap<Dependency, StepEffect>> {
     let client = reqwest::Client::builder().user_agent(USER_AGENT).build()?;
+    if create_superuser {
+        ensure_superuser(&client, &manifest.global).await?;
+    }
+    let staff = manifest.staff_users();
     let tree = chrisomatic_core::plan(manifest);
     let pb = ProgressBar::new(tree.count() as u64);
     pb.set_style(progress_style());
     let effects = fully_exec_tree(client, tree, |counts| pb.set_message(short_msg(counts))).await;
     pb.finish_and_clear();
     print_final_message(&effects);
+    for warning in warnings_of(&effects, &staff) {
+        println!("{} {warning}", "warning:".yellow().bold());
+    }
     Ok(effects)
 }
 
@@ -28,10 +38,11 @@ fn progress_style() -> ProgressStyle {
 fn short_msg(counts: Counts) -> String {
     let num_bad = counts.unfulfilled + counts.error;
     format!(
-        "{}/{}/{}/{}",
+        "{}/{}/{}/{}/{}",
         counts.unmodified.green(),
         counts.created.cyan(),
         counts.modified.yellow(),
+        counts.removed.magenta(),
         colorize_bad(num_bad)
     )
 }
@@ -39,10 +50,11 @@ fn short_msg(counts: Counts) -> String {
 fn print_final_message<T>(effects: &HashMap<T, StepEffect>) {
     let counts = Counts::from_iter(effects.values());
     println!(
-        "{} Okay  {} Created  {}  Modified {} Unfulfilled  {} Errors",
+        "{} Okay  {} Created  {}  Modified {} Removed  {} Unfulfilled  {} Errors",
         counts.unmodified.green().bold(),
         counts.created.cyan().bold(),
         counts.modified.yellow().bold(),
+        counts.removed.magenta().bold(),
         colorize_bad(counts.unfulfilled),
         colorize_bad(counts.error)
     )
@@ -2,8 +2,10 @@ mod canonicalize;
 mod container_engine;
 mod default_files;
 mod exec;
+mod pipeline_file;
 mod read_inputs;
 mod sample;
+mod superuser;
 
 use std::path::PathBuf;
 
@@ -20,6 +22,14 @@ struct Cli {
     /// Print sample chrisomatic.toml file
     #[clap(short, long)]
     sample: bool,
+    /// Wait for plugin instances and workflows to finish
+    /// (same as `global.wait = true`)
+    #[clap(short, long)]
+    wait: bool,
+    /// Create the admin user in the locally running CUBE container
+    /// if authenticating as `global.admin` fails
+    #[clap(long)]
+    create_superuser: bool,
     /// Files to apply. If unspecified, either ./chrisomatic.toml
     /// or ./chrisomatic.d/*.toml will be read.
     files: Vec<PathBuf>,
@@ -41,8 +51,11 @@ async fn main() -> color_eyre::Result<()> {
     };
 
     let given = read_inputs(&files).await?;
-    let manifest = canonicalize(given)?;
-    let effects = exec_with_progress(manifest).await?;
+    let mut manifest = canonicalize(given)?;
+    if args.wait {
+        manifest.global.wait = true;
+    }
+    let effects = exec_with_progress(manifest, args.create_superuser).await?;
 
     if effects
         .into_values()
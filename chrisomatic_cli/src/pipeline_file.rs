@@ -0,0 +1,177 @@
+//! Pipeline files in the YAML format of ChRIS pipelines.
+//!
+//! Ref: <https://github.com/FNNDSC/CHRIS_docs/blob/master/specs/ChRIS_pipeline_yaml.md>
+
+use std::collections::HashMap;
+
+use chrisomatic_spec::{ArgValue, ParameterDefault, PipelineDefinition, PipelinePiece};
+use color_eyre::eyre::bail;
+use indexmap::IndexMap;
+use serde::Deserialize;
+
+/// A pipeline as it is written in a YAML file.
+#[derive(Deserialize)]
+struct PipelineYaml {
+    name: String,
+    #[serde(default)]
+    authors: String,
+    #[serde(default)]
+    category: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    locked: Option<bool>,
+    plugin_tree: Vec<PieceYaml>,
+}
+
+/// A plugin of a pipeline as it is written in a YAML file.
+#[derive(Deserialize)]
+struct PieceYaml {
+    title: String,
+    /// Plugin name and version separated by a space, e.g. `pl-dircopy v2.1.2`.
+    plugin: String,
+    /// Title of the parent piece.
+    #[serde(default)]
+    previous: Option<String>,
+    #[serde(default)]
+    plugin_parameter_defaults: IndexMap<String, ArgValue>,
+}
+
+/// Parse a pipeline from the YAML format of ChRIS pipelines.
+pub(crate) fn parse_pipeline_yaml(data: &[u8]) -> color_eyre::Result<PipelineDefinition> {
+    let yaml: PipelineYaml = serde_norway::from_slice(data)?;
+    let mut indexes = HashMap::with_capacity(yaml.plugin_tree.len());
+    for (i, piece) in yaml.plugin_tree.iter().enumerate() {
+        if indexes.insert(piece.title.as_str(), i as u32).is_some() {
+            bail!(
+                "Title \"{}\" appears more than once in pipeline \"{}\"",
+                piece.title,
+                yaml.name
+            );
+        }
+    }
+    let plugin_tree = yaml
+        .plugin_tree
+        .iter()
+        .map(|piece| {
+            let Some((plugin_name, plugin_version)) = piece.plugin.split_once(' ') else {
+                bail!(
+                    "plugin of \"{}\" in pipeline \"{}\" must be a name and version \
+                    separated by a space, e.g. \"pl-dircopy v2.1.2\", not \"{}\"",
+                    piece.title,
+                    yaml.name,
+                    piece.plugin
+                );
+            };
+            let previous_index = if let Some(previous) = &piece.previous {
+                let Some(index) = indexes.get(previous.as_str()) else {
+                    bail!(
+                        "previous of \"{}\" in pipeline \"{}\" is not a title in the pipeline: \"{}\"",
+                        piece.title,
+                        yaml.name,
+                        previous
+                    );
+                };
+                Some(*index)
+            } else {
+                None
+            };
+            let plugin_parameter_defaults = piece
+                .plugin_parameter_defaults
+                .iter()
+                .map(|(name, default)| ParameterDefault {
+                    name: name.clone(),
+                    default: default.clone(),
+                })
+                .collect();
+            Ok(PipelinePiece {
+                title: piece.title.clone(),
+                plugin_name: plugin_name.trim().to_string(),
+                plugin_version: plugin_version.trim().trim_start_matches('v').to_string(),
+                previous_index,
+                plugin_parameter_defaults,
+            })
+        })
+        .collect::<color_eyre::Result<_>>()?;
+    Ok(PipelineDefinition {
+        name: yaml.name,
+        authors: yaml.authors,
+        category: yaml.category,
+        description: yaml.description,
+        locked: yaml.locked,
+        plugin_tree,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIPELINE_YAML: &str = r#"
+name: "Example pipeline v1.0.0"
+authors: "Alice <alice@example.org>"
+category: Example
+description: "Copy files, then convert DICOM to NIfTI"
+locked: false
+plugin_tree:
+  - title: copy
+    plugin: pl-dircopy v2.1.2
+    previous: ~
+  - title: convert
+    plugin: pl-dcm2niix v1.0.0
+    previous: copy
+    plugin_parameter_defaults:
+      z: "y"
+      b: true
+      f: 3
+"#;
+
+    #[test]
+    fn test_parse_pipeline_yaml() {
+        let actual = parse_pipeline_yaml(PIPELINE_YAML.as_bytes()).unwrap();
+        let expected = PipelineDefinition {
+            name: "Example pipeline v1.0.0".to_string(),
+            authors: "Alice <alice@example.org>".to_string(),
+            category: "Example".to_string(),
+            description: "Copy files, then convert DICOM to NIfTI".to_string(),
+            locked: Some(false),
+            plugin_tree: vec![
+                PipelinePiece {
+                    title: "copy".to_string(),
+                    plugin_name: "pl-dircopy".to_string(),
+                    plugin_version: "2.1.2".to_string(),
+                    previous_index: None,
+                    plugin_parameter_defaults: vec![],
+                },
+                PipelinePiece {
+                    title: "convert".to_string(),
+                    plugin_name: "pl-dcm2niix".to_string(),
+                    plugin_version: "1.0.0".to_string(),
+                    previous_index: Some(0),
+                    plugin_parameter_defaults: vec![
+                        ParameterDefault {
+                            name: "z".to_string(),
+                            default: ArgValue::Stringish("y".to_string()),
+                        },
+                        ParameterDefault {
+                            name: "b".to_string(),
+                            default: ArgValue::Boolean(true),
+                        },
+                        ParameterDefault {
+                            name: "f".to_string(),
+                            default: ArgValue::Integer(3),
+                        },
+                    ],
+                },
+            ],
+        };
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_pipeline_unknown_previous() {
+        let data = PIPELINE_YAML.replace("previous: copy", "previous: nope");
+        let error = parse_pipeline_yaml(data.as_bytes()).unwrap_err();
+        assert!(error.to_string().contains("\"nope\""));
+    }
+}
@@ -3,9 +3,14 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use chrisomatic_spec::GivenManifest;
+use chrisomatic_spec::{
+    DicomFile, GivenManifest, PipelineDefinition, PipelineSource, PluginDescriptor, UserFileContent,
+};
+use color_eyre::eyre::{OptionExt, bail};
 use futures::{StreamExt, TryStreamExt};
 
+use crate::{container_engine::ContainerEngine, pipeline_file::parse_pipeline_yaml};
+
 pub(crate) async fn read_inputs(files: &[PathBuf]) -> color_eyre::Result<GivenManifest> {
     if let Some(name) = files.first().and_then(|p| p.to_str())
         && files.len() == 1
@@ -29,13 +34,252 @@ async fn read_files(files: &[PathBuf]) -> color_eyre::Result<GivenManifest> {
 
 async fn read_file(path: &Path) -> color_eyre::Result<GivenManifest> {
     let data = fs_err::tokio::read(path).await?;
-    let manifest = toml::from_slice(&data)?;
+    let mut manifest = toml::from_slice(&data)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    resolve_sources(&mut manifest, dir)?;
+    load_pipelines(&mut manifest, dir)?;
+    load_plugin_descriptors(&mut manifest, dir)?;
     Ok(manifest)
 }
 
 fn read_stdin() -> color_eyre::Result<GivenManifest> {
     let mut buf = Vec::new();
     std::io::stdin().read_to_end(&mut buf)?;
-    let manifest = toml::from_slice(&buf)?;
+    let mut manifest = toml::from_slice(&buf)?;
+    resolve_sources(&mut manifest, Path::new(""))?;
+    load_pipelines(&mut manifest, Path::new(""))?;
+    load_plugin_descriptors(&mut manifest, Path::new(""))?;
     Ok(manifest)
 }
+
+/// Resolve the `source` and `directory` paths of `userfiles` and `pacs_files`
+/// relative to `dir`, which is the directory of the manifest file, and check
+/// that they are readable files or directories respectively.
+///
+/// The files of directories are listed, and the headers of DICOM files of
+/// `pacs_files` are read, so that planning does not need the filesystem.
+fn resolve_sources(manifest: &mut GivenManifest, dir: &Path) -> color_eyre::Result<()> {
+    for userfile in &mut manifest.userfiles {
+        let (local, is_dir) = match &mut userfile.content {
+            UserFileContent::Text { .. } => continue,
+            UserFileContent::Source { source } => (source, false),
+            UserFileContent::Directory { directory, .. } => (directory, true),
+        };
+        if local.is_relative() {
+            *local = dir.join(&*local);
+        }
+        let metadata = fs_err::metadata(&*local)?;
+        if is_dir && !metadata.is_dir() {
+            color_eyre::eyre::bail!(
+                "`directory` of user files \"{}\" is not a directory: {}",
+                userfile.path,
+                local.display()
+            );
+        } else if !is_dir && !metadata.is_file() {
+            color_eyre::eyre::bail!(
+                "`source` of user file \"{}\" is not a file: {}",
+                userfile.path,
+                local.display()
+            );
+        }
+        if let UserFileContent::Directory {
+            directory,
+            include,
+            exclude,
+            files,
+            ..
+        } = &mut userfile.content
+        {
+            *files = Some(chrisomatic_core::list_files(directory, include, exclude)?);
+        }
+    }
+    for pacs_files in &mut manifest.pacs_files {
+        let local = &mut pacs_files.directory;
+        if local.is_relative() {
+            *local = dir.join(&*local);
+        }
+        if !fs_err::metadata(&*local)?.is_dir() {
+            color_eyre::eyre::bail!(
+                "`directory` of PACS files of \"{}\" is not a directory: {}",
+                pacs_files.pacs_identifier,
+                local.display()
+            );
+        }
+        let files = chrisomatic_core::list_files(local, &[], &[])?
+            .into_iter()
+            .map(|relative| {
+                let header = chrisomatic_core::read_header(&local.join(&relative))?;
+                Ok(DicomFile { relative, header })
+            })
+            .collect::<std::io::Result<_>>()?;
+        pacs_files.files = Some(files);
+    }
+    Ok(())
+}
+
+/// Load pipelines which are defined in files, resolving their paths relative
+/// to `dir`, which is the directory of the manifest file.
+///
+/// YAML files are in the format of ChRIS pipelines, whereas JSON files are
+/// in the format of the CUBE API.
+fn load_pipelines(manifest: &mut GivenManifest, dir: &Path) -> color_eyre::Result<()> {
+    for pipeline in &mut manifest.pipeline {
+        if let PipelineSource::File { file } = &pipeline.source {
+            let path = dir.join(file);
+            let data = fs_err::read(&path)?;
+            let definition: PipelineDefinition =
+                if path.extension().is_some_and(|ext| ext == "json") {
+                    serde_json::from_slice(&data)?
+                } else {
+                    parse_pipeline_yaml(&data)?
+                };
+            pipeline.source = PipelineSource::Inline(definition);
+        }
+    }
+    Ok(())
+}
+
+/// Load plugin descriptors which are given as files, resolving their paths
+/// relative to `dir`, or by running `chris_plugin_info` in plugin images.
+///
+/// The plugin name, version and image are filled into the descriptor if
+/// missing. It is an error if the version of the descriptor is different
+/// from the version of the plugin.
+fn load_plugin_descriptors(manifest: &mut GivenManifest, dir: &Path) -> color_eyre::Result<()> {
+    for entry in &mut manifest.plugins {
+        let (data, image) = match &entry.descriptor {
+            None => continue,
+            Some(PluginDescriptor::Json { json }) => (json.clone(), None),
+            Some(PluginDescriptor::File(file)) => (fs_err::read_to_string(dir.join(file))?, None),
+            Some(PluginDescriptor::Image { image }) => {
+                let engine = ContainerEngine::installed()
+                    .next()
+                    .ok_or_eyre("Neither podman nor docker is installed")?;
+                (engine.run(image, &["chris_plugin_info"])?, Some(image))
+            }
+        };
+        let mut descriptor: serde_json::Value = serde_json::from_str(&data)?;
+        let Some(fields) = descriptor.as_object_mut() else {
+            bail!(
+                "Descriptor of plugin \"{}\" is not a JSON object",
+                entry.plugin
+            );
+        };
+        fields
+            .entry("name")
+            .or_insert_with(|| entry.plugin.name().into());
+        if let Some(version) = entry.plugin.version() {
+            let descriptor_version = fields.entry("version").or_insert_with(|| version.into());
+            if descriptor_version.as_str() != Some(version) {
+                bail!(
+                    "Descriptor of plugin \"{}\" has a different version: {}",
+                    entry.plugin,
+                    descriptor_version
+                );
+            }
+        }
+        if let Some(image) = image {
+            fields
+                .entry("dock_image")
+                .or_insert_with(|| image.as_str().into());
+        }
+        let json = serde_json::to_string(&descriptor)?;
+        entry.descriptor = Some(PluginDescriptor::Json { json });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrisomatic_spec::PluginEntry;
+    use rstest::*;
+    use tempfile::TempDir;
+
+    const PIPELINE_YAML: &str = r#"
+name: "Example pipeline v1.0.0"
+plugin_tree:
+  - title: copy
+    plugin: pl-dircopy v2.1.2
+  - title: convert
+    plugin: pl-dcm2niix v1.0.0
+    previous: copy
+"#;
+
+    #[test]
+    fn test_load_plugin_descriptors() {
+        let temp = TempDir::new().unwrap();
+        std::fs::write(
+            temp.path().join("pl-local.json"),
+            r#"{"type": "ds", "selfexec": "local"}"#,
+        )
+        .unwrap();
+        let mut manifest: GivenManifest = toml::from_str(
+            r#"
+            [[plugins]]
+            plugin = "pl-local@1.2.3"
+            descriptor = "pl-local.json"
+            "#,
+        )
+        .unwrap();
+        load_plugin_descriptors(&mut manifest, temp.path()).unwrap();
+        let Some(PluginDescriptor::Json { json }) = &manifest.plugins[0].descriptor else {
+            panic!(
+                "descriptor was not loaded: {:?}",
+                manifest.plugins[0].descriptor
+            )
+        };
+        let actual: serde_json::Value = serde_json::from_str(json).unwrap();
+        let expected = serde_json::json!({
+            "name": "pl-local",
+            "version": "1.2.3",
+            "type": "ds",
+            "selfexec": "local",
+        });
+        assert_eq!(actual, expected);
+    }
+
+    #[rstest]
+    #[case("pl-local@1.2.3", r#"{"version": "1.2.3"}"#, true)]
+    #[case("pl-local", r#"{"version": "1.2.3"}"#, true)]
+    #[case("pl-local@1.2.3", r#"{"version": "4.5.6"}"#, false)]
+    fn test_plugin_descriptor_version(
+        #[case] plugin: &str,
+        #[case] json: &str,
+        #[case] expected: bool,
+    ) {
+        let mut manifest = GivenManifest {
+            plugins: vec![PluginEntry {
+                plugin: plugin.parse().unwrap(),
+                compute_resources: vec![],
+                descriptor: Some(PluginDescriptor::Json {
+                    json: json.to_string(),
+                }),
+            }],
+            ..Default::default()
+        };
+        let actual = load_plugin_descriptors(&mut manifest, Path::new(""));
+        assert_eq!(actual.is_ok(), expected, "{actual:?}");
+    }
+
+    #[test]
+    fn test_load_pipelines() {
+        let temp = TempDir::new().unwrap();
+        std::fs::create_dir(temp.path().join("pipelines")).unwrap();
+        std::fs::write(temp.path().join("pipelines/example.yml"), PIPELINE_YAML).unwrap();
+        let mut manifest: GivenManifest = toml::from_str(
+            r#"
+            [[pipeline]]
+            file = "pipelines/example.yml"
+            "#,
+        )
+        .unwrap();
+        load_pipelines(&mut manifest, temp.path()).unwrap();
+        let PipelineSource::Inline(definition) = &manifest.pipeline[0].source else {
+            panic!("pipeline was not loaded: {:?}", manifest.pipeline[0].source)
+        };
+        assert_eq!(definition.name, "Example pipeline v1.0.0");
+        assert_eq!(definition.plugin_tree.len(), 2);
+        assert_eq!(definition.plugin_tree[1].previous_index, Some(0));
+    }
+}
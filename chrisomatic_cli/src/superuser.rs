@@ -0,0 +1,148 @@
+//! Creating the admin user in a locally running CUBE container.
+
+use std::net::IpAddr;
+
+use chrisomatic_spec::{CubeUrl, Global, PasswordOrToken};
+use color_eyre::eyre::{Context, bail};
+use reqwest::{StatusCode, Url, header};
+
+use crate::canonicalize::find_cube_container;
+
+/// Name of the environment variable read by `manage.py createsuperuser --noinput`.
+const DJANGO_SUPERUSER_PASSWORD: &str = "DJANGO_SUPERUSER_PASSWORD";
+
+/// If authenticating as `global.admin` fails, create the admin user by
+/// running `manage.py createsuperuser` inside the local CUBE container.
+///
+/// Nothing is done if the admin user's credentials are a token, since the
+/// password is unknown. An error is returned if the container does not
+/// publish `global.cube`, or if authenticating still fails afterwards.
+///
+/// WARNING: does blocking I/O.
+pub(crate) async fn ensure_superuser(
+    client: &reqwest::Client,
+    global: &Global,
+) -> color_eyre::Result<()> {
+    let PasswordOrToken::Password(password) = &global.admin.secret else {
+        return Ok(());
+    };
+    let username = global.admin.username.as_str();
+    if can_authenticate(client, global, password).await? {
+        return Ok(());
+    }
+    let Some(container) = find_cube_container()? else {
+        bail!(
+            "Cannot authenticate as admin user \"{username}\", \
+            and no CUBE container is running locally to create it in."
+        );
+    };
+    if !container
+        .global
+        .cube
+        .as_ref()
+        .is_some_and(|published| is_served_by(&global.cube, published))
+    {
+        bail!(
+            "Cannot authenticate as admin user \"{username}\", and the CUBE \
+            container {} running locally does not serve {}",
+            container.id,
+            global.cube.as_str()
+        );
+    }
+    let email = format!("{username}@{}", global.email_domain);
+    let command = [
+        "python",
+        "manage.py",
+        "createsuperuser",
+        "--noinput",
+        "--username",
+        username,
+        "--email",
+        &email,
+    ];
+    container
+        .engine
+        .exec(
+            &container.id,
+            &[(DJANGO_SUPERUSER_PASSWORD, password)],
+            &command,
+        )
+        .wrap_err_with(|| {
+            format!(
+                "Could not create admin user \"{username}\" in container {}",
+                container.id
+            )
+        })?;
+    if !can_authenticate(client, global, password).await? {
+        bail!(
+            "Created admin user \"{username}\" in container {}, \
+            but still cannot authenticate at {}",
+            container.id,
+            global.cube.as_str()
+        );
+    }
+    Ok(())
+}
+
+/// Whether `cube` is the URL `published` by a local container, i.e. it is
+/// a loopback address with the same port.
+fn is_served_by(cube: &CubeUrl, published: &CubeUrl) -> bool {
+    let (cube, published) = (cube.to_url(), published.to_url());
+    is_loopback(&cube) && cube.port_or_known_default() == published.port_or_known_default()
+}
+
+fn is_loopback(url: &Url) -> bool {
+    match url.host_str() {
+        Some("localhost") => true,
+        Some(host) => host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback()),
+        None => false,
+    }
+}
+
+/// Check whether the admin user can authenticate using its password.
+async fn can_authenticate(
+    client: &reqwest::Client,
+    global: &Global,
+    password: &str,
+) -> color_eyre::Result<bool> {
+    let url = global.cube.to_url().join("auth-token/").unwrap();
+    let body = serde_json::json!({
+        "username": global.admin.username.as_str(),
+        "password": password,
+    });
+    let res = client
+        .post(url)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(&body)?)
+        .send()
+        .await?;
+    match res.status() {
+        status if status.is_success() => Ok(true),
+        StatusCode::BAD_REQUEST => Ok(false),
+        status => bail!("HTTP status code {status} from POST {}", res.url()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[rstest]
+    #[case("http://localhost:8000/api/v1/", true)]
+    #[case("http://127.0.0.1:8000/api/v1/", true)]
+    #[case("http://[::1]:8000/api/v1/", true)]
+    #[case("http://localhost:8001/api/v1/", false)]
+    #[case("http://localhost/api/v1/", false)]
+    #[case("http://cube.example.org:8000/api/v1/", false)]
+    #[case("http://192.168.1.2:8000/api/v1/", false)]
+    fn test_is_served_by(#[case] cube: &str, #[case] expected: bool) {
+        let published = CubeUrl::try_new("http://localhost:8000/api/v1/").unwrap();
+        let cube = CubeUrl::try_new(cube).unwrap();
+        assert_eq!(is_served_by(&cube, &published), expected);
+    }
+}
@@ -0,0 +1,49 @@
+//! A time limit shared by the steps which wait for something.
+
+use std::cell::Cell;
+use std::time::Duration;
+
+use web_time::Instant;
+
+/// A wall-clock deadline shared by all the steps which wait for plugin
+/// instances and workflows to finish. It starts when the first of them
+/// starts waiting.
+#[derive(Debug)]
+pub(crate) struct Deadline {
+    timeout: Duration,
+    start: Cell<Option<Instant>>,
+}
+
+impl Deadline {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            start: Cell::new(None),
+        }
+    }
+
+    /// Time left until the deadline, starting it if it has not started yet.
+    pub(crate) fn remaining(&self) -> Duration {
+        let start = self.start.get().unwrap_or_else(|| {
+            let now = Instant::now();
+            self.start.set(Some(now));
+            now
+        });
+        self.timeout.saturating_sub(start.elapsed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline_is_shared() {
+        let deadline = Deadline::new(Duration::from_secs(60));
+        let first = deadline.remaining();
+        std::thread::sleep(Duration::from_millis(20));
+        let second = deadline.remaining();
+        assert!(second < first);
+        assert!(Deadline::new(Duration::ZERO).remaining().is_zero());
+    }
+}
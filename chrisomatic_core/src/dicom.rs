@@ -0,0 +1,372 @@
+//! Reading of DICOM file headers, just enough to know which series a file is of.
+//!
+//! Only DICOM Part 10 files (with the "DICM" preamble) encoded with
+//! little-endian transfer syntaxes are supported.
+
+use std::io::Read;
+use std::ops::Range;
+use std::path::Path;
+
+use chrisomatic_spec::DicomHeader;
+
+type Tag = (u16, u16);
+
+const TRANSFER_SYNTAX_UID: Tag = (0x0002, 0x0010);
+const STUDY_DATE: Tag = (0x0008, 0x0020);
+const MODALITY: Tag = (0x0008, 0x0060);
+const STUDY_DESCRIPTION: Tag = (0x0008, 0x1030);
+const SERIES_DESCRIPTION: Tag = (0x0008, 0x103E);
+const PATIENT_NAME: Tag = (0x0010, 0x0010);
+const PATIENT_ID: Tag = (0x0010, 0x0020);
+const STUDY_INSTANCE_UID: Tag = (0x0020, 0x000D);
+const SERIES_INSTANCE_UID: Tag = (0x0020, 0x000E);
+const ITEM_DELIMITATION: Tag = (0xFFFE, 0xE00D);
+const SEQUENCE_DELIMITATION: Tag = (0xFFFE, 0xE0DD);
+
+/// Number of bytes read from the start of a file. The elements of
+/// [DicomHeader] come before the (large) pixel data, so they are expected to
+/// be within this prefix.
+const MAX_HEADER_SIZE: u64 = 1 << 20;
+
+const META_GROUP: u16 = 0x0002;
+const ITEM_GROUP: u16 = 0xFFFE;
+const UNDEFINED_LENGTH: u32 = 0xFFFFFFFF;
+
+const IMPLICIT_VR_LITTLE_ENDIAN: &str = "1.2.840.10008.1.2";
+const EXPLICIT_VR_BIG_ENDIAN: &str = "1.2.840.10008.1.2.2";
+const DEFLATED_EXPLICIT_VR_LITTLE_ENDIAN: &str = "1.2.840.10008.1.2.1.99";
+
+/// Value representations which are encoded with a 4-byte length in explicit VR.
+const LONG_VRS: [&[u8; 2]; 13] = [
+    b"OB", b"OD", b"OF", b"OL", b"OV", b"OW", b"SQ", b"SV", b"UC", b"UN", b"UR", b"UT", b"UV",
+];
+
+/// Read the header of a DICOM file from its first [MAX_HEADER_SIZE] bytes.
+/// An error of kind [std::io::ErrorKind::InvalidData] is returned if the file
+/// is not a (supported) DICOM file, or if it does not identify its series.
+pub fn read_header(path: &Path) -> std::io::Result<DicomHeader> {
+    let mut data = Vec::new();
+    std::fs::File::open(path)
+        .and_then(|file| file.take(MAX_HEADER_SIZE).read_to_end(&mut data))
+        .map_err(|e| {
+            std::io::Error::new(
+                e.kind(),
+                format!("Cannot read file \"{}\": {e}", path.display()),
+            )
+        })?;
+    parse_header(&data).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "File \"{}\" is not a supported DICOM file which identifies its series",
+                path.display()
+            ),
+        )
+    })
+}
+
+fn parse_header(data: &[u8]) -> Option<DicomHeader> {
+    if data.get(128..132)? != b"DICM" {
+        return None;
+    }
+    let mut reader = Reader {
+        data,
+        pos: 132,
+        implicit: false,
+    };
+    let mut values = Values::default();
+    let mut is_meta = true;
+    while reader.pos < data.len() {
+        if is_meta && reader.peek_group()? != META_GROUP {
+            // the file meta information is always explicit VR little endian,
+            // the rest of the file is encoded with its transfer syntax.
+            is_meta = false;
+            match values.transfer_syntax.as_deref() {
+                Some(IMPLICIT_VR_LITTLE_ENDIAN) => reader.implicit = true,
+                Some(EXPLICIT_VR_BIG_ENDIAN | DEFLATED_EXPLICIT_VR_LITTLE_ENDIAN) => return None,
+                _ => {}
+            }
+        }
+        if reader.peek_tag()? > SERIES_INSTANCE_UID {
+            // elements are sorted by tag, so the rest of the file is not needed
+            break;
+        }
+        let element = reader.element()?;
+        match element.value {
+            Some(range) => values.set(element.tag, &data[range]),
+            None => reader.skip_undefined()?,
+        }
+    }
+    values.into_header()
+}
+
+/// The values of [DicomHeader] which were read.
+#[derive(Default)]
+struct Values {
+    transfer_syntax: Option<String>,
+    patient_id: Option<String>,
+    patient_name: Option<String>,
+    study_date: Option<String>,
+    study_instance_uid: Option<String>,
+    study_description: Option<String>,
+    series_instance_uid: Option<String>,
+    series_description: Option<String>,
+    modality: Option<String>,
+}
+
+impl Values {
+    fn set(&mut self, tag: Tag, value: &[u8]) {
+        let field = match tag {
+            TRANSFER_SYNTAX_UID => &mut self.transfer_syntax,
+            PATIENT_ID => &mut self.patient_id,
+            PATIENT_NAME => &mut self.patient_name,
+            STUDY_DATE => &mut self.study_date,
+            STUDY_INSTANCE_UID => &mut self.study_instance_uid,
+            STUDY_DESCRIPTION => &mut self.study_description,
+            SERIES_INSTANCE_UID => &mut self.series_instance_uid,
+            SERIES_DESCRIPTION => &mut self.series_description,
+            MODALITY => &mut self.modality,
+            _ => return,
+        };
+        // values are padded to an even length with spaces, or NUL for UIDs
+        let text = String::from_utf8_lossy(value);
+        let text = text.trim_matches([' ', '\0']);
+        *field = Some(text.to_string()).filter(|text| !text.is_empty());
+    }
+
+    fn into_header(self) -> Option<DicomHeader> {
+        Some(DicomHeader {
+            patient_id: self.patient_id?,
+            patient_name: self.patient_name,
+            study_date: self.study_date,
+            study_instance_uid: self.study_instance_uid?,
+            study_description: self.study_description,
+            series_instance_uid: self.series_instance_uid?,
+            series_description: self.series_description,
+            modality: self.modality,
+        })
+    }
+}
+
+/// The tag of a data element and the range of its value, or [None] if its
+/// length is undefined.
+struct Element {
+    tag: Tag,
+    value: Option<Range<usize>>,
+}
+
+/// Reader of data elements. Every method returns [None] if the data is truncated.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    implicit: bool,
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let bytes = self.data.get(self.pos..self.pos + N)?;
+        self.pos += N;
+        bytes.try_into().ok()
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn peek_group(&self) -> Option<u16> {
+        let bytes = self.data.get(self.pos..self.pos + 2)?;
+        Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn peek_tag(&self) -> Option<Tag> {
+        let bytes = self.data.get(self.pos..self.pos + 4)?;
+        Some((
+            u16::from_le_bytes([bytes[0], bytes[1]]),
+            u16::from_le_bytes([bytes[2], bytes[3]]),
+        ))
+    }
+
+    /// Read the next data element, moving past its value if its length is defined.
+    fn element(&mut self) -> Option<Element> {
+        let tag = (self.u16()?, self.u16()?);
+        // items and delimiters never have a VR
+        let length = if self.implicit || tag.0 == ITEM_GROUP {
+            self.u32()?
+        } else {
+            let vr: [u8; 2] = self.take()?;
+            if LONG_VRS.contains(&&vr) {
+                self.pos += 2; // reserved
+                self.u32()?
+            } else {
+                self.u16()? as u32
+            }
+        };
+        if length == UNDEFINED_LENGTH {
+            return Some(Element { tag, value: None });
+        }
+        let start = self.pos;
+        let end = start.checked_add(length as usize)?;
+        if end > self.data.len() {
+            return None;
+        }
+        self.pos = end;
+        Some(Element {
+            tag,
+            value: Some(start..end),
+        })
+    }
+
+    /// Move past the value of an element of undefined length (a sequence or
+    /// an item), up to and including its delimitation item.
+    fn skip_undefined(&mut self) -> Option<()> {
+        loop {
+            let element = self.element()?;
+            if element.tag == ITEM_DELIMITATION || element.tag == SEQUENCE_DELIMITATION {
+                return Some(());
+            }
+            if element.value.is_none() {
+                self.skip_undefined()?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    /// Encode an element in explicit VR little endian.
+    fn explicit(tag: Tag, vr: &[u8; 2], value: &[u8]) -> Vec<u8> {
+        let mut bytes = [tag.0.to_le_bytes(), tag.1.to_le_bytes()].concat();
+        bytes.extend_from_slice(vr);
+        if LONG_VRS.contains(&vr) {
+            bytes.extend_from_slice(&[0, 0]);
+            bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        } else {
+            bytes.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        }
+        bytes.extend_from_slice(value);
+        bytes
+    }
+
+    /// Encode an element in implicit VR little endian.
+    fn implicit(tag: Tag, value: &[u8]) -> Vec<u8> {
+        let mut bytes = [tag.0.to_le_bytes(), tag.1.to_le_bytes()].concat();
+        bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(value);
+        bytes
+    }
+
+    fn file(transfer_syntax: &str, dataset: Vec<Vec<u8>>) -> Vec<u8> {
+        let mut bytes = vec![0; 128];
+        bytes.extend_from_slice(b"DICM");
+        let mut uid = transfer_syntax.as_bytes().to_vec();
+        if uid.len() % 2 == 1 {
+            uid.push(0);
+        }
+        bytes.extend(explicit(TRANSFER_SYNTAX_UID, b"UI", &uid));
+        bytes.extend(dataset.concat());
+        bytes
+    }
+
+    /// A sequence of undefined length containing one item of undefined length.
+    fn undefined_sequence(tag: Tag) -> Vec<u8> {
+        let mut bytes = [tag.0.to_le_bytes(), tag.1.to_le_bytes()].concat();
+        bytes.extend_from_slice(b"SQ\0\0");
+        bytes.extend_from_slice(&UNDEFINED_LENGTH.to_le_bytes());
+        bytes.extend_from_slice(&[0xFE, 0xFF, 0x00, 0xE0]);
+        bytes.extend_from_slice(&UNDEFINED_LENGTH.to_le_bytes());
+        bytes.extend(explicit((0x0008, 0x1150), b"UI", b"1.2.3.4\0"));
+        bytes.extend_from_slice(&[0xFE, 0xFF, 0x0D, 0xE0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&[0xFE, 0xFF, 0xDD, 0xE0, 0, 0, 0, 0]);
+        bytes
+    }
+
+    fn expected() -> DicomHeader {
+        DicomHeader {
+            patient_id: "1449c1d".to_string(),
+            patient_name: Some("anonymized".to_string()),
+            study_date: Some("20090701".to_string()),
+            study_instance_uid: "1.2.3".to_string(),
+            study_description: None,
+            series_instance_uid: "1.2.3.1".to_string(),
+            series_description: None,
+            modality: Some("MR".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_parse_explicit() {
+        let data = file(
+            "1.2.840.10008.1.2.1",
+            vec![
+                explicit(STUDY_DATE, b"DA", b"20090701"),
+                explicit(MODALITY, b"CS", b"MR"),
+                undefined_sequence((0x0008, 0x1110)),
+                explicit(PATIENT_NAME, b"PN", b"anonymized"),
+                explicit(PATIENT_ID, b"LO", b"1449c1d "),
+                explicit(STUDY_INSTANCE_UID, b"UI", b"1.2.3\0"),
+                explicit(SERIES_INSTANCE_UID, b"UI", b"1.2.3.1\0"),
+                explicit((0x7FE0, 0x0010), b"OW", &[0; 16]),
+            ],
+        );
+        assert_eq!(parse_header(&data), Some(expected()));
+    }
+
+    #[test]
+    fn test_parse_truncated_after_series() {
+        let mut data = file(
+            "1.2.840.10008.1.2.1",
+            vec![
+                explicit(PATIENT_ID, b"LO", b"1449c1d "),
+                explicit(STUDY_INSTANCE_UID, b"UI", b"1.2.3\0"),
+                explicit(SERIES_INSTANCE_UID, b"UI", b"1.2.3.1\0"),
+                explicit((0x7FE0, 0x0010), b"OW", &[0; 16]),
+            ],
+        );
+        // only a prefix of the file is read, which can end within pixel data
+        data.truncate(data.len() - 8);
+        let header = parse_header(&data).unwrap();
+        assert_eq!(header.series_instance_uid, "1.2.3.1");
+    }
+
+    #[test]
+    fn test_parse_implicit() {
+        let data = file(
+            IMPLICIT_VR_LITTLE_ENDIAN,
+            vec![
+                implicit(STUDY_DATE, b"20090701"),
+                implicit(MODALITY, b"MR"),
+                implicit(PATIENT_NAME, b"anonymized"),
+                implicit(PATIENT_ID, b"1449c1d "),
+                implicit(STUDY_INSTANCE_UID, b"1.2.3\0"),
+                implicit(SERIES_INSTANCE_UID, b"1.2.3.1\0"),
+            ],
+        );
+        assert_eq!(parse_header(&data), Some(expected()));
+    }
+
+    #[rstest]
+    #[case(b"not a DICOM file".to_vec())]
+    #[case(file("1.2.840.10008.1.2.1", vec![explicit(PATIENT_ID, b"LO", b"1449c1d ")]))]
+    #[case(file(EXPLICIT_VR_BIG_ENDIAN, vec![explicit(PATIENT_ID, b"LO", b"1449c1d ")]))]
+    fn test_parse_unsupported(#[case] data: Vec<u8>) {
+        assert_eq!(parse_header(&data), None);
+    }
+
+    #[test]
+    fn test_read_header_not_dicom() {
+        let path =
+            std::env::temp_dir().join(format!("chrisomatic-notes-{}.txt", std::process::id()));
+        std::fs::write(&path, "not a DICOM file").unwrap();
+        let result = read_header(&path);
+        std::fs::remove_file(&path).unwrap();
+        let error = result.expect_err("a file which is not DICOM should be reported");
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
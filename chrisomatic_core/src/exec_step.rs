@@ -1,6 +1,11 @@
-use std::rc::Rc;
+use std::{rc::Rc, time::Duration};
 
-use chrisomatic_step::{Check, Dependency, Entries, StatusCheck, Step};
+use bytes::Bytes;
+use chrisomatic_step::{Check, Dependency, Entries, StatusCheck, Step, StepRequest};
+use reqwest::{Url, header::HeaderMap};
+
+/// How long to wait before searching again for a resource which is not ready.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
 
 /// Execute a [Step].
 ///
@@ -8,7 +13,10 @@ use chrisomatic_step::{Check, Dependency, Entries, StatusCheck, Step};
 ///    (It can also do the resource creation/modification right away if that is possible.)
 /// 2. [Step::deserialize] decides what to do next.
 /// 3. If the resource needs to be created, call [Step::create]. Or, if the resource
-///    needs to be modified, calll [Step::modify].
+///    needs to be modified, calll [Step::modify]. Or, if parts of the resource need
+///    to be removed, send the removal requests. Or, if the resource needs to be
+///    replaced, send the replacement requests. Or, if the resource is not ready,
+///    go back to 1.
 pub(crate) async fn exec_step(client: &reqwest::Client, step: Rc<dyn Step>) -> (Outcome, Entries) {
     let target = step.provides().head;
     match exec_step_impl(client, step).await {
@@ -30,43 +38,131 @@ async fn exec_step_impl(
     client: &reqwest::Client,
     step: Rc<dyn Step>,
 ) -> Result<(StepEffect, Entries), StepError> {
+    loop {
+        let (check, url) = search(client, step.as_ref()).await?;
+        return match check {
+            Check::Pending => {
+                let remaining = step.timeout();
+                if remaining.is_zero() {
+                    return Err(StepError::Timeout(url));
+                }
+                futures_timer::Delay::new(POLL_INTERVAL.min(remaining)).await;
+                continue;
+            }
+            Check::Exists(data) => Ok((StepEffect::Unmodified, data)),
+            Check::Modified(data) => Ok((StepEffect::Modified, data)),
+            Check::DoesNotExist => {
+                if let Some(req) = step.create() {
+                    let data = send(client, req.as_ref()).await?;
+                    Ok((StepEffect::Created, data))
+                } else {
+                    Err(StepError::Uncreatable(url))
+                }
+            }
+            Check::NeedsModification => {
+                if let Some(req) = step.modify() {
+                    let data = send(client, req.as_ref()).await?;
+                    Ok((StepEffect::Modified, data))
+                } else {
+                    Err(StepError::Unmodifiable(url))
+                }
+            }
+            Check::NeedsRemoval {
+                requests,
+                mut entries,
+            } => {
+                for req in requests {
+                    entries.extend(send(client, req.as_ref()).await?);
+                }
+                Ok((StepEffect::Removed, entries))
+            }
+            Check::NeedsReplacement(requests) => {
+                let mut entries = Vec::new();
+                for req in requests {
+                    entries.extend(send(client, req.as_ref()).await?);
+                }
+                Ok((StepEffect::Modified, entries))
+            }
+            Check::Invalid(reason) => Err(StepError::Invalid(reason)),
+            Check::Failed(reason) => Err(StepError::Failed(reason)),
+        };
+    }
+}
+
+/// Call [Step::search] and decide what to do next.
+async fn search(client: &reqwest::Client, step: &dyn Step) -> Result<(Check, Url), StepError> {
     let req = step.search();
     let method = req.method().clone();
-    let res = client.execute(step.search()).await?;
+    let headers = req.headers().clone();
+    let res = client.execute(req).await?;
     let url = res.url().clone();
     let check = match step.check_status(res.status()) {
+        StatusCheck::Exists if step.paginated() => {
+            step.deserialize(all_pages(client, headers, res).await?)?
+        }
         StatusCheck::Exists => step.deserialize(res.bytes().await?)?,
         StatusCheck::DoesNotExist => Check::DoesNotExist,
+        StatusCheck::Invalid(reason) => Check::Invalid(reason),
         StatusCheck::Error => {
             return Err(StepError::Status {
                 status: res.status(),
                 method,
-                url: res.url().clone(),
+                url,
             });
         }
     };
-    match check {
-        Check::Exists(data) => Ok((StepEffect::Unmodified, data)),
-        Check::Modified(data) => Ok((StepEffect::Modified, data)),
-        Check::DoesNotExist => {
-            if let Some(req) = step.create() {
-                let res = client.execute(req.request()).await?.error_for_status()?;
-                let data = req.deserialize(res.bytes().await?)?;
-                Ok((StepEffect::Created, data))
-            } else {
-                Err(StepError::Uncreatable(url))
-            }
-        }
-        Check::NeedsModification => {
-            if let Some(req) = step.modify() {
-                let res = client.execute(req.request()).await?.error_for_status()?;
-                let data = req.deserialize(res.bytes().await?)?;
-                Ok((StepEffect::Modified, data))
-            } else {
-                Err(StepError::Unmodifiable(url))
-            }
+    Ok((check, url))
+}
+
+/// Get the body of the first page of a paginated list, with the `results` of
+/// its next pages appended to its `results`. The next pages are requested with
+/// the same headers as the first page.
+async fn all_pages(
+    client: &reqwest::Client,
+    headers: HeaderMap,
+    first: reqwest::Response,
+) -> Result<Bytes, StepError> {
+    let mut page: serde_json::Value = serde_json::from_slice(&first.bytes().await?)?;
+    let mut next = next_page_of(&page);
+    while let Some(url) = next {
+        let res = client
+            .get(url)
+            .headers(headers.clone())
+            .send()
+            .await?
+            .error_for_status()?;
+        let mut next_page: serde_json::Value = serde_json::from_slice(&res.bytes().await?)?;
+        next = next_page_of(&next_page);
+        if let (Some(results), Some(more)) = (
+            page.get_mut("results").and_then(|v| v.as_array_mut()),
+            next_page.get_mut("results").and_then(|v| v.as_array_mut()),
+        ) {
+            results.append(more);
         }
     }
+    Ok(serde_json::to_vec(&page)?.into())
+}
+
+/// Get the URL of the next page of a paginated list.
+fn next_page_of(page: &serde_json::Value) -> Option<String> {
+    page.get("next")
+        .and_then(|next| next.as_str())
+        .map(|next| next.to_string())
+}
+
+/// Send the request of a [StepRequest] and deserialize its response.
+async fn send(client: &reqwest::Client, req: &dyn StepRequest) -> Result<Entries, StepError> {
+    let res = client.execute(req.request()).await?;
+    let status = res.status();
+    if status.is_client_error() || status.is_server_error() {
+        let error = res.error_for_status_ref().unwrap_err();
+        let body = res.bytes().await?;
+        return match req.invalid_status(status, &body) {
+            Some(reason) => Err(StepError::Invalid(reason)),
+            None => Err(error.into()),
+        };
+    }
+    Ok(req.deserialize(res.bytes().await?)?)
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -75,6 +171,12 @@ pub enum StepError {
     Uncreatable(reqwest::Url),
     #[error("Resource cannot be modified: {0}")]
     Unmodifiable(reqwest::Url),
+    #[error("Invalid specification: {0}")]
+    Invalid(String),
+    #[error("{0}")]
+    Failed(String),
+    #[error("Timed out waiting for resource to be ready: {0}")]
+    Timeout(reqwest::Url),
     #[error(transparent)]
     Request(#[from] reqwest::Error),
     #[error("HTTP status code {status} from {method} {url}")]
@@ -96,6 +198,8 @@ pub enum StepEffect {
     Unmodified,
     /// A resource was modified.
     Modified,
+    /// Parts of a resource which were not specified were removed.
+    Removed,
     /// The step was not performed because of an unfulfilled dependency.
     Unfulfilled(Dependency),
     /// The step produced an error.
@@ -116,7 +220,10 @@ impl Outcome {
     pub fn ok(&self) -> bool {
         matches!(
             &self.effect,
-            StepEffect::Created | StepEffect::Unmodified | StepEffect::Modified
+            StepEffect::Created
+                | StepEffect::Unmodified
+                | StepEffect::Modified
+                | StepEffect::Removed
         )
     }
 }
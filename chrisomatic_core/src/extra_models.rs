@@ -1,5 +1,7 @@
 //! CUBE API models which were not produced by OpenAPI-Generator.
 
+use std::collections::HashMap;
+
 use chris_oag::models;
 
 #[derive(serde::Deserialize)]
@@ -11,9 +13,377 @@ pub(crate) struct CollectionLinks {
     pub user: String,
 }
 
+/// The staff status of a user.
+#[derive(serde::Deserialize)]
+pub(crate) struct UserIsStaff {
+    pub is_staff: bool,
+}
+
+/// A page of the members of a group.
+#[derive(serde::Deserialize)]
+pub(crate) struct GroupUserList {
+    pub results: Vec<GroupUserBrief>,
+}
+
+/// A member of a group.
+#[derive(serde::Deserialize)]
+pub(crate) struct GroupUserBrief {
+    pub user_id: u32,
+    pub user_username: String,
+}
+
 #[derive(serde::Deserialize)]
 pub(crate) struct RootResponse {
     #[serde(flatten)]
     pub list: models::PaginatedFeedList,
     pub collection_links: CollectionLinks,
 }
+
+/// Request body for registering a plugin through the admin API.
+///
+/// Ref: <https://github.com/FNNDSC/ChRIS_ultron_backEnd/blob/v6.4.0/chris_backend/plugins/admin.py>
+#[derive(serde::Serialize)]
+pub(crate) struct PluginAdminRequest {
+    pub plugin_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plugin_version: Option<String>,
+    pub plugin_store_url: String,
+    pub compute_names: String,
+}
+
+/// Request body for adding plugins to compute resources through the admin API.
+#[derive(serde::Serialize)]
+pub(crate) struct PluginComputeNamesRequest {
+    pub compute_names: String,
+}
+
+/// Request body for creating or modifying a compute resource through the admin API.
+#[derive(serde::Serialize)]
+pub(crate) struct ComputeResourceAdminRequest {
+    pub name: String,
+    pub compute_url: String,
+    pub compute_user: String,
+    pub compute_password: String,
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compute_innetwork: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_job_exec_seconds: Option<u64>,
+}
+
+/// Compute resource as represented by the admin API.
+#[derive(serde::Deserialize)]
+pub(crate) struct ComputeResourceAdmin {
+    pub id: u32,
+    pub compute_url: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub compute_innetwork: Option<bool>,
+    #[serde(default)]
+    pub max_job_exec_seconds: Option<u64>,
+}
+
+/// Request body for creating a pipeline, where `plugin_tree` is a JSON string.
+///
+/// Ref: <https://github.com/FNNDSC/ChRIS_ultron_backEnd/blob/v6.4.0/chris_backend/pipelines/serializers.py>
+#[derive(serde::Serialize)]
+pub(crate) struct PipelineCreateRequest<'a> {
+    pub name: &'a str,
+    pub authors: &'a str,
+    pub category: &'a str,
+    pub description: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locked: Option<bool>,
+    pub plugin_tree: String,
+}
+
+/// Request body for creating a workflow, i.e. running a pipeline.
+#[derive(serde::Serialize)]
+pub(crate) struct WorkflowCreateRequest<'a> {
+    pub previous_plugin_inst_id: u32,
+    pub title: &'a str,
+}
+
+/// The parts of a plugin parameter which are needed to validate plugin arguments.
+///
+/// Ref: <https://github.com/FNNDSC/ChRIS_ultron_backEnd/blob/v6.4.0/chris_backend/plugins/serializers.py>
+#[derive(serde::Deserialize)]
+pub(crate) struct PluginParameterBrief {
+    pub name: String,
+    /// One of: "string", "integer", "float", "boolean", "path", "unextpath"
+    #[serde(rename = "type")]
+    pub param_type: String,
+    pub optional: bool,
+    #[serde(default)]
+    pub default: Option<serde_json::Value>,
+}
+
+/// A page of [PluginParameterBrief].
+#[derive(serde::Deserialize)]
+pub(crate) struct PluginParameterList {
+    pub results: Vec<PluginParameterBrief>,
+}
+
+/// A page of the parameter values of a plugin instance.
+#[derive(serde::Deserialize)]
+pub(crate) struct PluginInstanceParameterList {
+    pub results: Vec<PluginInstanceParameterBrief>,
+}
+
+/// The value of a parameter of a plugin instance.
+#[derive(serde::Deserialize)]
+pub(crate) struct PluginInstanceParameterBrief {
+    pub param_name: String,
+    pub value: serde_json::Value,
+}
+
+/// The part of a plugin instance which is needed to know whether it has finished.
+#[derive(serde::Deserialize)]
+pub(crate) struct PluginInstanceStatus {
+    /// One of: "created", "waiting", "scheduled", "started", "registeringFiles",
+    /// "finishedSuccessfully", "finishedWithError", "cancelled"
+    pub status: String,
+}
+
+/// The parts of a workflow which are needed to know whether it has finished.
+///
+/// Ref: <https://github.com/FNNDSC/ChRIS_ultron_backEnd/blob/v6.4.0/chris_backend/workflows/serializers.py>
+#[derive(serde::Deserialize)]
+pub(crate) struct WorkflowJobCounts {
+    pub created_jobs: u32,
+    pub waiting_jobs: u32,
+    pub scheduled_jobs: u32,
+    pub started_jobs: u32,
+    pub registering_jobs: u32,
+    pub finished_jobs: u32,
+    pub errored_jobs: u32,
+    pub cancelled_jobs: u32,
+}
+
+/// A page of plugin instances, with only the IDs of the workflows which created them.
+#[derive(serde::Deserialize)]
+pub(crate) struct PluginInstanceWorkflowList {
+    pub results: Vec<PluginInstanceWorkflow>,
+}
+
+/// The workflow of a plugin instance, if it was created by a workflow.
+#[derive(serde::Deserialize)]
+pub(crate) struct PluginInstanceWorkflow {
+    #[serde(default)]
+    pub workflow_id: Option<u32>,
+}
+
+/// Request body for modifying the content of a feed's note.
+#[derive(serde::Serialize)]
+pub(crate) struct NotePatchRequest<'a> {
+    pub content: &'a str,
+}
+
+/// Request body for tagging a feed.
+#[derive(serde::Serialize)]
+pub(crate) struct TaggingCreateRequest {
+    pub tag_id: u32,
+}
+
+/// Request body for creating a tag.
+#[derive(serde::Serialize)]
+pub(crate) struct TagCreateRequest<'a> {
+    pub name: &'a str,
+    pub color: &'a str,
+}
+
+/// Request body for creating or modifying a comment on a feed.
+#[derive(serde::Serialize)]
+pub(crate) struct CommentRequest<'a> {
+    pub title: &'a str,
+    pub content: &'a str,
+}
+
+/// The parts of a feed or folder which are needed to know whether it is public.
+#[derive(serde::Deserialize)]
+pub(crate) struct Publicity {
+    pub url: String,
+    pub public: bool,
+}
+
+/// A page of folders.
+#[derive(serde::Deserialize)]
+pub(crate) struct FolderList {
+    pub results: Vec<FolderBrief>,
+}
+
+/// The parts of a folder which are needed to find it and know whether it is public.
+#[derive(serde::Deserialize)]
+pub(crate) struct FolderBrief {
+    pub url: String,
+    pub path: String,
+    pub public: bool,
+    pub link_files: String,
+}
+
+/// A page of link files.
+#[derive(serde::Deserialize)]
+pub(crate) struct LinkFileList {
+    pub results: Vec<LinkFileBrief>,
+}
+
+/// The parts of a link file which are needed to find it and know what it links to.
+#[derive(serde::Deserialize)]
+pub(crate) struct LinkFileBrief {
+    pub url: String,
+    /// Path of the link file itself.
+    pub fname: String,
+    /// Path which is linked to.
+    pub path: String,
+}
+
+/// Request body for creating a link file in a folder.
+#[derive(serde::Serialize)]
+pub(crate) struct LinkFileCreateRequest<'a> {
+    /// Path which is linked to.
+    pub path: &'a str,
+    /// Name of the link file in the folder.
+    pub name: &'a str,
+}
+
+/// Request body for making a feed public or not.
+#[derive(serde::Serialize)]
+pub(crate) struct FeedPublicRequest<'a> {
+    pub name: &'a str,
+    pub public: bool,
+}
+
+/// Request body for making a folder public or not.
+#[derive(serde::Serialize)]
+pub(crate) struct FolderPublicRequest<'a> {
+    pub path: &'a str,
+    pub public: bool,
+}
+
+/// A page of user or group permissions of a user file or folder.
+#[derive(serde::Deserialize)]
+pub(crate) struct FilePermissionList {
+    pub results: Vec<FilePermissionBrief>,
+}
+
+/// The parts of a user or group permission of a user file or folder which
+/// are needed to know whether it is as specified.
+#[derive(serde::Deserialize)]
+pub(crate) struct FilePermissionBrief {
+    pub url: String,
+    /// Either `"r"` or `"w"`.
+    pub permission: String,
+    /// Username of the user, if it is a user permission.
+    #[serde(default)]
+    pub user_username: Option<String>,
+    /// Name of the group, if it is a group permission.
+    #[serde(default)]
+    pub group_name: Option<String>,
+}
+
+/// Request body for sharing a user file or folder with a user.
+#[derive(serde::Serialize)]
+pub(crate) struct FileUserPermissionRequest<'a> {
+    pub username: &'a str,
+    pub permission: &'a str,
+}
+
+/// Request body for sharing a user file or folder with a group.
+#[derive(serde::Serialize)]
+pub(crate) struct FileGroupPermissionRequest<'a> {
+    pub grp_name: &'a str,
+    pub permission: &'a str,
+}
+
+/// Request body for changing the permission of a user or group on a user
+/// file or folder.
+#[derive(serde::Serialize)]
+pub(crate) struct FilePermissionRequest<'a> {
+    pub permission: &'a str,
+}
+
+/// A page of PACS series.
+#[derive(serde::Deserialize)]
+pub(crate) struct PacsSeriesList {
+    pub results: Vec<PacsSeriesBrief>,
+}
+
+/// The parts of a PACS series which are needed to find it.
+#[derive(serde::Deserialize)]
+pub(crate) struct PacsSeriesBrief {
+    pub url: String,
+    #[serde(rename = "SeriesInstanceUID")]
+    pub series_instance_uid: String,
+}
+
+/// Request body for registering a series of DICOM files which are in the
+/// storage of a PACS.
+#[derive(serde::Serialize)]
+pub(crate) struct PacsSeriesCreateRequest<'a> {
+    pub path: &'a str,
+    pub ndicom: usize,
+    pub pacs_name: &'a str,
+    #[serde(rename = "PatientID")]
+    pub patient_id: &'a str,
+    #[serde(rename = "PatientName", skip_serializing_if = "Option::is_none")]
+    pub patient_name: Option<&'a str>,
+    /// Formatted as `YYYY-MM-DD`.
+    #[serde(rename = "StudyDate", skip_serializing_if = "Option::is_none")]
+    pub study_date: Option<String>,
+    #[serde(rename = "StudyInstanceUID")]
+    pub study_instance_uid: &'a str,
+    #[serde(rename = "StudyDescription", skip_serializing_if = "Option::is_none")]
+    pub study_description: Option<&'a str>,
+    #[serde(rename = "SeriesInstanceUID")]
+    pub series_instance_uid: &'a str,
+    #[serde(rename = "SeriesDescription", skip_serializing_if = "Option::is_none")]
+    pub series_description: Option<&'a str>,
+    #[serde(rename = "Modality", skip_serializing_if = "Option::is_none")]
+    pub modality: Option<&'a str>,
+}
+
+/// A page of the PACS list or PACS search results.
+#[derive(serde::Deserialize)]
+pub(crate) struct PacsList {
+    pub results: Vec<PacsBrief>,
+}
+
+/// The parts of a PACS which are needed to find it.
+#[derive(serde::Deserialize)]
+pub(crate) struct PacsBrief {
+    pub url: String,
+    pub identifier: String,
+}
+
+/// Request body for creating a PACS.
+#[derive(serde::Serialize)]
+pub(crate) struct PacsCreateRequest<'a> {
+    pub identifier: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active: Option<bool>,
+}
+
+/// A page of the PACS query search results.
+#[derive(serde::Deserialize)]
+pub(crate) struct PacsQueryList {
+    pub results: Vec<PacsQueryBrief>,
+}
+
+/// The parts of a PACS query which are needed to find it.
+#[derive(serde::Deserialize)]
+pub(crate) struct PacsQueryBrief {
+    pub url: String,
+    pub title: String,
+    pub owner_username: String,
+    pub pacs_identifier: String,
+}
+
+/// Request body for creating a PACS query.
+#[derive(serde::Serialize)]
+pub(crate) struct PacsQueryCreateRequest<'a> {
+    pub title: &'a str,
+    pub query: &'a HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<&'a str>,
+}
@@ -32,6 +32,8 @@ pub struct Counts {
     pub unmodified: u32,
     /// Count of resources modified.
     pub modified: u32,
+    /// Count of resources which had unspecified parts removed.
+    pub removed: u32,
     /// Count of resources which could not be affected due to unsatisfied prerequisites.
     pub unfulfilled: u32,
     /// Count of resources which could not be modified due to errors.
@@ -46,6 +48,7 @@ impl<'a> FromIterator<&'a StepEffect> for Counts {
                     StepEffect::Created => &mut counts.created,
                     StepEffect::Unmodified => &mut counts.unmodified,
                     StepEffect::Modified => &mut counts.modified,
+                    StepEffect::Removed => &mut counts.removed,
                     StepEffect::Unfulfilled(..) => &mut counts.unfulfilled,
                     StepEffect::Error(..) => &mut counts.error,
                 };
@@ -101,7 +104,8 @@ fn importance_of(effect: &StepEffect) -> u8 {
         StepEffect::Created => 3,
         StepEffect::Unmodified => 1,
         StepEffect::Modified => 2,
-        StepEffect::Unfulfilled(..) => 4,
-        StepEffect::Error(..) => 5,
+        StepEffect::Removed => 4,
+        StepEffect::Unfulfilled(..) => 5,
+        StepEffect::Error(..) => 6,
     }
 }
@@ -1,16 +1,23 @@
+mod deadline;
 mod dependency_spy;
 mod dependency_tree;
+mod dicom;
 mod exec_step;
 mod exec_tree;
 mod extra_models;
 mod fully_exec_tree;
+mod local_files;
 mod plan;
 mod request_builder;
 mod state;
 mod steps;
+mod warnings;
 
 pub use dependency_tree::DependencyTree;
+pub use dicom::read_header;
 pub use exec_step::{Outcome, StepEffect, StepError};
 pub use exec_tree::exec_tree;
 pub use fully_exec_tree::*;
+pub use local_files::list_files;
 pub use plan::plan;
+pub use warnings::warnings_of;
@@ -0,0 +1,81 @@
+//! Listing of local files to upload as user files.
+
+use std::io;
+use std::path::Path;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+/// List the files under `dir` recursively, as paths relative to `dir`
+/// separated by `/`, in sorted order.
+///
+/// A file is listed if it matches any of `include` (or `include` is empty)
+/// and does not match any of `exclude`. Symbolic links to files are listed,
+/// symbolic links to directories are not followed.
+pub fn list_files(dir: &Path, include: &[Glob], exclude: &[Glob]) -> io::Result<Vec<String>> {
+    let include = glob_set(include)?;
+    let exclude = glob_set(exclude)?;
+    let mut files = Vec::new();
+    walk(dir, "", &mut files)?;
+    files.retain(|file| (include.is_empty() || include.is_match(file)) && !exclude.is_match(file));
+    files.sort();
+    Ok(files)
+}
+
+fn glob_set(globs: &[Glob]) -> io::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(glob.clone());
+    }
+    builder.build().map_err(io::Error::other)
+}
+
+fn walk(dir: &Path, prefix: &str, files: &mut Vec<String>) -> io::Result<()> {
+    let context = |e: io::Error| {
+        io::Error::new(
+            e.kind(),
+            format!("Cannot read directory \"{}\": {e}", dir.display()),
+        )
+    };
+    for entry in std::fs::read_dir(dir).map_err(context)? {
+        let entry = entry.map_err(context)?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let relative = if prefix.is_empty() {
+            name
+        } else {
+            format!("{prefix}/{name}")
+        };
+        let file_type = entry.file_type().map_err(context)?;
+        if file_type.is_dir() {
+            walk(&entry.path(), &relative, files)?;
+        } else if file_type.is_file() || (file_type.is_symlink() && entry.path().is_file()) {
+            files.push(relative);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_files_skips_symlinked_directories() {
+        let dir =
+            std::env::temp_dir().join(format!("chrisomatic-list-files-{}", std::process::id()));
+        let sub = dir.join("sub");
+        std::fs::create_dir_all(&sub).unwrap();
+        std::fs::write(dir.join("a.txt"), "a").unwrap();
+        std::fs::write(sub.join("b.txt"), "b").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&dir, sub.join("loop")).unwrap();
+        let files = list_files(&dir, &[], &[]);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files.unwrap(), vec!["a.txt", "sub/b.txt"]);
+    }
+
+    #[test]
+    fn test_list_files_missing_directory() {
+        let dir = Path::new("/chrisomatic/does/not/exist");
+        assert!(list_files(dir, &[], &[]).is_err());
+    }
+}
@@ -1,39 +1,210 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroUsize;
 use std::rc::Rc;
+use std::time::Duration;
 
+use crate::deadline::Deadline;
 use crate::dependency_tree::{Dag, DependencyTree, NodeIndex};
 use crate::steps::*;
 use chrisomatic_spec::*;
 use chrisomatic_step::PendingStep;
+use compact_str::CompactString;
+use nonempty::NonEmpty;
 use petgraph::acyclic::Acyclic;
 use petgraph::data::Build;
 
 pub fn plan(manifest: Manifest) -> DependencyTree<Rc<dyn PendingStep>> {
     let mut tree = TreeBuilder::new();
     let url = manifest.global.cube;
-    let users: HashMap<_, _> = manifest
-        .user
+    let wait = manifest.global.wait.then(|| {
+        let timeout = Duration::from_secs(manifest.global.wait_timeout.get());
+        Rc::new(Deadline::new(timeout))
+    });
+    let groups = group_memberships(&manifest.user, &manifest.group);
+    let pacs_queries = pacs_queries_of(&manifest.user);
+    let links = links_of(&manifest.user);
+    let reset_password = manifest.global.reset_password;
+    // staff users are created by the admin user, and passwords are reset by the
+    // admin user, so those users are added after the admin node
+    let (by_admin, others): (Vec<_>, Vec<_>) =
+        manifest.user.into_iter().partition(|(username, details)| {
+            let admin = &manifest.global.admin.username;
+            details.is_staff(username, admin) || (reset_password && username != admin)
+        });
+    let mut users: HashMap<_, _> = others
         .into_iter()
-        .map(|(username, details)| add_steps_for_user(&mut tree, username, details, url.clone()))
+        .map(|(username, details)| {
+            add_steps_for_user(&mut tree, None, None, username, details, url.clone())
+        })
         .collect();
+    let mut admin = AdminNode::new(manifest.global.admin, url.clone(), &users);
+    let all_users = (reset_password && !by_admin.is_empty()).then(|| {
+        let admin_token = admin.get(&mut tree);
+        tree.add(
+            GroupExists {
+                group: Group::new(CompactString::const_new(ALL_USERS)),
+                url: url.clone(),
+            },
+            vec![admin_token],
+        )
+    });
+    for (username, details) in by_admin {
+        let (username, nodes) = add_steps_for_user(
+            &mut tree,
+            Some(&mut admin),
+            all_users,
+            username,
+            details,
+            url.clone(),
+        );
+        users.insert(username, nodes);
+    }
+    let mut memberships = HashMap::new();
+    let groups: HashMap<_, _> = groups
+        .into_iter()
+        .map(|(group, members)| {
+            let exclusive = manifest
+                .group
+                .get(&group)
+                .is_some_and(|spec| spec.exclusive);
+            let declared = exclusive.then(|| members.iter().cloned().collect());
+            let id = add_steps_for_group(
+                &mut tree,
+                &mut admin,
+                &users,
+                &mut memberships,
+                group.clone(),
+                members,
+                url.clone(),
+            );
+            if let Some(members) = declared {
+                add_steps_for_exclusive_group(&mut tree, &mut admin, group.clone(), members, id);
+            }
+            (group, id)
+        })
+        .collect();
+    let pacs: HashMap<_, _> = manifest
+        .pacs
+        .into_iter()
+        .map(|(identifier, spec)| add_steps_for_pacs(&mut tree, &mut admin, identifier, spec, &url))
+        .collect();
+    for (owner, query) in pacs_queries {
+        add_steps_for_pacs_query(&mut tree, &users, &memberships, &pacs, owner, query, &url);
+    }
+    let compute_resources: HashMap<_, _> = manifest
+        .compute_resource
+        .into_iter()
+        .map(|(name, spec)| {
+            add_steps_for_compute_resource(&mut tree, &mut admin, name, spec, reset_password, &url)
+        })
+        .collect();
+    let mut plugins = PluginNodes::new(url.clone(), manifest.global.public_cube);
+    for entry in merge_plugin_entries(manifest.plugins) {
+        plugins.add(&mut tree, &mut admin, &compute_resources, entry);
+    }
+    let pipelines: HashMap<_, _> = manifest
+        .pipeline
+        .into_iter()
+        .map(|pipeline| {
+            add_steps_for_pipeline(&mut tree, &mut admin, &mut plugins, &users, pipeline, &url)
+        })
+        .collect();
+    let mut uploads = UploadLanes::new(manifest.global.upload_concurrency);
+    let mut userfiles = Vec::new();
+    for userfile in manifest.userfiles {
+        userfiles.extend(add_steps_for_userfile(
+            &mut tree,
+            &mut admin,
+            &users,
+            &groups,
+            &mut uploads,
+            userfile,
+            &url,
+        ));
+    }
+    add_steps_for_links(&mut tree, &users, &userfiles, links, &url);
+    for pacs_files in manifest.pacs_files {
+        add_steps_for_pacs_files(
+            &mut tree,
+            &mut admin,
+            &users,
+            &pacs,
+            &mut uploads,
+            pacs_files,
+            &url,
+        );
+    }
+    let inputs = FeedInputs {
+        users: &users,
+        groups: &groups,
+        userfiles: &userfiles,
+        pipelines: &pipelines,
+        wait,
+    };
+    let mut tags = TagNodes::new(url.clone());
+    for feed in manifest.feed {
+        add_steps_for_feed(
+            &mut tree,
+            &mut admin,
+            &mut plugins,
+            &mut tags,
+            &inputs,
+            feed,
+            &url,
+        );
+    }
     tree.into()
 }
 
+/// Name of the plugin used to create feeds from files in _ChRIS_ storage.
+const DIRCOPY: &str = "pl-dircopy";
+
+/// Name of the group which every user is a member of.
+const ALL_USERS: &str = "all_users";
+
+/// Add the steps for a user.
+///
+/// If `admin` is given, the user is made a staff user if specified. If
+/// `all_users` (the node providing the users URL of the [ALL_USERS] group)
+/// is also given, the user's password is reset if it is not as specified.
 fn add_steps_for_user(
     tree: &mut TreeBuilder,
+    admin: Option<&mut AdminNode>,
+    all_users: Option<NodeIndex>,
     username: Username,
     details: UserDetails,
     url: CubeUrl,
-) -> (Username, NodeIndex) {
+) -> (Username, UserNodes) {
     let password = details.password.clone();
     let details = Rc::new(details);
+    let admin_token = admin.map(|admin| admin.get(tree));
+    let staff = details.admin && admin_token.is_some();
+    let reset = admin_token.zip(all_users).map(|(admin_token, all_users)| {
+        let find = tree.add(
+            UserFind {
+                username: username.clone(),
+                all_users: Group::new(CompactString::const_new(ALL_USERS)),
+                url: url.clone(),
+            },
+            vec![all_users, admin_token],
+        );
+        tree.add(
+            UserPasswordReset {
+                username: username.clone(),
+                details: Rc::clone(&details),
+                url: url.clone(),
+            },
+            vec![find, admin_token],
+        )
+    });
     let exists = tree.add(
         UserExists {
             username: username.clone(),
             details: Rc::clone(&details),
+            staff,
             url: url.clone(),
         },
-        vec![],
+        admin_token.into_iter().chain(reset).collect(),
     );
     let auth_token = tree.add(
         UserGetAuthToken {
@@ -59,14 +230,1135 @@ fn add_steps_for_user(
         },
         vec![get_url, auth_token],
     );
-    tree.add(
+    let finalize = tree.add(
         UserDetailsFinalize {
             username: username.clone(),
-            details,
+            details: Rc::clone(&details),
         },
         vec![get_details, auth_token],
     );
-    (username, auth_token)
+    if let Some(admin_token) = admin_token.filter(|_| staff) {
+        tree.add(
+            UserStaffFinalize {
+                username: username.clone(),
+                details,
+            },
+            vec![get_details, finalize, admin_token],
+        );
+    }
+    let nodes = UserNodes {
+        auth_token,
+        details: get_details,
+    };
+    (username, nodes)
+}
+
+/// Nodes of the steps for a user which steps of other resources may depend on.
+#[derive(Copy, Clone, Debug)]
+struct UserNodes {
+    /// Node providing [chrisomatic_step::Dependency::AuthToken].
+    auth_token: NodeIndex,
+    /// Node providing [chrisomatic_step::Dependency::UserGroupsUrl].
+    details: NodeIndex,
+}
+
+/// Collect the members of every group mentioned by the users or declared.
+fn group_memberships(
+    users: &HashMap<Username, UserDetails>,
+    declared: &HashMap<Group, GroupSpec>,
+) -> HashMap<Group, Vec<Username>> {
+    let mut groups: HashMap<Group, Vec<Username>> = declared
+        .keys()
+        .map(|group| (group.clone(), Vec::new()))
+        .collect();
+    for (username, details) in users {
+        for group in &details.groups {
+            groups
+                .entry(group.clone())
+                .or_default()
+                .push(username.clone());
+        }
+    }
+    groups
+}
+
+/// Add the steps for a group and its members. The nodes of the steps which
+/// add the members are inserted into `memberships`.
+fn add_steps_for_group(
+    tree: &mut TreeBuilder,
+    admin: &mut AdminNode,
+    users: &HashMap<Username, UserNodes>,
+    memberships: &mut HashMap<(Group, Username), NodeIndex>,
+    group: Group,
+    members: Vec<Username>,
+    url: CubeUrl,
+) -> NodeIndex {
+    let admin_token = admin.get(tree);
+    let exists = tree.add(
+        GroupExists {
+            group: group.clone(),
+            url,
+        },
+        vec![admin_token],
+    );
+    for username in members {
+        let user = users[&username];
+        let member = tree.add(
+            GroupMemberExists {
+                group: group.clone(),
+                username: username.clone(),
+            },
+            vec![exists, admin_token, user.auth_token, user.details],
+        );
+        memberships.insert((group.clone(), username), member);
+    }
+    exists
+}
+
+/// Add the step to remove the members of an exclusive group who are not
+/// declared. `exists` is the node of the [GroupExists] step of the group.
+fn add_steps_for_exclusive_group(
+    tree: &mut TreeBuilder,
+    admin: &mut AdminNode,
+    group: Group,
+    members: HashSet<Username>,
+    exists: NodeIndex,
+) {
+    let admin_token = admin.get(tree);
+    tree.add(
+        GroupExclusive {
+            group,
+            members: Rc::new(members),
+        },
+        vec![exists, admin_token],
+    );
+}
+
+/// Name of the group whose members can use PACS.
+const PACS_USERS: &str = "pacs_users";
+
+/// Collect the PACS queries of every user.
+fn pacs_queries_of(users: &HashMap<Username, UserDetails>) -> Vec<(Username, PacsQuerySpec)> {
+    users
+        .iter()
+        .flat_map(|(username, details)| {
+            details
+                .pacs_queries
+                .iter()
+                .map(|query| (username.clone(), query.clone()))
+        })
+        .collect()
+}
+
+fn add_steps_for_pacs(
+    tree: &mut TreeBuilder,
+    admin: &mut AdminNode,
+    identifier: String,
+    spec: PacsSpec,
+    url: &CubeUrl,
+) -> (String, NodeIndex) {
+    let admin_token = admin.get(tree);
+    let exists = tree.add(
+        PacsExists {
+            identifier: identifier.clone(),
+            spec: Rc::new(spec),
+            url: url.clone(),
+        },
+        vec![admin_token],
+    );
+    (identifier, exists)
+}
+
+/// Add the step to create a PACS query as its owner. If the owner is made a
+/// member of the [PACS_USERS] group, the query is created afterwards.
+fn add_steps_for_pacs_query(
+    tree: &mut TreeBuilder,
+    users: &HashMap<Username, UserNodes>,
+    memberships: &HashMap<(Group, Username), NodeIndex>,
+    pacs: &HashMap<String, NodeIndex>,
+    owner: Username,
+    query: PacsQuerySpec,
+    url: &CubeUrl,
+) {
+    let pacs_users = Group::new(CompactString::const_new(PACS_USERS));
+    let membership = memberships.get(&(pacs_users, owner.clone())).copied();
+    let needs = [users[&owner].auth_token, pacs[&query.pacs]]
+        .into_iter()
+        .chain(membership)
+        .collect();
+    tree.add(
+        PacsQueryExists {
+            owner,
+            query: Rc::new(query),
+            url: url.clone(),
+        },
+        needs,
+    );
+}
+
+fn add_steps_for_compute_resource(
+    tree: &mut TreeBuilder,
+    admin: &mut AdminNode,
+    name: ComputeResourceName,
+    spec: ComputeResourceSpec,
+    reset_password: bool,
+    url: &CubeUrl,
+) -> (ComputeResourceName, NodeIndex) {
+    let admin_token = admin.get(tree);
+    let spec = Rc::new(spec);
+    let exists = tree.add(
+        ComputeResourceExists {
+            name: name.clone(),
+            spec: Rc::clone(&spec),
+            url: url.clone(),
+        },
+        vec![admin_token],
+    );
+    tree.add(
+        ComputeResourceFinalize {
+            name: name.clone(),
+            spec,
+            reset_password,
+        },
+        vec![exists, admin_token],
+    );
+    (name, exists)
+}
+
+/// Merge plugins which are specified more than once. Their compute resources
+/// are combined and the first specified descriptor is used. Plugins are
+/// returned in the order they are first specified.
+fn merge_plugin_entries(plugins: Vec<PluginEntry>) -> Vec<PluginEntry> {
+    let mut indexes: HashMap<PluginSpec, usize> = HashMap::with_capacity(plugins.len());
+    let mut merged: Vec<PluginEntry> = Vec::with_capacity(plugins.len());
+    for entry in plugins {
+        let Some(existing) = indexes.get(&entry.plugin).map(|i| &mut merged[*i]) else {
+            indexes.insert(entry.plugin.clone(), merged.len());
+            merged.push(entry);
+            continue;
+        };
+        for name in entry.compute_resources {
+            if !existing.compute_resources.contains(&name) {
+                existing.compute_resources.push(name);
+            }
+        }
+        if existing.descriptor.is_none() {
+            existing.descriptor = entry.descriptor;
+        }
+    }
+    merged
+}
+
+/// Add steps to register a plugin. The steps will run after the creation of
+/// any of the plugin's compute resources which are specified in the manifest.
+fn add_steps_for_plugin(
+    tree: &mut TreeBuilder,
+    admin: &mut AdminNode,
+    compute_resources: &HashMap<ComputeResourceName, NodeIndex>,
+    entry: PluginEntry,
+    url: &CubeUrl,
+    public_cube: &CubeUrl,
+) -> NodeIndex {
+    let PluginEntry {
+        plugin,
+        compute_resources: names,
+        descriptor,
+    } = entry;
+    let admin_token = admin.get(tree);
+    let needs: Vec<_> = std::iter::once(admin_token)
+        .chain(
+            names
+                .iter()
+                .filter_map(|name| compute_resources.get(name).copied()),
+        )
+        .collect();
+    let names = Rc::new(names);
+    let exists = tree.add(
+        PluginExists {
+            plugin: plugin.clone(),
+            compute_resources: Rc::clone(&names),
+            descriptor: descriptor.map(Rc::new),
+            url: url.clone(),
+            public_cube: public_cube.clone(),
+        },
+        needs.clone(),
+    );
+    if !names.is_empty() {
+        tree.add(
+            PluginComputeResourcesFinalize {
+                plugin,
+                compute_resources: names,
+            },
+            [exists].into_iter().chain(needs).collect(),
+        );
+    }
+    exists
+}
+
+/// Steps which register plugins, by [PluginSpec].
+struct PluginNodes {
+    url: CubeUrl,
+    public_cube: CubeUrl,
+    nodes: HashMap<PluginSpec, NodeIndex>,
+}
+
+impl PluginNodes {
+    fn new(url: CubeUrl, public_cube: CubeUrl) -> Self {
+        Self {
+            url,
+            public_cube,
+            nodes: HashMap::new(),
+        }
+    }
+
+    /// Add steps to register a plugin specified in the manifest.
+    fn add(
+        &mut self,
+        tree: &mut TreeBuilder,
+        admin: &mut AdminNode,
+        compute_resources: &HashMap<ComputeResourceName, NodeIndex>,
+        entry: PluginEntry,
+    ) -> NodeIndex {
+        let plugin = entry.plugin.clone();
+        let id = add_steps_for_plugin(
+            tree,
+            admin,
+            compute_resources,
+            entry,
+            &self.url,
+            &self.public_cube,
+        );
+        self.nodes.insert(plugin, id);
+        id
+    }
+
+    /// Get the node of the step which registers a plugin. If the plugin is
+    /// not specified in the manifest, a step is added to register it.
+    fn get(
+        &mut self,
+        tree: &mut TreeBuilder,
+        admin: &mut AdminNode,
+        plugin: PluginSpec,
+    ) -> NodeIndex {
+        if let Some(id) = self.nodes.get(&plugin) {
+            return *id;
+        }
+        self.add(tree, admin, &HashMap::new(), plugin.into())
+    }
+
+    /// Get the node of the step which registers a plugin. If the version of
+    /// the plugin is not specified, see [PluginNodes::get_by_name].
+    fn resolve(
+        &mut self,
+        tree: &mut TreeBuilder,
+        admin: &mut AdminNode,
+        plugin: PluginSpec,
+    ) -> (PluginSpec, NodeIndex) {
+        if plugin.version().is_some() {
+            let id = self.get(tree, admin, plugin.clone());
+            (plugin, id)
+        } else {
+            self.get_by_name(tree, admin, plugin.name())
+        }
+    }
+
+    /// Get the node of the step which registers a plugin by name. If no
+    /// version of the plugin is specified in the manifest, a step is added
+    /// to register its latest version.
+    fn get_by_name(
+        &mut self,
+        tree: &mut TreeBuilder,
+        admin: &mut AdminNode,
+        name: &str,
+    ) -> (PluginSpec, NodeIndex) {
+        if let Some((plugin, id)) = self.nodes.iter().find(|(plugin, _)| plugin.name() == name) {
+            return (plugin.clone(), *id);
+        }
+        let plugin = PluginSpec::new(name, None);
+        let id = self.add(tree, admin, &HashMap::new(), plugin.clone().into());
+        (plugin, id)
+    }
+}
+
+/// Add steps to create a pipeline, which will run after the registration
+/// of its plugins.
+fn add_steps_for_pipeline(
+    tree: &mut TreeBuilder,
+    admin: &mut AdminNode,
+    plugins: &mut PluginNodes,
+    users: &HashMap<Username, UserNodes>,
+    pipeline: PipelineSpec,
+    url: &CubeUrl,
+) -> (String, PipelineNode) {
+    let owner = pipeline.owner.unwrap_or_else(|| admin.username().clone());
+    let owner_token = auth_token_of(tree, admin, users, &owner);
+    let definition = pipeline.definition;
+    let mut needs = vec![owner_token];
+    for piece in &definition.plugin_tree {
+        let id = plugins.get(tree, admin, piece.plugin());
+        if !needs.contains(&id) {
+            needs.push(id);
+        }
+    }
+    let name = definition.name.clone();
+    let titles = definition
+        .plugin_tree
+        .iter()
+        .map(|piece| piece.title.clone())
+        .collect();
+    let id = tree.add(
+        PipelineExists {
+            owner,
+            definition: Rc::new(definition),
+            url: url.clone(),
+        },
+        needs,
+    );
+    (name, PipelineNode { id, titles })
+}
+
+/// Node of the step which creates a pipeline, and the titles of its plugins.
+struct PipelineNode {
+    id: NodeIndex,
+    titles: Vec<String>,
+}
+
+/// Suffix of the names of _ChRIS_ link files.
+const CHRISLINK_SUFFIX: &str = ".chrislink";
+
+/// Collect the link files of every user.
+fn links_of(users: &HashMap<Username, UserDetails>) -> Vec<(Username, LinkSpec)> {
+    users
+        .iter()
+        .flat_map(|(username, details)| {
+            details
+                .links
+                .iter()
+                .map(|link| (username.clone(), link.clone()))
+        })
+        .collect()
+}
+
+/// Add steps to create the link files of users. The folder of each link
+/// file is found first, since link files are listed and created through it.
+/// Folders are created by uploading files, so a folder is found after the
+/// user files uploaded into it.
+fn add_steps_for_links(
+    tree: &mut TreeBuilder,
+    users: &HashMap<Username, UserNodes>,
+    userfiles: &[(String, NodeIndex)],
+    links: Vec<(Username, LinkSpec)>,
+    url: &CubeUrl,
+) {
+    let mut folders: HashMap<(Username, String), NodeIndex> = HashMap::new();
+    for (owner, link) in links {
+        let owner_token = users[&owner].auth_token;
+        let path = if link.path.ends_with(CHRISLINK_SUFFIX) {
+            link.path
+        } else {
+            format!("{}{CHRISLINK_SUFFIX}", link.path)
+        };
+        let (folder, _) = split_link_path(&path);
+        let key = (owner.clone(), folder.to_string());
+        let find = *folders.entry(key).or_insert_with(|| {
+            let prefix = format!("{folder}/");
+            let needs = std::iter::once(owner_token)
+                .chain(
+                    userfiles
+                        .iter()
+                        .filter(|(path, _)| path.starts_with(&prefix))
+                        .map(|(_, id)| *id),
+                )
+                .collect();
+            tree.add(
+                FolderFind {
+                    owner: owner.clone(),
+                    path: folder.to_string(),
+                    public: None,
+                    needed_for: Some("link files"),
+                    url: url.clone(),
+                },
+                needs,
+            )
+        });
+        tree.add(
+            LinkFileExists {
+                owner,
+                path,
+                target: link.target,
+            },
+            vec![find, owner_token],
+        );
+    }
+}
+
+/// Add steps to upload and share user files. A directory is expanded into
+/// steps for each of its files, and its folder is shared instead.
+fn add_steps_for_userfile(
+    tree: &mut TreeBuilder,
+    admin: &mut AdminNode,
+    users: &HashMap<Username, UserNodes>,
+    groups: &HashMap<Group, NodeIndex>,
+    uploads: &mut UploadLanes,
+    userfile: UserFileSpec,
+    url: &CubeUrl,
+) -> Vec<(String, NodeIndex)> {
+    let owner = userfile.owner.unwrap_or_else(|| admin.username().clone());
+    let owner_token = auth_token_of(tree, admin, users, &owner);
+    let (files, folder) = match userfile.content {
+        UserFileContent::Directory {
+            directory,
+            public,
+            files,
+            ..
+        } => {
+            let dest = userfile.path.trim_end_matches('/');
+            let files = files
+                .into_iter()
+                .flatten()
+                .map(|relative| {
+                    let source = directory.join(&relative);
+                    (format!("{dest}/{relative}"), UploadContent::Source(source))
+                })
+                .collect();
+            (files, Some((dest.to_string(), public)))
+        }
+        UserFileContent::Text { text } => (vec![(userfile.path, UploadContent::Text(text))], None),
+        UserFileContent::Source { source } => {
+            (vec![(userfile.path, UploadContent::Source(source))], None)
+        }
+    };
+    let share_steps = |tree: &mut TreeBuilder, kind, path: &str, found| {
+        for share in &userfile.share {
+            // the user or group being shared with must exist first
+            let target_node = match &share.target {
+                PermissionTarget::User(username) => {
+                    users.get(username).map(|nodes| nodes.auth_token)
+                }
+                PermissionTarget::Group(group) => groups.get(group).copied(),
+            };
+            let needs = [found, owner_token]
+                .into_iter()
+                .chain(target_node)
+                .collect();
+            let id = tree.add(
+                FilePermissionExists {
+                    owner: owner.clone(),
+                    kind,
+                    path: path.to_string(),
+                    target: share.target.clone(),
+                    permission: share.permission,
+                },
+                needs,
+            );
+            tree.add(
+                FilePermissionFinalize {
+                    owner: owner.clone(),
+                    path: path.to_string(),
+                    target: share.target.clone(),
+                    permission: share.permission,
+                },
+                vec![id, owner_token],
+            );
+        }
+    };
+    let files: Vec<_> = files
+        .into_iter()
+        .map(|(path, content)| {
+            let content = Rc::new(content);
+            let needs = [owner_token].into_iter().chain(uploads.last()).collect();
+            let exists = tree.add(
+                UserFileExists {
+                    owner: owner.clone(),
+                    path: path.clone(),
+                    content: Rc::clone(&content),
+                    url: url.clone(),
+                },
+                needs,
+            );
+            let finalize = tree.add(
+                UserFileContentFinalize {
+                    owner: owner.clone(),
+                    path: path.clone(),
+                    content,
+                    url: url.clone(),
+                },
+                vec![exists, owner_token],
+            );
+            uploads.push(finalize);
+            if folder.is_none() {
+                // a replaced file has a new URL, so it is shared once finalized
+                share_steps(tree, SharedFileKind::UserFile, &path, finalize);
+            }
+            (path, finalize)
+        })
+        .collect();
+    if let Some((path, public)) = folder
+        && (public.is_some() || !userfile.share.is_empty())
+    {
+        // the folder is created by uploading files into it
+        let needs = [owner_token]
+            .into_iter()
+            .chain(files.iter().map(|(_, id)| *id))
+            .collect();
+        let find = tree.add(
+            FolderFind {
+                owner: owner.clone(),
+                path: path.clone(),
+                public,
+                needed_for: None,
+                url: url.clone(),
+            },
+            needs,
+        );
+        if let Some(public) = public {
+            tree.add(
+                FolderPublicFinalize {
+                    owner: owner.clone(),
+                    path: path.clone(),
+                    public,
+                },
+                vec![find, owner_token],
+            );
+        }
+        share_steps(tree, SharedFileKind::Folder, &path, find);
+    }
+    files
+}
+
+/// Add steps to upload the DICOM files of a local directory into the storage
+/// of a PACS, then to register each of their series.
+///
+/// The files are uploaded by the admin user into the folder
+/// `SERVICES/PACS/{pacs_identifier}/{PatientID}/{StudyInstanceUID}/{SeriesInstanceUID}`,
+/// keeping their paths relative to the directory so that files of the same
+/// name do not collide.
+fn add_steps_for_pacs_files(
+    tree: &mut TreeBuilder,
+    admin: &mut AdminNode,
+    users: &HashMap<Username, UserNodes>,
+    pacs: &HashMap<String, NodeIndex>,
+    uploads: &mut UploadLanes,
+    pacs_files: PacsFilesSpec,
+    url: &CubeUrl,
+) {
+    let PacsFilesSpec {
+        pacs_identifier,
+        directory,
+        files,
+    } = pacs_files;
+    let owner = admin.username().clone();
+    let owner_token = auth_token_of(tree, admin, users, &owner);
+    // series by SeriesInstanceUID, in order of their first file
+    let mut series: Vec<(String, Rc<DicomHeader>, Vec<NodeIndex>)> = Vec::new();
+    for DicomFile { relative, header } in files.into_iter().flatten() {
+        let source = directory.join(&relative);
+        let index = series
+            .iter()
+            .position(|(_, h, _)| h.series_instance_uid == header.series_instance_uid)
+            .unwrap_or_else(|| {
+                let folder = format!(
+                    "SERVICES/PACS/{pacs_identifier}/{}/{}/{}",
+                    header.patient_id, header.study_instance_uid, header.series_instance_uid
+                );
+                series.push((folder, Rc::new(header), vec![]));
+                series.len() - 1
+            });
+        let (folder, _, files) = &mut series[index];
+        let needs = [owner_token].into_iter().chain(uploads.last()).collect();
+        let exists = tree.add(
+            UserFileExists {
+                owner: owner.clone(),
+                path: format!("{folder}/{relative}"),
+                content: Rc::new(UploadContent::Source(source)),
+                url: url.clone(),
+            },
+            needs,
+        );
+        uploads.push(exists);
+        files.push(exists);
+    }
+    let admin_token = admin.get(tree);
+    // the PACS is created first if it is declared in the manifest
+    let pacs = pacs.get(&pacs_identifier).copied();
+    for (path, header, files) in series {
+        tree.add(
+            PacsSeriesExists {
+                pacs_identifier: pacs_identifier.clone(),
+                path,
+                header,
+                ndicom: files.len(),
+                url: url.clone(),
+            },
+            [admin_token].into_iter().chain(pacs).chain(files).collect(),
+        );
+    }
+}
+
+/// Limits how many user files are uploaded at the same time, by chaining
+/// the steps of uploads into a fixed number of "lanes".
+struct UploadLanes {
+    lanes: Vec<Option<NodeIndex>>,
+    next: usize,
+}
+
+impl UploadLanes {
+    fn new(concurrency: NonZeroUsize) -> Self {
+        Self {
+            lanes: vec![None; concurrency.get()],
+            next: 0,
+        }
+    }
+
+    /// Get the last node of the next lane, which the next upload must wait for.
+    fn last(&self) -> Option<NodeIndex> {
+        self.lanes[self.next]
+    }
+
+    /// Append the last node of an upload to the next lane.
+    fn push(&mut self, id: NodeIndex) {
+        self.lanes[self.next] = Some(id);
+        self.next = (self.next + 1) % self.lanes.len();
+    }
+}
+
+/// Nodes of steps for other resources which the steps for feeds may depend on.
+struct FeedInputs<'a> {
+    users: &'a HashMap<Username, UserNodes>,
+    groups: &'a HashMap<Group, NodeIndex>,
+    userfiles: &'a [(String, NodeIndex)],
+    pipelines: &'a HashMap<String, PipelineNode>,
+    /// Deadline for plugin instances and workflows to finish, if waited for at all.
+    wait: Option<Rc<Deadline>>,
+}
+
+fn add_steps_for_feed(
+    tree: &mut TreeBuilder,
+    admin: &mut AdminNode,
+    plugins: &mut PluginNodes,
+    tags: &mut TagNodes,
+    inputs: &FeedInputs,
+    feed: FeedSpec,
+    url: &CubeUrl,
+) -> NodeIndex {
+    let owner = feed.owner.unwrap_or_else(|| admin.username().clone());
+    let owner_token = auth_token_of(tree, admin, inputs.users, &owner);
+    let (plugin, args, files) = match feed.plugin {
+        FsPluginSpec::Dircopy { path } => {
+            // files to copy must be uploaded first
+            let files: Vec<_> = inputs
+                .userfiles
+                .iter()
+                .filter(|(file, _)| is_under(file, &path))
+                .map(|(_, id)| *id)
+                .collect();
+            let args =
+                HashMap::from([(CompactString::const_new("dir"), ArgValue::Stringish(path))]);
+            (PluginSpec::new(DIRCOPY, None), args, files)
+        }
+        FsPluginSpec::Other { plugin, args } => (plugin, args, vec![]),
+    };
+    let (plugin, plugin_node) = plugins.resolve(tree, admin, plugin);
+    let root_plugin = plugin.name().to_string();
+    let valid = tree.add(
+        PluginArgsValidate {
+            owner: owner.clone(),
+            feed: feed.name.clone(),
+            title: None,
+            plugin: plugin.clone(),
+            args: Rc::new(args.clone()),
+        },
+        vec![plugin_node, owner_token],
+    );
+    let needs = [owner_token, plugin_node, valid]
+        .into_iter()
+        .chain(files)
+        .collect();
+    let exists = tree.add(
+        FeedExists {
+            owner: owner.clone(),
+            name: feed.name.clone(),
+            plugin,
+            params: Rc::new(params_of(args)),
+            url: url.clone(),
+        },
+        needs,
+    );
+    if let Some(public) = feed.public {
+        tree.add(
+            FeedPublicFinalize {
+                owner: owner.clone(),
+                name: feed.name.clone(),
+                public,
+            },
+            vec![exists, owner_token],
+        );
+    }
+    if !needs_details(&feed) && inputs.wait.is_none() {
+        return exists;
+    }
+    let details = tree.add(
+        FeedGetDetails {
+            owner: owner.clone(),
+            name: feed.name.clone(),
+        },
+        vec![exists, owner_token],
+    );
+    for target in feed.share {
+        // the user or group being shared with must exist first
+        let target_node = match &target {
+            PermissionTarget::User(username) => {
+                inputs.users.get(username).map(|nodes| nodes.auth_token)
+            }
+            PermissionTarget::Group(group) => inputs.groups.get(group).copied(),
+        };
+        let needs = [details, owner_token]
+            .into_iter()
+            .chain(target_node)
+            .collect();
+        tree.add(
+            FeedShare {
+                owner: owner.clone(),
+                name: feed.name.clone(),
+                target,
+            },
+            needs,
+        );
+    }
+    if let Some(content) = feed.note {
+        tree.add(
+            FeedNoteFinalize {
+                owner: owner.clone(),
+                name: feed.name.clone(),
+                content,
+            },
+            vec![details, owner_token],
+        );
+    }
+    for tag in feed.tags {
+        let tag_node = tags.get(tree, &owner, owner_token, tag.clone());
+        tree.add(
+            FeedTag {
+                owner: owner.clone(),
+                name: feed.name.clone(),
+                tag,
+            },
+            vec![details, tag_node, owner_token],
+        );
+    }
+    for comment in feed.comments {
+        let exists = tree.add(
+            FeedCommentExists {
+                owner: owner.clone(),
+                feed: feed.name.clone(),
+                comment: comment.clone(),
+            },
+            vec![details, owner_token],
+        );
+        tree.add(
+            FeedCommentContentFinalize {
+                owner: owner.clone(),
+                feed: feed.name.clone(),
+                comment,
+            },
+            vec![exists, owner_token],
+        );
+    }
+    let mut instances = PluginInstanceNodes {
+        owner: owner.clone(),
+        feed: feed.name.clone(),
+        root_plugin,
+        url: url.clone(),
+        needs: vec![details, owner_token],
+        nodes: HashMap::new(),
+    };
+    // the root plugin instance was created by the feed
+    let mut created = vec![None];
+    for instance in feed.plugin_instances {
+        let previous = instances.get(tree, instance.previous.clone());
+        let (plugin, plugin_node) = plugins.resolve(tree, admin, instance.plugin);
+        let title = instance.title.unwrap_or_else(|| plugin.name().to_string());
+        let valid = tree.add(
+            PluginArgsValidate {
+                owner: owner.clone(),
+                feed: feed.name.clone(),
+                title: Some(title.clone()),
+                plugin: plugin.clone(),
+                args: Rc::new(instance.args.clone()),
+            },
+            vec![plugin_node, owner_token],
+        );
+        let params = Rc::new(params_of(instance.args));
+        let id = tree.add(
+            PluginInstanceExists {
+                owner: owner.clone(),
+                feed: feed.name.clone(),
+                plugin,
+                title: title.clone(),
+                previous: instance.previous,
+                params: Rc::clone(&params),
+                url: url.clone(),
+            },
+            vec![previous, details, plugin_node, owner_token, valid],
+        );
+        tree.add(
+            PluginInstanceArgsFinalize {
+                owner: owner.clone(),
+                feed: feed.name.clone(),
+                title: title.clone(),
+                params,
+                url: url.clone(),
+            },
+            vec![id, owner_token],
+        );
+        instances.nodes.insert(Some(title.clone()), id);
+        created.push(Some(title));
+    }
+    let mut workflows = Vec::with_capacity(feed.workflows.len());
+    let mut workflows_after = HashMap::new();
+    for workflow in feed.workflows {
+        let previous = instances.get(tree, workflow.previous.clone());
+        let after = *workflows_after
+            .entry(workflow.previous.clone())
+            .or_insert_with(|| {
+                tree.add(
+                    WorkflowsAfter {
+                        owner: owner.clone(),
+                        feed: feed.name.clone(),
+                        previous: workflow.previous.clone(),
+                        url: url.clone(),
+                    },
+                    vec![previous, owner_token],
+                )
+            });
+        let title = workflow
+            .title
+            .unwrap_or_else(|| format!("{}: {}", &feed.name, &workflow.pipeline));
+        let pipeline = &inputs.pipelines[&workflow.pipeline];
+        let id = tree.add(
+            WorkflowExists {
+                owner: owner.clone(),
+                feed: feed.name.clone(),
+                pipeline: workflow.pipeline,
+                title: title.clone(),
+                previous: workflow.previous,
+                url: url.clone(),
+            },
+            vec![previous, after, pipeline.id, owner_token],
+        );
+        // the plugin instances of the workflow can be the previous plugin
+        // instances of workflows declared after it
+        if let Some(pieces) = NonEmpty::from_vec(pipeline.titles.clone()) {
+            let found = tree.add(
+                WorkflowPluginInstances {
+                    owner: owner.clone(),
+                    feed: feed.name.clone(),
+                    title: title.clone(),
+                    pieces: Rc::new(pieces.clone()),
+                    url: url.clone(),
+                },
+                vec![id, owner_token],
+            );
+            for piece in pieces {
+                instances.nodes.insert(Some(piece), found);
+            }
+        }
+        workflows.push((title, id));
+    }
+    if let Some(deadline) = &inputs.wait {
+        for title in created {
+            let id = instances.get(tree, title.clone());
+            tree.add(
+                PluginInstanceWait {
+                    owner: owner.clone(),
+                    feed: feed.name.clone(),
+                    title,
+                    deadline: Rc::clone(deadline),
+                    url: url.clone(),
+                },
+                vec![id, owner_token],
+            );
+        }
+        for (title, id) in workflows {
+            tree.add(
+                WorkflowWait {
+                    owner: owner.clone(),
+                    feed: feed.name.clone(),
+                    title,
+                    deadline: Rc::clone(deadline),
+                },
+                vec![id, owner_token],
+            );
+        }
+    }
+    exists
+}
+
+/// Returns `true` if the ID or URLs of the feed are needed to create or
+/// modify anything else.
+fn needs_details(feed: &FeedSpec) -> bool {
+    !(feed.share.is_empty()
+        && feed.note.is_none()
+        && feed.tags.is_empty()
+        && feed.comments.is_empty()
+        && feed.plugin_instances.is_empty()
+        && feed.workflows.is_empty())
+}
+
+/// Steps which create tags, which are shared by the feeds of their owner.
+struct TagNodes {
+    url: CubeUrl,
+    nodes: HashMap<(Username, String), NodeIndex>,
+}
+
+impl TagNodes {
+    fn new(url: CubeUrl) -> Self {
+        Self {
+            url,
+            nodes: HashMap::new(),
+        }
+    }
+
+    /// Get the node of the step which creates a tag, adding the step if needed.
+    fn get(
+        &mut self,
+        tree: &mut TreeBuilder,
+        owner: &Username,
+        owner_token: NodeIndex,
+        name: String,
+    ) -> NodeIndex {
+        let key = (owner.clone(), name);
+        if let Some(id) = self.nodes.get(&key) {
+            return *id;
+        }
+        let id = tree.add(
+            TagExists {
+                owner: owner.clone(),
+                name: key.1.clone(),
+                url: self.url.clone(),
+            },
+            vec![owner_token],
+        );
+        self.nodes.insert(key, id);
+        id
+    }
+}
+
+/// Steps which provide the [chrisomatic_step::Dependency::PluginInstanceId]
+/// of plugin instances in a feed, by title.
+struct PluginInstanceNodes {
+    owner: Username,
+    feed: String,
+    root_plugin: String,
+    url: CubeUrl,
+    /// Nodes providing the feed's ID and the owner's auth token.
+    needs: Vec<NodeIndex>,
+    nodes: HashMap<Option<String>, NodeIndex>,
+}
+
+impl PluginInstanceNodes {
+    /// Get the node of the step which provides the ID of a plugin instance.
+    /// If the plugin instance is not created by a step, a step is added to
+    /// find it.
+    fn get(&mut self, tree: &mut TreeBuilder, title: Option<String>) -> NodeIndex {
+        if let Some(id) = self.nodes.get(&title) {
+            return *id;
+        }
+        let id = tree.add(
+            PluginInstanceFind {
+                owner: self.owner.clone(),
+                feed: self.feed.clone(),
+                title: title.clone(),
+                root_plugin: self.root_plugin.clone(),
+                url: self.url.clone(),
+            },
+            self.needs.clone(),
+        );
+        self.nodes.insert(title, id);
+        id
+    }
+}
+
+/// Convert plugin arguments to plugin parameter values.
+fn params_of(args: HashMap<CompactString, ArgValue>) -> PluginParams {
+    args.into_iter()
+        .map(|(name, value)| (name.to_string(), serde_json::to_value(value).unwrap()))
+        .collect()
+}
+
+/// Returns `true` if `file` is at or below the directory `dir` in _ChRIS_ storage.
+fn is_under(file: &str, dir: &str) -> bool {
+    let dir = dir.trim_end_matches('/');
+    file == dir
+        || file
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Get the node which provides the [chrisomatic_step::Dependency::AuthToken]
+/// of a user. The user must be either specified in the manifest or the admin.
+fn auth_token_of(
+    tree: &mut TreeBuilder,
+    admin: &mut AdminNode,
+    users: &HashMap<Username, UserNodes>,
+    username: &Username,
+) -> NodeIndex {
+    users
+        .get(username)
+        .map(|nodes| nodes.auth_token)
+        .unwrap_or_else(|| {
+            debug_assert_eq!(username, admin.username());
+            admin.get(tree)
+        })
+}
+
+/// The step to authenticate as the admin user, which is only added to the
+/// tree if some other step needs it.
+struct AdminNode {
+    credentials: UserCredentials,
+    url: CubeUrl,
+    /// Node providing the admin user's [chrisomatic_step::Dependency::AuthToken],
+    /// if the admin user is also specified as a user.
+    user: Option<NodeIndex>,
+    id: Option<NodeIndex>,
+}
+
+impl AdminNode {
+    /// If the admin user is also specified as a user, the admin user is
+    /// authenticated after the steps for that user.
+    fn new(
+        credentials: UserCredentials,
+        url: CubeUrl,
+        users: &HashMap<Username, UserNodes>,
+    ) -> Self {
+        let user = users
+            .get(&credentials.username)
+            .map(|nodes| nodes.auth_token);
+        Self {
+            credentials,
+            url,
+            user,
+            id: None,
+        }
+    }
+
+    fn username(&self) -> &Username {
+        &self.credentials.username
+    }
+
+    /// Get the node which provides [chrisomatic_step::Dependency::AdminToken],
+    /// adding it to the tree if needed.
+    fn get(&mut self, tree: &mut TreeBuilder) -> NodeIndex {
+        *self.id.get_or_insert_with(|| {
+            tree.add(
+                AdminGetAuthToken {
+                    credentials: self.credentials.clone(),
+                    url: self.url.clone(),
+                },
+                self.user.into_iter().collect(),
+            )
+        })
+    }
 }
 
 struct TreeBuilder(Dag<Rc<dyn PendingStep>>);
@@ -118,19 +1410,21 @@ impl From<TreeBuilder> for DependencyTree<Rc<dyn PendingStep>> {
 #[cfg(test)]
 mod tests {
 
-    use crate::dependency_spy::provides_of;
+    use crate::dependency_spy::{provides_of, target_of};
 
     use super::*;
     use chrisomatic_step::Dependency;
     use compact_str::CompactString;
     use rstest::*;
+    use std::num::NonZeroU64;
 
     #[rstest]
     fn test_add_steps_for_user(user: (Username, UserDetails), cube_url: CubeUrl) {
         let (username, details) = user;
         let mut tree = TreeBuilder::new();
-        let (username, token_id) = add_steps_for_user(&mut tree, username, details, cube_url);
-        let pending_step_for_token = tree.0.node_weight(token_id).unwrap();
+        let (username, nodes) =
+            add_steps_for_user(&mut tree, None, None, username, details, cube_url);
+        let pending_step_for_token = tree.0.node_weight(nodes.auth_token).unwrap();
 
         let provides = provides_of(pending_step_for_token);
         assert!(provides.contains(&Dependency::AuthToken(username.clone())));
@@ -148,20 +1442,751 @@ mod tests {
         )
     }
 
+    #[rstest]
+    fn test_plan_shared_group(user: (Username, UserDetails), manifest: Manifest) {
+        let (alice, alice_details) = user;
+        let bobby = Username::from("bobby");
+        let manifest = Manifest {
+            user: HashMap::from([
+                (alice.clone(), alice_details),
+                (bobby.clone(), details_of("bobby", &["pacs_users"])),
+            ]),
+            ..manifest
+        };
+        let tree = plan(manifest);
+        let pacs_users = Group::new(CompactString::const_new("pacs_users"));
+        assert_eq!(
+            nodes_of(&tree.0, &Dependency::GroupUrl(pacs_users.clone())).len(),
+            1
+        );
+        assert_eq!(
+            nodes_of(&tree.0, &Dependency::GroupMember(pacs_users.clone(), alice)).len(),
+            1
+        );
+        assert_eq!(
+            nodes_of(&tree.0, &Dependency::GroupMember(pacs_users, bobby)).len(),
+            1
+        );
+        assert_eq!(
+            nodes_of(&tree.0, &Dependency::AdminToken).len(),
+            1,
+            "admin user should be authenticated exactly once"
+        );
+    }
+
+    #[rstest]
+    #[case("alice", 0)]
+    #[case("chris", 1)]
+    fn test_staff_user(
+        user: (Username, UserDetails),
+        mut manifest: Manifest,
+        #[case] admin_username: &str,
+        #[case] expected: usize,
+    ) {
+        let (alice, mut alice_details) = user;
+        alice_details.admin = true;
+        manifest.global.admin = UserCredentials::basic_auth(admin_username, "chris1234");
+        manifest.user = HashMap::from([(alice.clone(), alice_details)]);
+        let tree = plan(manifest);
+        assert_eq!(
+            nodes_of(&tree.0, &Dependency::UserStaff(alice)).len(),
+            expected,
+            "the admin user itself should not be promoted"
+        );
+    }
+
+    #[rstest]
+    #[case("home/alice/reference")]
+    #[case("home/alice/reference.chrislink")]
+    fn test_user_link(user: (Username, UserDetails), manifest: Manifest, #[case] path: &str) {
+        let (alice, mut alice_details) = user;
+        alice_details.links = vec![
+            LinkSpec {
+                target: "home/chris/datasets/reference".to_string(),
+                path: path.to_string(),
+            },
+            LinkSpec {
+                target: "home/chris/datasets/other".to_string(),
+                path: "home/alice/other".to_string(),
+            },
+        ];
+        let manifest = Manifest {
+            user: HashMap::from([(alice.clone(), alice_details)]),
+            ..manifest
+        };
+        let tree = plan(manifest);
+        let link_path = "home/alice/reference.chrislink".to_string();
+        let exists = node_of(&tree.0, &Dependency::LinkFileUrl(link_path));
+        // both link files are in the same folder, which is found once
+        let folder = node_of(&tree.0, &Dependency::FolderUrl("home/alice".to_string()));
+        let auth_token = node_of(&tree.0, &Dependency::AuthToken(alice));
+        let parents = parents_of(&tree.0, exists);
+        assert!(parents.contains(&folder));
+        assert!(parents.contains(&auth_token));
+    }
+
+    #[rstest]
+    fn test_link_after_userfile(user: (Username, UserDetails), manifest: Manifest) {
+        let (alice, mut alice_details) = user;
+        alice_details.links = vec![LinkSpec {
+            target: "home/chris/datasets/reference".to_string(),
+            path: "home/alice/data/reference".to_string(),
+        }];
+        let userfile = |path: &str| UserFileSpec {
+            path: path.to_string(),
+            owner: Some(alice.clone()),
+            share: vec![],
+            content: UserFileContent::Text {
+                text: "hello".to_string(),
+            },
+        };
+        let manifest = Manifest {
+            user: HashMap::from([(alice.clone(), alice_details)]),
+            userfiles: vec![
+                userfile("home/alice/data/README.txt"),
+                userfile("home/alice/other/README.txt"),
+            ],
+            ..manifest
+        };
+        let tree = plan(manifest);
+        let folder = node_of(
+            &tree.0,
+            &Dependency::FolderUrl("home/alice/data".to_string()),
+        );
+        let parents = parents_of(&tree.0, folder);
+        let upload = |path: &str| node_of(&tree.0, &Dependency::UserFileContent(path.to_string()));
+        assert!(parents.contains(&upload("home/alice/data/README.txt")));
+        assert!(!parents.contains(&upload("home/alice/other/README.txt")));
+    }
+
+    #[rstest]
+    fn test_reset_password(user: (Username, UserDetails), mut manifest: Manifest) {
+        let (alice, alice_details) = user;
+        let chris = Username::from("chris");
+        manifest.global.reset_password = true;
+        manifest.user = HashMap::from([
+            (alice.clone(), alice_details),
+            (chris.clone(), details_of("chris", &[])),
+        ]);
+        let tree = plan(manifest);
+        let all_users = Group::new(CompactString::const_new(ALL_USERS));
+        assert_eq!(nodes_of(&tree.0, &Dependency::GroupUrl(all_users)).len(), 1);
+        assert_eq!(nodes_of(&tree.0, &Dependency::UserPassword(alice)).len(), 1);
+        assert_eq!(
+            nodes_of(&tree.0, &Dependency::UserPassword(chris)).len(),
+            0,
+            "the password of the admin user itself should not be reset"
+        );
+    }
+
+    #[rstest]
+    fn test_admin_after_admin_user(cube_url: CubeUrl) {
+        let mut tree = TreeBuilder::new();
+        let (chris, nodes) = add_steps_for_user(
+            &mut tree,
+            None,
+            None,
+            Username::from("chris"),
+            details_of("chris", &[]),
+            cube_url.clone(),
+        );
+        let users = HashMap::from([(chris, nodes)]);
+        let mut admin = AdminNode::new(
+            UserCredentials::basic_auth("chris", "chris1234"),
+            cube_url,
+            &users,
+        );
+        let id = admin.get(&mut tree);
+        assert_eq!(parents_of(&tree.0, id), vec![nodes.auth_token]);
+        assert_eq!(
+            provides_of(tree.0.node_weight(id).unwrap()).head,
+            Dependency::AdminToken
+        );
+    }
+
+    #[rstest]
+    fn test_exclusive_group(user: (Username, UserDetails), manifest: Manifest) {
+        let (alice, alice_details) = user;
+        let group = |name: &'static str| Group::new(CompactString::const_new(name));
+        let exclusive = GroupSpec { exclusive: true };
+        let manifest = Manifest {
+            user: HashMap::from([(alice, alice_details)]),
+            group: HashMap::from([
+                (group("pacs_users"), exclusive.clone()),
+                (group("empty"), exclusive),
+                (group("people"), GroupSpec::default()),
+            ]),
+            ..manifest
+        };
+        let tree = plan(manifest);
+        for name in ["pacs_users", "empty"] {
+            let exclusive_id = node_of(&tree.0, &Dependency::GroupExclusive(group(name)));
+            let group_id = node_of(&tree.0, &Dependency::GroupUrl(group(name)));
+            assert!(parents_of(&tree.0, exclusive_id).contains(&group_id));
+        }
+        for name in ["people", "mri.team"] {
+            assert!(nodes_of(&tree.0, &Dependency::GroupExclusive(group(name))).is_empty());
+        }
+    }
+
+    #[rstest]
+    fn test_plugin_after_compute_resource(cube_url: CubeUrl) {
+        let gpu = ComputeResourceName::new(CompactString::const_new("gpu"));
+        let spec = ComputeResourceSpec {
+            url: "http://pfcon.local:5005/api/v1/".to_string(),
+            username: "pfcon".to_string(),
+            password: "pfcon1234".to_string(),
+            description: "GPU compute resource".to_string(),
+            innetwork: None,
+            max_job_exec_seconds: None,
+        };
+        let mut tree = TreeBuilder::new();
+        let mut admin = AdminNode::new(
+            UserCredentials::basic_auth("chris", "chris1234"),
+            cube_url.clone(),
+            &HashMap::new(),
+        );
+        let compute_resources = HashMap::from([add_steps_for_compute_resource(
+            &mut tree,
+            &mut admin,
+            gpu.clone(),
+            spec,
+            false,
+            &cube_url,
+        )]);
+        let plugin_id = add_steps_for_plugin(
+            &mut tree,
+            &mut admin,
+            &compute_resources,
+            PluginEntry {
+                plugin: PluginSpec::new("pl-dcm2niix", None),
+                compute_resources: vec![gpu.clone()],
+                descriptor: None,
+            },
+            &cube_url,
+            &cube_url,
+        );
+        assert!(parents_of(&tree.0, plugin_id).contains(&compute_resources[&gpu]));
+    }
+
+    #[test]
+    fn test_merge_plugin_entries() {
+        let gpu = ComputeResourceName::new(CompactString::const_new("gpu"));
+        let cpu = ComputeResourceName::new(CompactString::const_new("cpu"));
+        let json = PluginDescriptor::Json {
+            json: r#"{"name":"pl-local"}"#.to_string(),
+        };
+        let plugins = vec![
+            PluginEntry {
+                plugin: PluginSpec::new("pl-local", None),
+                compute_resources: vec![gpu.clone()],
+                descriptor: None,
+            },
+            PluginSpec::new("pl-dircopy", None).into(),
+            PluginEntry {
+                plugin: PluginSpec::new("pl-local", None),
+                compute_resources: vec![cpu.clone(), gpu.clone()],
+                descriptor: Some(json.clone()),
+            },
+        ];
+        let expected = vec![
+            PluginEntry {
+                plugin: PluginSpec::new("pl-local", None),
+                compute_resources: vec![gpu, cpu],
+                descriptor: Some(json),
+            },
+            PluginSpec::new("pl-dircopy", None).into(),
+        ];
+        assert_eq!(merge_plugin_entries(plugins), expected);
+    }
+
+    #[rstest]
+    fn test_feed_shared_after_group(user: (Username, UserDetails), manifest: Manifest) {
+        let (alice, alice_details) = user;
+        let pacs_users = Group::new(CompactString::const_new("pacs_users"));
+        let feed = FeedSpec {
+            share: vec![PermissionTarget::Group(pacs_users.clone())],
+            ..feed_of(&alice, "Example feed")
+        };
+        let manifest = Manifest {
+            user: HashMap::from([(alice.clone(), alice_details)]),
+            feed: vec![feed],
+            ..manifest
+        };
+        let tree = plan(manifest);
+        let share_id = node_of(
+            &tree.0,
+            &Dependency::FeedShared(
+                alice,
+                "Example feed".to_string(),
+                PermissionTarget::Group(pacs_users.clone()),
+            ),
+        );
+        let group_id = node_of(&tree.0, &Dependency::GroupUrl(pacs_users));
+        assert!(parents_of(&tree.0, share_id).contains(&group_id));
+    }
+
+    #[rstest]
+    fn test_userfile_shared_after_group(user: (Username, UserDetails), manifest: Manifest) {
+        let (alice, alice_details) = user;
+        let pacs_users = Group::new(CompactString::const_new("pacs_users"));
+        let path = "home/alice/uploads/README.txt".to_string();
+        let userfile = UserFileSpec {
+            path: path.clone(),
+            owner: Some(alice.clone()),
+            share: vec![FileShare {
+                target: PermissionTarget::Group(pacs_users.clone()),
+                permission: FilePermission::Write,
+            }],
+            content: UserFileContent::Text {
+                text: "hello".to_string(),
+            },
+        };
+        let manifest = Manifest {
+            user: HashMap::from([(alice, alice_details)]),
+            userfiles: vec![userfile],
+            ..manifest
+        };
+        let tree = plan(manifest);
+        let target = PermissionTarget::Group(pacs_users.clone());
+        let share_id = node_of(
+            &tree.0,
+            &Dependency::FilePermissionUrl(path.clone(), target.clone()),
+        );
+        let parents = parents_of(&tree.0, share_id);
+        assert!(parents.contains(&node_of(&tree.0, &Dependency::GroupUrl(pacs_users))));
+        let content_id = node_of(&tree.0, &Dependency::UserFileContent(path.clone()));
+        assert!(parents.contains(&content_id));
+        let finalize_id = node_of(&tree.0, &Dependency::FileShared(path, target));
+        assert!(parents_of(&tree.0, finalize_id).contains(&share_id));
+    }
+
+    #[rstest]
+    fn test_pacs_files_by_series(mut manifest: Manifest) {
+        let dicom_file = |relative: &str, series_instance_uid: &str| DicomFile {
+            relative: relative.to_string(),
+            header: DicomHeader {
+                patient_id: "1449c1d".to_string(),
+                patient_name: None,
+                study_date: None,
+                study_instance_uid: "1.2.3".to_string(),
+                study_description: None,
+                series_instance_uid: series_instance_uid.to_string(),
+                series_description: None,
+                modality: None,
+            },
+        };
+        let identifier = "MINICHRISORTHANC".to_string();
+        manifest.pacs_files = vec![PacsFilesSpec {
+            pacs_identifier: identifier.clone(),
+            directory: "dicoms".into(),
+            files: Some(vec![
+                dicom_file("a/1.dcm", "1.2.3.1"),
+                dicom_file("b/1.dcm", "1.2.3.2"),
+                dicom_file("a/2.dcm", "1.2.3.1"),
+            ]),
+        }];
+        let tree = plan(manifest);
+        let series_id = node_of(
+            &tree.0,
+            &Dependency::PacsSeriesUrl(identifier, "1.2.3.1".to_string()),
+        );
+        let parents = parents_of(&tree.0, series_id);
+        for name in ["a/1.dcm", "a/2.dcm"] {
+            let path = format!("SERVICES/PACS/MINICHRISORTHANC/1449c1d/1.2.3/1.2.3.1/{name}");
+            assert!(parents.contains(&node_of(&tree.0, &Dependency::UserFileUrl(path))));
+        }
+        assert_eq!(
+            parents.len(),
+            3,
+            "series should need the admin token and its two files"
+        );
+    }
+
+    #[rstest]
+    fn test_pacs_series_after_pacs(mut manifest: Manifest) {
+        let identifier = "MINICHRISORTHANC".to_string();
+        manifest.pacs = HashMap::from([(identifier.clone(), PacsSpec::default())]);
+        manifest.pacs_files = vec![PacsFilesSpec {
+            pacs_identifier: identifier.clone(),
+            directory: "dicoms".into(),
+            files: Some(vec![DicomFile {
+                relative: "1.dcm".to_string(),
+                header: DicomHeader {
+                    patient_id: "1449c1d".to_string(),
+                    patient_name: None,
+                    study_date: None,
+                    study_instance_uid: "1.2.3".to_string(),
+                    study_description: None,
+                    series_instance_uid: "1.2.3.1".to_string(),
+                    series_description: None,
+                    modality: None,
+                },
+            }]),
+        }];
+        let tree = plan(manifest);
+        let series_id = node_of(
+            &tree.0,
+            &Dependency::PacsSeriesUrl(identifier.clone(), "1.2.3.1".to_string()),
+        );
+        let pacs_id = node_of(&tree.0, &Dependency::PacsUrl(identifier));
+        assert!(parents_of(&tree.0, series_id).contains(&pacs_id));
+    }
+
+    #[rstest]
+    fn test_pacs_query_after_pacs_users(user: (Username, UserDetails), manifest: Manifest) {
+        let (alice, mut alice_details) = user;
+        let pacs_users = Group::new(CompactString::const_new("pacs_users"));
+        let identifier = "MINICHRISORTHANC".to_string();
+        let title = "Brain MRIs".to_string();
+        alice_details.pacs_queries = vec![PacsQuerySpec {
+            title: title.clone(),
+            pacs: identifier.clone(),
+            query: HashMap::from([("Modality".to_string(), "MR".to_string())]),
+            description: None,
+        }];
+        let manifest = Manifest {
+            user: HashMap::from([(alice.clone(), alice_details)]),
+            pacs: HashMap::from([(identifier.clone(), PacsSpec::default())]),
+            ..manifest
+        };
+        let tree = plan(manifest);
+        let query_id = node_of(&tree.0, &Dependency::PacsQueryUrl(alice.clone(), title));
+        let parents = parents_of(&tree.0, query_id);
+        assert!(parents.contains(&node_of(&tree.0, &Dependency::PacsUrl(identifier))));
+        assert!(parents.contains(&node_of(
+            &tree.0,
+            &Dependency::GroupMember(pacs_users, alice)
+        )));
+        assert_eq!(parents.len(), 3);
+    }
+
+    #[rstest]
+    fn test_plugin_instance_after_previous(user: (Username, UserDetails), manifest: Manifest) {
+        let (alice, alice_details) = user;
+        let simpledsapp = PluginSpec::new("pl-simpledsapp", None);
+        let instance = |title: &str, previous: Option<&str>| PluginInstanceSpec {
+            plugin: simpledsapp.clone(),
+            title: Some(title.to_string()),
+            previous: previous.map(|s| s.to_string()),
+            args: HashMap::new(),
+        };
+        let feed = FeedSpec {
+            plugin_instances: vec![instance("first", None), instance("second", Some("first"))],
+            plugin: FsPluginSpec::Other {
+                plugin: PluginSpec::new("pl-mri10yr06mo01da_normal", None),
+                args: HashMap::new(),
+            },
+            ..feed_of(&alice, "Example feed")
+        };
+        let manifest = Manifest {
+            user: HashMap::from([(alice.clone(), alice_details)]),
+            feed: vec![feed],
+            ..manifest
+        };
+        let tree = plan(manifest);
+        let instance_id = |title: Option<&str>| {
+            Dependency::PluginInstanceId(
+                alice.clone(),
+                "Example feed".to_string(),
+                title.map(|s| s.to_string()),
+            )
+        };
+        let first = nodes_of(&tree.0, &instance_id(Some("first")));
+        let second = nodes_of(&tree.0, &instance_id(Some("second")));
+        assert_eq!(first.len(), 1);
+        assert_eq!(second.len(), 1);
+        assert_eq!(nodes_of(&tree.0, &instance_id(None)).len(), 1);
+        let parents = parents_of(&tree.0, second[0]);
+        assert!(parents.contains(&first[0]));
+        let valid = node_of(
+            &tree.0,
+            &Dependency::PluginArgsValid(
+                alice.clone(),
+                "Example feed".to_string(),
+                Some("second".to_string()),
+            ),
+        );
+        assert!(
+            parents.contains(&valid),
+            "arguments should be validated before creating the plugin instance"
+        );
+        let args = node_of(
+            &tree.0,
+            &Dependency::PluginInstanceArgs(
+                alice.clone(),
+                "Example feed".to_string(),
+                "second".to_string(),
+            ),
+        );
+        assert!(
+            parents_of(&tree.0, args).contains(&second[0]),
+            "arguments of the plugin instance should be compared after it is found"
+        );
+        assert_eq!(
+            nodes_of(&tree.0, &Dependency::PluginUrl(simpledsapp)).len(),
+            1,
+            "plugin should be registered once"
+        );
+    }
+
+    #[rstest]
+    fn test_workflow_after_workflow(user: (Username, UserDetails), manifest: Manifest) {
+        let (alice, alice_details) = user;
+        let piece = |title: &str, previous_index: Option<u32>| PipelinePiece {
+            title: title.to_string(),
+            plugin_name: "pl-simpledsapp".to_string(),
+            plugin_version: "2.1.0".to_string(),
+            previous_index,
+            plugin_parameter_defaults: vec![],
+        };
+        let pipeline = PipelineSpec {
+            owner: None,
+            definition: PipelineDefinition {
+                name: "Example pipeline".to_string(),
+                authors: String::new(),
+                category: String::new(),
+                description: String::new(),
+                locked: None,
+                plugin_tree: vec![piece("a", None), piece("b", Some(0))],
+            },
+        };
+        let workflow = |title: &str, previous: Option<&str>| WorkflowSpec {
+            pipeline: "Example pipeline".to_string(),
+            previous: previous.map(|s| s.to_string()),
+            title: Some(title.to_string()),
+        };
+        let feed = FeedSpec {
+            workflows: vec![workflow("first", None), workflow("second", Some("b"))],
+            ..feed_of(&alice, "Example feed")
+        };
+        let manifest = Manifest {
+            user: HashMap::from([(alice.clone(), alice_details)]),
+            pipeline: vec![pipeline],
+            feed: vec![feed],
+            ..manifest
+        };
+        let tree = plan(manifest);
+        let feed_name = "Example feed".to_string();
+        let workflow_url = |title: &str| {
+            Dependency::WorkflowUrl(alice.clone(), feed_name.clone(), title.to_string())
+        };
+        let after = |previous: Option<&str>| {
+            Dependency::WorkflowsAfter(
+                alice.clone(),
+                feed_name.clone(),
+                previous.map(|s| s.to_string()),
+            )
+        };
+        let first = node_of(&tree.0, &workflow_url("first"));
+        let second = node_of(&tree.0, &workflow_url("second"));
+        let pieces = node_of(
+            &tree.0,
+            &Dependency::PluginInstanceId(alice.clone(), feed_name.clone(), Some("a".to_string())),
+        );
+        assert!(parents_of(&tree.0, pieces).contains(&first));
+        assert!(provides_of(tree.0.node_weight(pieces).unwrap()).contains(
+            &Dependency::PluginInstanceId(alice.clone(), feed_name.clone(), Some("b".to_string()))
+        ));
+        let second_after = node_of(&tree.0, &after(Some("b")));
+        assert!(parents_of(&tree.0, second_after).contains(&pieces));
+        let parents = parents_of(&tree.0, second);
+        assert!(parents.contains(&pieces));
+        assert!(parents.contains(&second_after));
+        assert!(parents_of(&tree.0, first).contains(&node_of(&tree.0, &after(None))));
+    }
+
+    #[rstest]
+    #[case(false, 0)]
+    #[case(true, 1)]
+    fn test_wait_for_feed(
+        user: (Username, UserDetails),
+        mut manifest: Manifest,
+        #[case] wait: bool,
+        #[case] expected: usize,
+    ) {
+        let (alice, alice_details) = user;
+        manifest.global.wait = wait;
+        manifest.user = HashMap::from([(alice.clone(), alice_details)]);
+        manifest.feed = vec![feed_of(&alice, "Example feed")];
+        let tree = plan(manifest);
+        let finished = Dependency::PluginInstanceFinished(alice, "Example feed".to_string(), None);
+        assert_eq!(nodes_of(&tree.0, &finished).len(), expected);
+    }
+
+    #[rstest]
+    fn test_tag_created_once(user: (Username, UserDetails), manifest: Manifest) {
+        let (alice, alice_details) = user;
+        let feed = |name: &str| FeedSpec {
+            tags: vec!["demo".to_string()],
+            ..feed_of(&alice, name)
+        };
+        let manifest = Manifest {
+            user: HashMap::from([(alice.clone(), alice_details)]),
+            feed: vec![feed("First feed"), feed("Second feed")],
+            ..manifest
+        };
+        let tree = plan(manifest);
+        assert_eq!(
+            nodes_of(
+                &tree.0,
+                &Dependency::TagId(alice.clone(), "demo".to_string())
+            )
+            .len(),
+            1
+        );
+        for name in ["First feed", "Second feed"] {
+            let tagged =
+                Dependency::FeedTagged(alice.clone(), name.to_string(), "demo".to_string());
+            assert_eq!(nodes_of(&tree.0, &tagged).len(), 1);
+        }
+    }
+
+    #[rstest]
+    fn test_upload_concurrency(cube_url: CubeUrl) {
+        let mut tree = TreeBuilder::new();
+        let mut admin = AdminNode::new(
+            UserCredentials::basic_auth("chris", "chris1234"),
+            cube_url.clone(),
+            &HashMap::new(),
+        );
+        let mut uploads = UploadLanes::new(NonZeroUsize::new(2).unwrap());
+        let ids: Vec<_> = ["a.txt", "b.txt", "c.txt"]
+            .into_iter()
+            .map(|name| UserFileSpec {
+                path: format!("home/chris/{name}"),
+                owner: None,
+                share: vec![],
+                content: UserFileContent::Text {
+                    text: name.to_string(),
+                },
+            })
+            .flat_map(|userfile| {
+                add_steps_for_userfile(
+                    &mut tree,
+                    &mut admin,
+                    &HashMap::new(),
+                    &HashMap::new(),
+                    &mut uploads,
+                    userfile,
+                    &cube_url,
+                )
+                .unwrap()
+            })
+            .map(|(_, id)| id)
+            .collect();
+        let grandparents_of = |id| -> Vec<_> {
+            parents_of(&tree.0, id)
+                .into_iter()
+                .flat_map(|parent| parents_of(&tree.0, parent))
+                .collect()
+        };
+        assert!(!grandparents_of(ids[1]).contains(&ids[0]));
+        assert!(
+            grandparents_of(ids[2]).contains(&ids[0]),
+            "third upload should wait for the first upload"
+        );
+    }
+
+    #[rstest]
+    #[case("home/alice/uploads/a.txt", "home/alice/uploads", true)]
+    #[case("home/alice/uploads/a.txt", "home/alice/uploads/", true)]
+    #[case("home/alice/uploads", "home/alice/uploads", true)]
+    #[case("home/alice/uploads2/a.txt", "home/alice/uploads", false)]
+    #[case("home/bobby/a.txt", "home/alice", false)]
+    fn test_is_under(#[case] file: &str, #[case] dir: &str, #[case] expected: bool) {
+        assert_eq!(is_under(file, dir), expected)
+    }
+
+    /// Get the nodes of the steps whose target is `target`.
+    fn nodes_of(tree: &Dag<Rc<dyn PendingStep>>, target: &Dependency) -> Vec<NodeIndex> {
+        tree.node_indices()
+            .filter(|i| &target_of(tree.node_weight(*i).unwrap()) == target)
+            .collect()
+    }
+
+    /// Get the node of the only step whose target is `target`.
+    fn node_of(tree: &Dag<Rc<dyn PendingStep>>, target: &Dependency) -> NodeIndex {
+        let nodes = nodes_of(tree, target);
+        assert_eq!(nodes.len(), 1, "expected one step for {target:?}");
+        nodes[0]
+    }
+
+    /// Get the nodes which a node depends on.
+    fn parents_of(tree: &Dag<Rc<dyn PendingStep>>, id: NodeIndex) -> Vec<NodeIndex> {
+        tree.neighbors_directed(id, petgraph::Direction::Incoming)
+            .collect()
+    }
+
+    /// Details of a user whose password and email are derived from their username.
+    fn details_of(username: &str, groups: &[&'static str]) -> UserDetails {
+        UserDetails {
+            password: format!("{username}1234"),
+            email: format!("{username}@example.org"),
+            groups: groups
+                .iter()
+                .map(|s| Group::new(CompactString::const_new(*s)))
+                .collect(),
+            admin: false,
+            links: vec![],
+            pacs_queries: vec![],
+        }
+    }
+
+    /// A feed created by pl-dircopy without anything else.
+    fn feed_of(owner: &Username, name: &str) -> FeedSpec {
+        FeedSpec {
+            name: name.to_string(),
+            owner: Some(owner.clone()),
+            share: vec![],
+            public: None,
+            note: None,
+            tags: vec![],
+            comments: vec![],
+            plugin_instances: vec![],
+            workflows: vec![],
+            plugin: FsPluginSpec::Dircopy {
+                path: format!("home/{owner}/uploads"),
+            },
+        }
+    }
+
     #[fixture]
     fn user() -> (Username, UserDetails) {
         let username = Username::new(CompactString::const_new("alice"));
         let details = UserDetails {
-            password: "alice1234".to_string(),
             email: "alice.test@example.org".to_string(),
-            groups: ["people", "pacs_users", "mri.team"]
-                .map(|s| s.to_string())
-                .into_iter()
-                .collect(),
+            ..details_of("alice", &["people", "pacs_users", "mri.team"])
         };
         (username, details)
     }
 
+    /// A manifest which has nothing but its global configuration.
+    #[fixture]
+    fn manifest(cube_url: CubeUrl) -> Manifest {
+        Manifest {
+            global: Global {
+                cube: cube_url.clone(),
+                admin: UserCredentials::basic_auth("chris", "chris1234"),
+                email_domain: CompactString::const_new("example.org"),
+                public_cube: cube_url,
+                upload_concurrency: NonZeroUsize::new(4).unwrap(),
+                wait: false,
+                wait_timeout: NonZeroU64::new(3600).unwrap(),
+                reset_password: false,
+            },
+            user: HashMap::new(),
+            group: HashMap::new(),
+            plugins: vec![],
+            compute_resource: HashMap::new(),
+            userfiles: vec![],
+            pacs: HashMap::new(),
+            pacs_files: vec![],
+            pipeline: vec![],
+            feed: vec![],
+        }
+    }
+
     #[fixture]
     fn cube_url() -> CubeUrl {
         CubeUrl::try_new("https://example.com:12345/api/v1/").unwrap()
@@ -1,11 +1,21 @@
 use reqwest::{Request, header, header::HeaderValue};
 use serde::Serialize;
+use std::collections::HashSet;
 
 /// Extension trait for [reqwest::Request].
 pub(crate) trait RequestBuilder {
     /// Add a JSON body.
     fn json<T: Serialize + ?Sized>(self, body: &T) -> serde_json::Result<Request>;
 
+    /// Add a `multipart/form-data` body consisting of text fields and one file.
+    fn multipart_file(
+        self,
+        fields: &[(&str, &str)],
+        file_field: &str,
+        filename: &str,
+        content: &[u8],
+    ) -> Request;
+
     /// Expect JSON response.
     fn accept_json(self) -> Request;
 
@@ -25,6 +35,41 @@ impl RequestBuilder for reqwest::Request {
         Ok(self)
     }
 
+    fn multipart_file(
+        mut self,
+        fields: &[(&str, &str)],
+        file_field: &str,
+        filename: &str,
+        content: &[u8],
+    ) -> Self {
+        let boundary = boundary_for(content);
+        let filename = escape_quoted(filename);
+        let mut body = Vec::with_capacity(content.len() + 512);
+        for (name, value) in fields {
+            body.extend_from_slice(
+                format!(
+                    "--{boundary}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n"
+                )
+                .as_bytes(),
+            );
+        }
+        body.extend_from_slice(
+            format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"{file_field}\"; filename=\"{filename}\"\r\nContent-Type: application/octet-stream\r\n\r\n"
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(content);
+        body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+        let content_type = format!("multipart/form-data; boundary={boundary}");
+        let _ = self.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_str(&content_type).unwrap(),
+        );
+        let _ = self.body_mut().insert(body.into());
+        self
+    }
+
     fn accept_json(mut self) -> Self {
         let _ = self.headers_mut().insert(header::ACCEPT, APPLICATION_JSON);
         self
@@ -38,3 +83,60 @@ impl RequestBuilder for reqwest::Request {
         self
     }
 }
+
+const BOUNDARY_PREFIX: &str = "chrisomatic-boundary-";
+
+/// Choose a multipart boundary which does not appear in the content.
+///
+/// The content is scanned once for the prefix of all candidate boundaries.
+fn boundary_for(content: &[u8]) -> String {
+    let prefix = BOUNDARY_PREFIX.as_bytes();
+    let taken: HashSet<&[u8]> = content
+        .windows(prefix.len())
+        .enumerate()
+        .filter(|(_, window)| *window == prefix)
+        .filter_map(|(i, _)| content.get(i + prefix.len()..i + prefix.len() + 8))
+        .collect();
+    (0u32..)
+        .map(|i| format!("{i:08x}"))
+        .find(|suffix| !taken.contains(suffix.as_bytes()))
+        .map(|suffix| format!("{BOUNDARY_PREFIX}{suffix}"))
+        .unwrap()
+}
+
+/// Escape a value to be quoted in a `Content-Disposition` header the way
+/// browsers do for `multipart/form-data`.
+fn escape_quoted(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[rstest]
+    #[case(b"hello", "chrisomatic-boundary-00000000")]
+    #[case(
+        b"--chrisomatic-boundary-00000000\r\n",
+        "chrisomatic-boundary-00000001"
+    )]
+    #[case(
+        b"chrisomatic-boundary-00000001 chrisomatic-boundary-00000000",
+        "chrisomatic-boundary-00000002"
+    )]
+    fn test_boundary_for(#[case] content: &[u8], #[case] expected: &str) {
+        assert_eq!(boundary_for(content), expected)
+    }
+
+    #[rstest]
+    #[case("a.txt", "a.txt")]
+    #[case("say \"hi\".txt", "say %22hi%22.txt")]
+    #[case("a\r\nb.txt", "a%0D%0Ab.txt")]
+    fn test_escape_quoted(#[case] value: &str, #[case] expected: &str) {
+        assert_eq!(escape_quoted(value), expected)
+    }
+}
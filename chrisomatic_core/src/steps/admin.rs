@@ -0,0 +1,124 @@
+use crate::request_builder::RequestBuilder;
+use chris_oag::models;
+use chrisomatic_spec::*;
+use chrisomatic_step::*;
+use chrisomatic_step_macro::AsRefPendingStep;
+use nonempty::{NonEmpty, nonempty};
+use reqwest::{Method, Request, StatusCode, Url};
+use std::rc::Rc;
+
+/// A [PendingStep] to authenticate as the admin user. See [AdminGetAuthTokenStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct AdminGetAuthToken {
+    pub(crate) credentials: UserCredentials,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for AdminGetAuthToken {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let auth_token = Dependency::AuthToken(self.credentials.username.clone());
+        // the admin user is also specified as a user, so its token is already known
+        let secret = if map.contains_key(&auth_token) {
+            AdminSecret::Token(map.get(auth_token)?)
+        } else {
+            match &self.credentials.secret {
+                PasswordOrToken::Password(password) => AdminSecret::Password(password.clone()),
+                PasswordOrToken::Token(token) => {
+                    AdminSecret::Token(Rc::new(format!("Token {token}")))
+                }
+            }
+        };
+        let step = AdminGetAuthTokenStep {
+            admin: self.clone(),
+            secret,
+        };
+        ok_step(step)
+    }
+}
+
+/// How to authenticate as the admin user.
+enum AdminSecret {
+    /// Obtain a token using the password.
+    Password(String),
+    /// Check that the token (including its `Token ` prefix) is an admin user's.
+    Token(Rc<String>),
+}
+
+/// A [Step] to obtain the [Dependency::AdminToken].
+///
+/// - If given a password, the token is obtained from `auth-token/`.
+/// - If given a token, it is checked by making a request to the admin API.
+///
+/// Wrong credentials are reported as [Check::Invalid].
+pub(crate) struct AdminGetAuthTokenStep {
+    admin: AdminGetAuthToken,
+    secret: AdminSecret,
+}
+
+impl Step for AdminGetAuthTokenStep {
+    fn search(&self) -> reqwest::Request {
+        match &self.secret {
+            AdminSecret::Password(password) => {
+                let url = self.admin.url.to_url().join("auth-token/").unwrap();
+                let body = models::AuthTokenRequest {
+                    username: self.admin.credentials.username.to_string(),
+                    password: password.to_string(),
+                };
+                Request::new(Method::POST, url)
+                    .json(&body)
+                    .unwrap()
+                    .accept_json()
+            }
+            AdminSecret::Token(token) => Request::new(Method::GET, admin_api_url(&self.admin.url))
+                .auth_token(token.as_str())
+                .accept_json(),
+        }
+    }
+
+    fn check_status(&self, status: reqwest::StatusCode) -> StatusCheck {
+        let username = &self.admin.credentials.username;
+        match (&self.secret, status) {
+            _ if status.is_success() => StatusCheck::Exists,
+            (AdminSecret::Password(_), StatusCode::BAD_REQUEST) => {
+                StatusCheck::Invalid(format!("wrong password for admin user \"{username}\""))
+            }
+            (AdminSecret::Token(_), StatusCode::UNAUTHORIZED) => {
+                StatusCheck::Invalid(format!("invalid token for admin user \"{username}\""))
+            }
+            (AdminSecret::Token(_), StatusCode::FORBIDDEN) => {
+                StatusCheck::Invalid(format!("user \"{username}\" is not an admin user"))
+            }
+            _ => StatusCheck::Error,
+        }
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let token = match &self.secret {
+            AdminSecret::Password(_) => {
+                let body: models::AuthToken = serde_json::from_slice(&body)?;
+                format!("Token {}", body.token)
+            }
+            AdminSecret::Token(token) => token.to_string(),
+        };
+        let outputs = vec![
+            (Dependency::AdminToken, token.clone()),
+            (
+                Dependency::AuthToken(self.admin.credentials.username.clone()),
+                token,
+            ),
+        ];
+        Ok(Check::Exists(outputs))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![
+            Dependency::AdminToken,
+            Dependency::AuthToken(self.admin.credentials.username.clone())
+        ]
+    }
+}
+
+/// Get the URL of the admin API, which is a sibling of the CUBE API.
+pub(crate) fn admin_api_url(url: &CubeUrl) -> Url {
+    url.to_url().join("../../chris-admin/api/v1/").unwrap()
+}
@@ -0,0 +1,238 @@
+use crate::extra_models::CommentRequest;
+use crate::request_builder::RequestBuilder;
+use chris_oag::models;
+use chrisomatic_spec::*;
+use chrisomatic_step::*;
+use chrisomatic_step_macro::AsRefPendingStep;
+use nonempty::{NonEmpty, nonempty};
+use reqwest::{Method, Request, Url};
+use std::rc::Rc;
+
+/// A [PendingStep] to make sure that a comment on a feed exists.
+/// See [FeedCommentExistsStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct FeedCommentExists {
+    pub(crate) owner: Username,
+    pub(crate) feed: String,
+    pub(crate) comment: CommentSpec,
+}
+
+impl PendingStep for FeedCommentExists {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let (owner, feed) = (self.owner.clone(), self.feed.clone());
+        let step = FeedCommentExistsStep {
+            comment: self.clone(),
+            comments_url: map.get(Dependency::FeedCommentsUrl(owner, feed))?,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to search for a comment on a feed by its title. If not found, the
+/// comment is created by the feed's owner.
+pub(crate) struct FeedCommentExistsStep {
+    comment: FeedCommentExists,
+    comments_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for FeedCommentExistsStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = Url::parse(&self.comments_url).unwrap();
+        url.query_pairs_mut().append_pair("limit", "100");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: models::PaginatedCommentList = serde_json::from_slice(&body)?;
+        let spec = &self.comment.comment;
+        let check = data
+            .results
+            .into_iter()
+            .find(|comment| comment.title == spec.title)
+            .map(|comment| {
+                let mut entries = vec![(self.target(), comment.url)];
+                if comment.content == spec.content {
+                    entries.push(content_entry(&self.comment));
+                }
+                Check::Exists(entries)
+            })
+            .unwrap_or(Check::DoesNotExist);
+        Ok(check)
+    }
+
+    fn create(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(CreateCommentRequest {
+            target: self.target(),
+            comment: self.comment.clone(),
+            comments_url: Rc::clone(&self.comments_url),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl FeedCommentExistsStep {
+    fn target(&self) -> Dependency {
+        let c = &self.comment;
+        Dependency::FeedCommentUrl(c.owner.clone(), c.feed.clone(), c.comment.title.clone())
+    }
+}
+
+/// Create a comment on a feed.
+pub(crate) struct CreateCommentRequest {
+    target: Dependency,
+    comment: FeedCommentExists,
+    comments_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for CreateCommentRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = Url::parse(&self.comments_url).unwrap();
+        let spec = &self.comment.comment;
+        let body = CommentRequest {
+            title: &spec.title,
+            content: &spec.content,
+        };
+        Request::new(Method::POST, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let comment: models::Comment = serde_json::from_slice(&body)?;
+        // the comment was just created, so its content is known to be correct.
+        Ok(vec![
+            (self.target.clone(), comment.url),
+            content_entry(&self.comment),
+        ])
+    }
+}
+
+/// A [PendingStep] to make sure that the content of a comment on a feed is
+/// the same as what is specified. See [FeedCommentContentFinalizeStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct FeedCommentContentFinalize {
+    pub(crate) owner: Username,
+    pub(crate) feed: String,
+    pub(crate) comment: CommentSpec,
+}
+
+impl PendingStep for FeedCommentContentFinalize {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let (owner, feed, title) = (
+            self.owner.clone(),
+            self.feed.clone(),
+            self.comment.title.clone(),
+        );
+        if map.contains_key(&Dependency::FeedCommentContent(
+            owner.clone(),
+            feed.clone(),
+            title.clone(),
+        )) {
+            return Ok(None);
+        }
+        let step = FeedCommentContentFinalizeStep {
+            comment: self.clone(),
+            comment_url: map.get(Dependency::FeedCommentUrl(owner, feed, title))?,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to modify the content of an existing comment.
+pub(crate) struct FeedCommentContentFinalizeStep {
+    comment: FeedCommentContentFinalize,
+    comment_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for FeedCommentContentFinalizeStep {
+    fn search(&self) -> reqwest::Request {
+        let url = Url::parse(&self.comment_url).unwrap();
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let comment: models::Comment = serde_json::from_slice(&body)?;
+        let check = if comment.content == self.comment.comment.content {
+            Check::Exists(vec![(self.target(), comment.url)])
+        } else {
+            Check::NeedsModification
+        };
+        Ok(check)
+    }
+
+    fn modify(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(ModifyCommentRequest {
+            target: self.target(),
+            comment: self.comment.comment.clone(),
+            comment_url: Rc::clone(&self.comment_url),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl FeedCommentContentFinalizeStep {
+    fn target(&self) -> Dependency {
+        let c = &self.comment;
+        Dependency::FeedCommentContent(c.owner.clone(), c.feed.clone(), c.comment.title.clone())
+    }
+}
+
+/// Modify the content of a comment.
+pub(crate) struct ModifyCommentRequest {
+    target: Dependency,
+    comment: CommentSpec,
+    comment_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for ModifyCommentRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = Url::parse(&self.comment_url).unwrap();
+        let body = CommentRequest {
+            title: &self.comment.title,
+            content: &self.comment.content,
+        };
+        Request::new(Method::PUT, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let comment: models::Comment = serde_json::from_slice(&body)?;
+        Ok(vec![(self.target.clone(), comment.url)])
+    }
+}
+
+fn content_entry(comment: &FeedCommentExists) -> Entry {
+    let key = Dependency::FeedCommentContent(
+        comment.owner.clone(),
+        comment.feed.clone(),
+        comment.comment.title.clone(),
+    );
+    (key, comment.comment.content.clone())
+}
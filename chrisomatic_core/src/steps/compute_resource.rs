@@ -0,0 +1,250 @@
+use crate::{
+    extra_models::{ComputeResourceAdmin, ComputeResourceAdminRequest},
+    request_builder::RequestBuilder,
+};
+use chris_oag::models;
+use chrisomatic_spec::*;
+use chrisomatic_step::*;
+use chrisomatic_step_macro::AsRefPendingStep;
+use nonempty::{NonEmpty, nonempty};
+use reqwest::{Method, Request, Url};
+use std::rc::Rc;
+
+use super::admin_api_url;
+
+/// A [PendingStep] to make sure that a compute resource exists. See [ComputeResourceExistsStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct ComputeResourceExists {
+    pub(crate) name: ComputeResourceName,
+    pub(crate) spec: Rc<ComputeResourceSpec>,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for ComputeResourceExists {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let step = ComputeResourceExistsStep {
+            resource: self.clone(),
+            auth_token: map.get(Dependency::AdminToken)?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to search for a compute resource by name, creating it if necessary.
+/// The name filter of the search matches substrings, so every page of results
+/// is read to find the compute resource with exactly the same name.
+/// Produces [Dependency::ComputeResourceUrl].
+pub(crate) struct ComputeResourceExistsStep {
+    resource: ComputeResourceExists,
+    auth_token: Rc<String>,
+}
+
+impl Step for ComputeResourceExistsStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = self
+            .resource
+            .url
+            .to_url()
+            .join("computeresources/search/")
+            .unwrap();
+        url.query_pairs_mut()
+            .append_pair("name", self.resource.name.as_str())
+            .append_pair("limit", "100");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: models::PaginatedComputeResourceList = serde_json::from_slice(&body)?;
+        let check = data
+            .results
+            .into_iter()
+            .find(|resource| resource.name == self.resource.name.as_str())
+            .map(|resource| {
+                let url = admin_url_of(&self.resource.url, resource.id);
+                Check::Exists(vec![(
+                    Dependency::ComputeResourceUrl(self.resource.name.clone()),
+                    url.to_string(),
+                )])
+            })
+            .unwrap_or(Check::DoesNotExist);
+        Ok(check)
+    }
+
+    fn create(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(CreateComputeResourceRequest {
+            resource: self.resource.clone(),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![Dependency::ComputeResourceUrl(self.resource.name.clone())]
+    }
+}
+
+pub(crate) struct CreateComputeResourceRequest {
+    resource: ComputeResourceExists,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for CreateComputeResourceRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = admin_api_url(&self.resource.url)
+            .join("computeresources/")
+            .unwrap();
+        let body = admin_request_body(&self.resource.name, &self.resource.spec);
+        Request::new(Method::POST, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let resource: ComputeResourceAdmin = serde_json::from_slice(&body)?;
+        let url = admin_url_of(&self.resource.url, resource.id);
+        Ok(vec![(
+            Dependency::ComputeResourceUrl(self.resource.name.clone()),
+            url.to_string(),
+        )])
+    }
+}
+
+/// A [PendingStep] to sync a compute resource on the backend with what is specified.
+/// See [ComputeResourceFinalizeStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct ComputeResourceFinalize {
+    pub(crate) name: ComputeResourceName,
+    pub(crate) spec: Rc<ComputeResourceSpec>,
+    /// Whether to set the password even if nothing else needs modification.
+    pub(crate) reset_password: bool,
+}
+
+impl PendingStep for ComputeResourceFinalize {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let step = ComputeResourceFinalizeStep {
+            name: self.name.clone(),
+            spec: Rc::clone(&self.spec),
+            reset_password: self.reset_password,
+            resource_url: map.get(Dependency::ComputeResourceUrl(self.name.clone()))?,
+            auth_token: map.get(Dependency::AdminToken)?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to sync a compute resource on the backend with what is specified.
+///
+/// The compute resource's password cannot be read back from the API, so
+/// a changed password is only applied when some other field was changed too,
+/// or on every run if `reset_password` is set.
+pub(crate) struct ComputeResourceFinalizeStep {
+    name: ComputeResourceName,
+    spec: Rc<ComputeResourceSpec>,
+    reset_password: bool,
+    resource_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for ComputeResourceFinalizeStep {
+    fn search(&self) -> reqwest::Request {
+        let url = Url::parse(&self.resource_url).unwrap();
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let current: ComputeResourceAdmin = serde_json::from_slice(&body)?;
+        let spec = &self.spec;
+        let is_same = current.compute_url == spec.url
+            && current.description == spec.description
+            && spec
+                .innetwork
+                .is_none_or(|v| current.compute_innetwork == Some(v))
+            && spec
+                .max_job_exec_seconds
+                .is_none_or(|v| current.max_job_exec_seconds == Some(v));
+        if is_same && !self.reset_password {
+            Ok(Check::Exists(vec![self.entry()]))
+        } else {
+            Ok(Check::NeedsModification)
+        }
+    }
+
+    fn modify(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(ModifyComputeResourceRequest {
+            name: self.name.clone(),
+            spec: Rc::clone(&self.spec),
+            resource_url: Rc::clone(&self.resource_url),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![Dependency::ComputeResourceUrl(self.name.clone())]
+    }
+}
+
+impl ComputeResourceFinalizeStep {
+    fn entry(&self) -> (Dependency, String) {
+        (
+            Dependency::ComputeResourceUrl(self.name.clone()),
+            self.resource_url.to_string(),
+        )
+    }
+}
+
+pub(crate) struct ModifyComputeResourceRequest {
+    name: ComputeResourceName,
+    spec: Rc<ComputeResourceSpec>,
+    resource_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for ModifyComputeResourceRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = Url::parse(&self.resource_url).unwrap();
+        let body = admin_request_body(&self.name, &self.spec);
+        Request::new(Method::PUT, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, _body: bytes::Bytes) -> serde_json::Result<Entries> {
+        Ok(vec![(
+            Dependency::ComputeResourceUrl(self.name.clone()),
+            self.resource_url.to_string(),
+        )])
+    }
+}
+
+fn admin_request_body(
+    name: &ComputeResourceName,
+    spec: &ComputeResourceSpec,
+) -> ComputeResourceAdminRequest {
+    ComputeResourceAdminRequest {
+        name: name.to_string(),
+        compute_url: spec.url.clone(),
+        compute_user: spec.username.clone(),
+        compute_password: spec.password.clone(),
+        description: spec.description.clone(),
+        compute_innetwork: spec.innetwork,
+        max_job_exec_seconds: spec.max_job_exec_seconds,
+    }
+}
+
+/// Get the URL of a compute resource in the admin API.
+fn admin_url_of(url: &CubeUrl, id: impl std::fmt::Display) -> Url {
+    admin_api_url(url)
+        .join(&format!("computeresources/{id}/"))
+        .unwrap()
+}
@@ -1,62 +1,569 @@
-use crate::step::{Check, Dependency, RequestBuilder, RequestResult, Step};
-use crate::{spec::*, types::*};
+use crate::{
+    extra_models::{FeedPublicRequest, NotePatchRequest, Publicity},
+    request_builder::RequestBuilder,
+};
 use chris_oag::models;
+use chrisomatic_spec::*;
+use chrisomatic_step::*;
+use chrisomatic_step_macro::AsRefPendingStep;
+use nonempty::{NonEmpty, nonempty};
+use reqwest::{Method, Request, Url};
+use std::rc::Rc;
 
-use super::auth_token::AuthTokenFor;
+/// Plugin parameter values, as they appear in the body of a request to create a plugin instance.
+pub(crate) type PluginParams = serde_json::Map<String, serde_json::Value>;
 
-// pub(crate) struct UserfileShareWithUser<'a> {
-//     path: &'a str,
-//     owner: &'a Username,
-//     user: &'a Username,
-// }
+/// A [PendingStep] to make sure that a feed exists. See [FeedExistsStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct FeedExists {
+    pub(crate) owner: Username,
+    pub(crate) name: String,
+    /// The _fs_-type plugin which creates the feed.
+    pub(crate) plugin: PluginSpec,
+    pub(crate) params: Rc<PluginParams>,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for FeedExists {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        map.get(Dependency::PluginArgsValid(
+            self.owner.clone(),
+            self.name.clone(),
+            None,
+        ))?;
+        let step = FeedExistsStep {
+            feed: self.clone(),
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+            instances_url: map.get(Dependency::PluginInstancesUrl(self.plugin.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to search for a feed by name and owner. If not found, a feed is
+/// created by running an _fs_-type plugin (e.g. `pl-dircopy`).
+pub(crate) struct FeedExistsStep {
+    feed: FeedExists,
+    auth_token: Rc<String>,
+    instances_url: Rc<String>,
+}
+
+impl Step for FeedExistsStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = self.feed.url.to_url().join("feeds/search/").unwrap();
+        url.query_pairs_mut()
+            .append_pair("name_exact", &self.feed.name)
+            .append_pair("owner_username", self.feed.owner.as_str())
+            .append_pair("limit", "1");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: models::PaginatedFeedList = serde_json::from_slice(&body)?;
+        let check = data
+            .results
+            .into_iter()
+            .find(|feed| {
+                feed.name == self.feed.name && feed.owner_username == self.feed.owner.as_str()
+            })
+            .map(|feed| Check::Exists(vec![(self.target(), feed.url)]))
+            .unwrap_or(Check::DoesNotExist);
+        Ok(check)
+    }
+
+    fn create(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(CreateFeedRequest {
+            target: self.target(),
+            name: self.feed.name.clone(),
+            params: Rc::clone(&self.feed.params),
+            instances_url: Rc::clone(&self.instances_url),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
 
-/// An [Step] to share a [models::Feed] with a [models::User].
-#[derive(Debug, Copy, Clone)]
-pub(crate) struct FeedShareWithUser<'a> {
-    feed: &'a FeedSpec,
-    user: &'a Username,
+impl FeedExistsStep {
+    fn target(&self) -> Dependency {
+        Dependency::FeedUrl(self.feed.owner.clone(), self.feed.name.clone())
+    }
+}
+
+/// Create a plugin instance of an _fs_-type plugin, which creates a new feed.
+/// The feed's name will be the title of the plugin instance.
+pub(crate) struct CreateFeedRequest {
+    target: Dependency,
+    name: String,
+    params: Rc<PluginParams>,
+    instances_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for CreateFeedRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = Url::parse(&self.instances_url).unwrap();
+        let mut body = (*self.params).clone();
+        body.insert("title".to_string(), self.name.clone().into());
+        Request::new(Method::POST, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let plugin_instance: models::PluginInstance = serde_json::from_slice(&body)?;
+        Ok(vec![(self.target.clone(), plugin_instance.feed)])
+    }
 }
 
-impl Dependency for FeedShareWithUser<'_> {
-    type Value = models::FeedUserPermission;
+/// A [PendingStep] to make sure that [DependencyMap] contains details of a feed.
+/// See [FeedGetDetailsStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct FeedGetDetails {
+    pub(crate) owner: Username,
+    pub(crate) name: String,
 }
 
-/// A [Dependency] on a [models::Feed].
-#[derive(Debug, Copy, Clone)]
-pub(crate) struct FeedFor<'a>(&'a FeedSpec);
+impl PendingStep for FeedGetDetails {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let feed_url = map.get(Dependency::FeedUrl(self.owner.clone(), self.name.clone()))?;
+        let step = FeedGetDetailsStep {
+            feed: self.clone(),
+            feed_url,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to get the ID of a feed and the URLs of its permissions, note,
+/// taggings, and comments.
+pub(crate) struct FeedGetDetailsStep {
+    feed: FeedGetDetails,
+    feed_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for FeedGetDetailsStep {
+    fn search(&self) -> reqwest::Request {
+        let url = Url::parse(&self.feed_url).unwrap();
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let feed: models::Feed = serde_json::from_slice(&body)?;
+        let (owner, name) = (&self.feed.owner, &self.feed.name);
+        let outputs = vec![
+            (Dependency::FeedUrl(owner.clone(), name.clone()), feed.url),
+            (
+                Dependency::FeedId(owner.clone(), name.clone()),
+                feed.id.to_string(),
+            ),
+            (
+                Dependency::FeedUserPermissionsUrl(owner.clone(), name.clone()),
+                feed.user_permissions,
+            ),
+            (
+                Dependency::FeedGroupPermissionsUrl(owner.clone(), name.clone()),
+                feed.group_permissions,
+            ),
+            (
+                Dependency::FeedNoteUrl(owner.clone(), name.clone()),
+                feed.note,
+            ),
+            (
+                Dependency::FeedTaggingsUrl(owner.clone(), name.clone()),
+                feed.taggings,
+            ),
+            (
+                Dependency::FeedCommentsUrl(owner.clone(), name.clone()),
+                feed.comments,
+            ),
+        ];
+        Ok(Check::Exists(outputs))
+    }
 
-impl Dependency for FeedFor<'_> {
-    type Value = models::Feed;
+    fn provides(&self) -> NonEmpty<Dependency> {
+        let (owner, name) = (&self.feed.owner, &self.feed.name);
+        nonempty![
+            Dependency::FeedUrl(owner.clone(), name.clone()),
+            Dependency::FeedId(owner.clone(), name.clone()),
+            Dependency::FeedUserPermissionsUrl(owner.clone(), name.clone()),
+            Dependency::FeedGroupPermissionsUrl(owner.clone(), name.clone()),
+            Dependency::FeedNoteUrl(owner.clone(), name.clone()),
+            Dependency::FeedTaggingsUrl(owner.clone(), name.clone()),
+            Dependency::FeedCommentsUrl(owner.clone(), name.clone()),
+        ]
+    }
 }
 
-impl<'a> Step for FeedShareWithUser<'a> {
-    type Dep = (AuthTokenFor<'a>, FeedFor<'a>);
-    type Res = models::PaginatedFeedUserPermissionList;
-    type Out = Self;
+/// A [PendingStep] to make sure that a feed is public or not.
+/// See [FeedPublicFinalizeStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct FeedPublicFinalize {
+    pub(crate) owner: Username,
+    pub(crate) name: String,
+    pub(crate) public: bool,
+}
 
-    fn query(&self, (token, feed): &<Self::Dep as Dependency>::Value) -> RequestResult {
-        let uri = format!("{}?username={}&limit=1", feed.user_permissions, self.user);
-        RequestBuilder::new(uri).auth(token).get()
+impl PendingStep for FeedPublicFinalize {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let (owner, name) = (self.owner.clone(), self.name.clone());
+        let step = FeedPublicFinalizeStep {
+            public: self.clone(),
+            feed_url: map.get(Dependency::FeedUrl(owner, name))?,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
     }
+}
+
+/// A [Step] to check whether a feed is public, modifying the feed if it
+/// is not as specified.
+pub(crate) struct FeedPublicFinalizeStep {
+    public: FeedPublicFinalize,
+    feed_url: Rc<String>,
+    auth_token: Rc<String>,
+}
 
-    fn dependencies(&self) -> Self::Dep {
-        (AuthTokenFor(self.user), FeedFor(self.feed))
+impl Step for FeedPublicFinalizeStep {
+    fn search(&self) -> reqwest::Request {
+        let url = Url::parse(&self.feed_url).unwrap();
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
     }
 
-    fn check(&self, body: Self::Res) -> Check<Self::Out> {
-        if let Some(permission) = body.results.into_iter().next() {
-            Check::Exists(self.clone(), permission)
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let feed: Publicity = serde_json::from_slice(&body)?;
+        let check = if feed.public == self.public.public {
+            Check::Exists(vec![(self.target(), feed.url)])
         } else {
-            Check::DoesNotExist
+            Check::NeedsModification
+        };
+        Ok(check)
+    }
+
+    fn modify(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(ModifyFeedPublicRequest {
+            target: self.target(),
+            public: self.public.clone(),
+            feed_url: Rc::clone(&self.feed_url),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl FeedPublicFinalizeStep {
+    fn target(&self) -> Dependency {
+        Dependency::FeedPublic(self.public.owner.clone(), self.public.name.clone())
+    }
+}
+
+/// Make a feed public or not.
+pub(crate) struct ModifyFeedPublicRequest {
+    target: Dependency,
+    public: FeedPublicFinalize,
+    feed_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for ModifyFeedPublicRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = Url::parse(&self.feed_url).unwrap();
+        let body = FeedPublicRequest {
+            name: &self.public.name,
+            public: self.public.public,
+        };
+        Request::new(Method::PUT, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let feed: Publicity = serde_json::from_slice(&body)?;
+        Ok(vec![(self.target.clone(), feed.url)])
+    }
+}
+
+/// A [PendingStep] to make sure that the note of a feed is as specified.
+/// See [FeedNoteFinalizeStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct FeedNoteFinalize {
+    pub(crate) owner: Username,
+    pub(crate) name: String,
+    pub(crate) content: String,
+}
+
+impl PendingStep for FeedNoteFinalize {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let (owner, name) = (self.owner.clone(), self.name.clone());
+        let step = FeedNoteFinalizeStep {
+            note: self.clone(),
+            note_url: map.get(Dependency::FeedNoteUrl(owner, name))?,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to compare the content of a feed's note with what is specified,
+/// modifying the note if different. Every feed has a note, so it is never created.
+pub(crate) struct FeedNoteFinalizeStep {
+    note: FeedNoteFinalize,
+    note_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for FeedNoteFinalizeStep {
+    fn search(&self) -> reqwest::Request {
+        let url = Url::parse(&self.note_url).unwrap();
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let note: models::Note = serde_json::from_slice(&body)?;
+        let check = if note.content == self.note.content {
+            Check::Exists(vec![(self.target(), note.url)])
+        } else {
+            Check::NeedsModification
+        };
+        Ok(check)
+    }
+
+    fn modify(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(ModifyNoteRequest {
+            target: self.target(),
+            content: self.note.content.clone(),
+            note_url: Rc::clone(&self.note_url),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl FeedNoteFinalizeStep {
+    fn target(&self) -> Dependency {
+        Dependency::FeedNote(self.note.owner.clone(), self.note.name.clone())
+    }
+}
+
+/// Modify the content of a feed's note.
+pub(crate) struct ModifyNoteRequest {
+    target: Dependency,
+    content: String,
+    note_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for ModifyNoteRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = Url::parse(&self.note_url).unwrap();
+        let body = NotePatchRequest {
+            content: &self.content,
+        };
+        Request::new(Method::PATCH, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let note: models::Note = serde_json::from_slice(&body)?;
+        Ok(vec![(self.target.clone(), note.url)])
+    }
+}
+
+/// A [PendingStep] to share a feed with a user or group.
+/// See [FeedShareWithUserStep] and [FeedShareWithGroupStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct FeedShare {
+    pub(crate) owner: Username,
+    pub(crate) name: String,
+    pub(crate) target: PermissionTarget,
+}
+
+impl PendingStep for FeedShare {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let auth_token = map.get(Dependency::AuthToken(self.owner.clone()))?;
+        let (owner, name) = (self.owner.clone(), self.name.clone());
+        match &self.target {
+            PermissionTarget::User(user) => ok_step(FeedShareWithUserStep {
+                share: self.clone(),
+                user: user.clone(),
+                permissions_url: map.get(Dependency::FeedUserPermissionsUrl(owner, name))?,
+                auth_token,
+            }),
+            PermissionTarget::Group(group) => ok_step(FeedShareWithGroupStep {
+                share: self.clone(),
+                group: group.clone(),
+                permissions_url: map.get(Dependency::FeedGroupPermissionsUrl(owner, name))?,
+                auth_token,
+            }),
         }
     }
+}
+
+impl FeedShare {
+    fn target(&self) -> Dependency {
+        Dependency::FeedShared(self.owner.clone(), self.name.clone(), self.target.clone())
+    }
+}
+
+/// A [Step] to share a [models::Feed] with a [models::User].
+pub(crate) struct FeedShareWithUserStep {
+    share: FeedShare,
+    user: Username,
+    permissions_url: Rc<String>,
+    auth_token: Rc<String>,
+}
 
-    fn create(&self, (token, feed): &<Self::Dep as Dependency>::Value) -> RequestResult {
+impl Step for FeedShareWithUserStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = Url::parse(&self.permissions_url)
+            .unwrap()
+            .join("search/")
+            .unwrap();
+        url.query_pairs_mut()
+            .append_pair("username", self.user.as_str())
+            .append_pair("limit", "100");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: models::PaginatedFeedUserPermissionList = serde_json::from_slice(&body)?;
+        let check = data
+            .results
+            .into_iter()
+            .find(|permission| permission.user_username == self.user.as_str())
+            .map(|permission| Check::Exists(vec![(self.share.target(), permission.url)]))
+            .unwrap_or(Check::DoesNotExist);
+        Ok(check)
+    }
+
+    fn create(&self) -> Option<Box<dyn StepRequest>> {
         let body = models::FeedUserPermissionRequest {
             username: self.user.to_string(),
         };
-        RequestBuilder::new(&feed.user_permissions)
-            .auth(token)
-            .post(&body)
+        Some(Box::new(CreatePermissionRequest {
+            target: self.share.target(),
+            permissions_url: Rc::clone(&self.permissions_url),
+            auth_token: Rc::clone(&self.auth_token),
+            body: serde_json::to_value(body).unwrap(),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.share.target()]
     }
 }
+
+/// A [Step] to share a [models::Feed] with a [models::Group].
+pub(crate) struct FeedShareWithGroupStep {
+    share: FeedShare,
+    group: Group,
+    permissions_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for FeedShareWithGroupStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = Url::parse(&self.permissions_url)
+            .unwrap()
+            .join("search/")
+            .unwrap();
+        url.query_pairs_mut()
+            .append_pair("group_name", self.group.as_str())
+            .append_pair("limit", "100");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: models::PaginatedFeedGroupPermissionList = serde_json::from_slice(&body)?;
+        let check = data
+            .results
+            .into_iter()
+            .find(|permission| permission.group_name == self.group.as_str())
+            .map(|permission| Check::Exists(vec![(self.share.target(), permission.url)]))
+            .unwrap_or(Check::DoesNotExist);
+        Ok(check)
+    }
+
+    fn create(&self) -> Option<Box<dyn StepRequest>> {
+        let body = models::FeedGroupPermissionRequest {
+            grp_name: self.group.to_string(),
+        };
+        Some(Box::new(CreatePermissionRequest {
+            target: self.share.target(),
+            permissions_url: Rc::clone(&self.permissions_url),
+            auth_token: Rc::clone(&self.auth_token),
+            body: serde_json::to_value(body).unwrap(),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.share.target()]
+    }
+}
+
+/// Create a user or group permission by POST-ing to a permissions collection.
+pub(crate) struct CreatePermissionRequest {
+    target: Dependency,
+    permissions_url: Rc<String>,
+    auth_token: Rc<String>,
+    body: serde_json::Value,
+}
+
+impl StepRequest for CreatePermissionRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = Url::parse(&self.permissions_url).unwrap();
+        Request::new(Method::POST, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&self.body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let permission: PermissionResponse = serde_json::from_slice(&body)?;
+        Ok(vec![(self.target.clone(), permission.url)])
+    }
+}
+
+/// The only part of a permission API response which is needed.
+#[derive(serde::Deserialize)]
+struct PermissionResponse {
+    url: String,
+}
@@ -0,0 +1,291 @@
+use crate::extra_models::{
+    FileGroupPermissionRequest, FilePermissionBrief, FilePermissionList, FilePermissionRequest,
+    FileUserPermissionRequest,
+};
+use crate::request_builder::RequestBuilder;
+use chrisomatic_spec::*;
+use chrisomatic_step::*;
+use chrisomatic_step_macro::AsRefPendingStep;
+use nonempty::{NonEmpty, nonempty};
+use reqwest::{Method, Request, Url};
+use std::rc::Rc;
+
+/// What is being shared: user files and folders are shared the same way,
+/// through the permissions collections under their URL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SharedFileKind {
+    UserFile,
+    Folder,
+}
+
+impl SharedFileKind {
+    fn url_of(&self, path: String) -> Dependency {
+        match self {
+            Self::UserFile => Dependency::UserFileUrl(path),
+            Self::Folder => Dependency::FolderUrl(path),
+        }
+    }
+}
+
+/// A [PendingStep] to share a user file or folder with a user or group.
+/// See [FilePermissionExistsStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct FilePermissionExists {
+    pub(crate) owner: Username,
+    pub(crate) kind: SharedFileKind,
+    pub(crate) path: String,
+    /// User or group to share with. (Sharing with everyone is done by making
+    /// a folder public, see [crate::steps::FolderPublicFinalize].)
+    pub(crate) target: PermissionTarget,
+    pub(crate) permission: FilePermission,
+}
+
+impl PendingStep for FilePermissionExists {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let step = FilePermissionExistsStep {
+            share: self.clone(),
+            file_url: map.get(self.kind.url_of(self.path.clone()))?,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to search for the permission of a user or group on a user file
+/// or folder. If not found, the permission is created by the owner.
+pub(crate) struct FilePermissionExistsStep {
+    share: FilePermissionExists,
+    file_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for FilePermissionExistsStep {
+    fn search(&self) -> reqwest::Request {
+        let (collection, query) = match &self.share.target {
+            PermissionTarget::User(user) => {
+                ("userpermissions/search/", ("username", user.as_str()))
+            }
+            PermissionTarget::Group(group) => {
+                ("grouppermissions/search/", ("group_name", group.as_str()))
+            }
+        };
+        let mut url = Url::parse(&self.file_url)
+            .unwrap()
+            .join(collection)
+            .unwrap();
+        url.query_pairs_mut()
+            .append_pair(query.0, query.1)
+            .append_pair("limit", "100");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: FilePermissionList = serde_json::from_slice(&body)?;
+        let check = data
+            .results
+            .into_iter()
+            .find(|permission| match &self.share.target {
+                PermissionTarget::User(user) => {
+                    permission.user_username.as_deref() == Some(user.as_str())
+                }
+                PermissionTarget::Group(group) => {
+                    permission.group_name.as_deref() == Some(group.as_str())
+                }
+            })
+            .map(|permission| Check::Exists(self.found_entries(permission)))
+            .unwrap_or(Check::DoesNotExist);
+        Ok(check)
+    }
+
+    fn create(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(CreateFilePermissionRequest {
+            share: self.share.clone(),
+            file_url: Rc::clone(&self.file_url),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        let share = &self.share;
+        nonempty![Dependency::FilePermissionUrl(
+            share.path.clone(),
+            share.target.clone()
+        )]
+    }
+}
+
+impl FilePermissionExistsStep {
+    fn found_entries(&self, permission: FilePermissionBrief) -> Entries {
+        let share = &self.share;
+        let (path, target) = (share.path.clone(), share.target.clone());
+        let is_specified = permission.permission == permission_code(share.permission);
+        let mut entries = vec![(
+            Dependency::FilePermissionUrl(path.clone(), target.clone()),
+            permission.url.clone(),
+        )];
+        if is_specified {
+            entries.push((Dependency::FileShared(path, target), permission.url));
+        }
+        entries
+    }
+}
+
+/// Share a user file or folder with a user or group.
+pub(crate) struct CreateFilePermissionRequest {
+    share: FilePermissionExists,
+    file_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for CreateFilePermissionRequest {
+    fn request(&self) -> reqwest::Request {
+        let permission = permission_code(self.share.permission);
+        let base = Url::parse(&self.file_url).unwrap();
+        let request = match &self.share.target {
+            PermissionTarget::User(user) => {
+                let body = FileUserPermissionRequest {
+                    username: user.as_str(),
+                    permission,
+                };
+                Request::new(Method::POST, base.join("userpermissions/").unwrap()).json(&body)
+            }
+            PermissionTarget::Group(group) => {
+                let body = FileGroupPermissionRequest {
+                    grp_name: group.as_str(),
+                    permission,
+                };
+                Request::new(Method::POST, base.join("grouppermissions/").unwrap()).json(&body)
+            }
+        };
+        request
+            .unwrap()
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let permission: FilePermissionBrief = serde_json::from_slice(&body)?;
+        let (path, target) = (self.share.path.clone(), self.share.target.clone());
+        // the permission was just created as specified.
+        Ok(vec![
+            (
+                Dependency::FilePermissionUrl(path.clone(), target.clone()),
+                permission.url.clone(),
+            ),
+            (Dependency::FileShared(path, target), permission.url),
+        ])
+    }
+}
+
+/// A [PendingStep] to make sure that the permission of a user or group on a
+/// user file or folder is as specified. See [FilePermissionFinalizeStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct FilePermissionFinalize {
+    pub(crate) owner: Username,
+    pub(crate) path: String,
+    pub(crate) target: PermissionTarget,
+    pub(crate) permission: FilePermission,
+}
+
+impl PendingStep for FilePermissionFinalize {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        if map.contains_key(&self.target()) {
+            return Ok(None);
+        }
+        let permission_url = Dependency::FilePermissionUrl(self.path.clone(), self.target.clone());
+        let step = FilePermissionFinalizeStep {
+            finalize: self.clone(),
+            permission_url: map.get(permission_url)?,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+impl FilePermissionFinalize {
+    fn target(&self) -> Dependency {
+        Dependency::FileShared(self.path.clone(), self.target.clone())
+    }
+}
+
+/// A [Step] to check the permission of a user or group on a user file or
+/// folder, changing it if it is not as specified (e.g. read-only access
+/// which should be read and write access).
+pub(crate) struct FilePermissionFinalizeStep {
+    finalize: FilePermissionFinalize,
+    permission_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for FilePermissionFinalizeStep {
+    fn search(&self) -> reqwest::Request {
+        let url = Url::parse(&self.permission_url).unwrap();
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let permission: FilePermissionBrief = serde_json::from_slice(&body)?;
+        let check = if permission.permission == permission_code(self.finalize.permission) {
+            Check::Exists(vec![(self.finalize.target(), permission.url)])
+        } else {
+            Check::NeedsModification
+        };
+        Ok(check)
+    }
+
+    fn modify(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(ModifyFilePermissionRequest {
+            target: self.finalize.target(),
+            permission: self.finalize.permission,
+            permission_url: Rc::clone(&self.permission_url),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.finalize.target()]
+    }
+}
+
+/// Change the permission of a user or group on a user file or folder.
+pub(crate) struct ModifyFilePermissionRequest {
+    target: Dependency,
+    permission: FilePermission,
+    permission_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for ModifyFilePermissionRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = Url::parse(&self.permission_url).unwrap();
+        let body = FilePermissionRequest {
+            permission: permission_code(self.permission),
+        };
+        Request::new(Method::PUT, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let permission: FilePermissionBrief = serde_json::from_slice(&body)?;
+        Ok(vec![(self.target.clone(), permission.url)])
+    }
+}
+
+/// Value of `permission` in the API of CUBE.
+fn permission_code(permission: FilePermission) -> &'static str {
+    match permission {
+        FilePermission::Read => "r",
+        FilePermission::Write => "w",
+    }
+}
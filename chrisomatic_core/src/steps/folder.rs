@@ -0,0 +1,197 @@
+use crate::extra_models::{FolderBrief, FolderList, FolderPublicRequest, Publicity};
+use crate::request_builder::RequestBuilder;
+use chrisomatic_spec::*;
+use chrisomatic_step::*;
+use chrisomatic_step_macro::AsRefPendingStep;
+use nonempty::{NonEmpty, nonempty};
+use reqwest::{Method, Request, Url};
+use std::rc::Rc;
+
+/// A [PendingStep] to find a folder. See [FolderFindStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct FolderFind {
+    pub(crate) owner: Username,
+    pub(crate) path: String,
+    /// Whether the folder should be public, if specified.
+    pub(crate) public: Option<bool>,
+    /// What needs the folder. If specified, a folder which does not exist is
+    /// reported as [Check::Invalid] explaining so.
+    pub(crate) needed_for: Option<&'static str>,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for FolderFind {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let step = FolderFindStep {
+            folder: self.clone(),
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to search for a folder by path.
+///
+/// Folders are created by uploading files into them, so this step does not
+/// create folders.
+pub(crate) struct FolderFindStep {
+    folder: FolderFind,
+    auth_token: Rc<String>,
+}
+
+impl Step for FolderFindStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = self
+            .folder
+            .url
+            .to_url()
+            .join("filebrowser/search/")
+            .unwrap();
+        url.query_pairs_mut()
+            .append_pair("path", &self.folder.path)
+            .append_pair("limit", "1");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: FolderList = serde_json::from_slice(&body)?;
+        let check = data
+            .results
+            .into_iter()
+            .find(|folder| folder.path == self.folder.path)
+            .map(|folder| Check::Exists(self.found_entries(folder)))
+            .unwrap_or_else(|| match self.folder.needed_for {
+                Some(needed_for) => Check::Invalid(format!(
+                    "folder \"{}\" does not exist, it must be created (by uploading files into it) for {needed_for}",
+                    self.folder.path
+                )),
+                None => Check::DoesNotExist,
+            });
+        Ok(check)
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        let path = &self.folder.path;
+        nonempty![
+            Dependency::FolderUrl(path.clone()),
+            Dependency::FolderLinkFilesUrl(path.clone())
+        ]
+    }
+}
+
+impl FolderFindStep {
+    fn found_entries(&self, folder: FolderBrief) -> Entries {
+        let path = &self.folder.path;
+        let public = self.folder.public == Some(folder.public);
+        let mut entries = vec![
+            (Dependency::FolderUrl(path.clone()), folder.url.clone()),
+            (
+                Dependency::FolderLinkFilesUrl(path.clone()),
+                folder.link_files,
+            ),
+        ];
+        if public {
+            entries.push((Dependency::FolderPublic(path.clone()), folder.url));
+        }
+        entries
+    }
+}
+
+/// A [PendingStep] to make sure that a folder is public or not.
+/// See [FolderPublicFinalizeStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct FolderPublicFinalize {
+    pub(crate) owner: Username,
+    pub(crate) path: String,
+    pub(crate) public: bool,
+}
+
+impl PendingStep for FolderPublicFinalize {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        if map.contains_key(&Dependency::FolderPublic(self.path.clone())) {
+            return Ok(None);
+        }
+        let step = FolderPublicFinalizeStep {
+            folder: self.clone(),
+            folder_url: map.get(Dependency::FolderUrl(self.path.clone()))?,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to check whether a folder is public, modifying the folder if it
+/// is not as specified.
+pub(crate) struct FolderPublicFinalizeStep {
+    folder: FolderPublicFinalize,
+    folder_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for FolderPublicFinalizeStep {
+    fn search(&self) -> reqwest::Request {
+        let url = Url::parse(&self.folder_url).unwrap();
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let folder: Publicity = serde_json::from_slice(&body)?;
+        let check = if folder.public == self.folder.public {
+            Check::Exists(vec![(self.target(), folder.url)])
+        } else {
+            Check::NeedsModification
+        };
+        Ok(check)
+    }
+
+    fn modify(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(ModifyFolderPublicRequest {
+            target: self.target(),
+            folder: self.folder.clone(),
+            folder_url: Rc::clone(&self.folder_url),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl FolderPublicFinalizeStep {
+    fn target(&self) -> Dependency {
+        Dependency::FolderPublic(self.folder.path.clone())
+    }
+}
+
+/// Make a folder public or not.
+pub(crate) struct ModifyFolderPublicRequest {
+    target: Dependency,
+    folder: FolderPublicFinalize,
+    folder_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for ModifyFolderPublicRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = Url::parse(&self.folder_url).unwrap();
+        let body = FolderPublicRequest {
+            path: &self.folder.path,
+            public: self.folder.public,
+        };
+        Request::new(Method::PUT, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let folder: Publicity = serde_json::from_slice(&body)?;
+        Ok(vec![(self.target.clone(), folder.url)])
+    }
+}
@@ -0,0 +1,314 @@
+use crate::request_builder::RequestBuilder;
+use chris_oag::models;
+use chrisomatic_spec::*;
+use chrisomatic_step::*;
+use chrisomatic_step_macro::AsRefPendingStep;
+use compact_str::CompactString;
+use nonempty::{NonEmpty, nonempty};
+use reqwest::{Method, Request, Url};
+use std::collections::HashSet;
+use std::rc::Rc;
+
+/// A [PendingStep] to make sure that a group exists. See [GroupExistsStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct GroupExists {
+    pub(crate) group: Group,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for GroupExists {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let step = GroupExistsStep {
+            group: self.group.clone(),
+            url: self.url.clone(),
+            auth_token: map.get(Dependency::AdminToken)?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to search for a group by name, creating it if necessary. The name
+/// filter of the search matches substrings, so every page of results is read
+/// to find the group with exactly the same name.
+/// Produces [Dependency::GroupUrl] and [Dependency::GroupUsersUrl].
+pub(crate) struct GroupExistsStep {
+    group: Group,
+    url: CubeUrl,
+    auth_token: Rc<String>,
+}
+
+impl Step for GroupExistsStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = self.url.to_url().join("groups/search/").unwrap();
+        url.query_pairs_mut()
+            .append_pair("name", self.group.as_str())
+            .append_pair("limit", "100");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: models::PaginatedGroupList = serde_json::from_slice(&body)?;
+        let check = data
+            .results
+            .into_iter()
+            .find(|group| group.name == self.group.as_str())
+            .map(|group| Check::Exists(group_entries(&self.group, group)))
+            .unwrap_or(Check::DoesNotExist);
+        Ok(check)
+    }
+
+    fn create(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(CreateGroupRequest {
+            group: self.group.clone(),
+            url: self.url.clone(),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![
+            Dependency::GroupUrl(self.group.clone()),
+            Dependency::GroupUsersUrl(self.group.clone())
+        ]
+    }
+}
+
+pub(crate) struct CreateGroupRequest {
+    group: Group,
+    url: CubeUrl,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for CreateGroupRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = self.url.to_url().join("groups/").unwrap();
+        let body = models::GroupRequest {
+            name: self.group.to_string(),
+        };
+        Request::new(Method::POST, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let group: models::Group = serde_json::from_slice(&body)?;
+        Ok(group_entries(&self.group, group))
+    }
+}
+
+fn group_entries(name: &Group, group: models::Group) -> Entries {
+    vec![
+        (Dependency::GroupUrl(name.clone()), group.url),
+        (Dependency::GroupUsersUrl(name.clone()), group.users),
+    ]
+}
+
+/// A [PendingStep] to make sure that a user is a member of a group. See [GroupMemberExistsStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct GroupMemberExists {
+    pub(crate) group: Group,
+    pub(crate) username: Username,
+}
+
+impl PendingStep for GroupMemberExists {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let step = GroupMemberExistsStep {
+            group: self.group.clone(),
+            username: self.username.clone(),
+            user_groups_url: map.get(Dependency::UserGroupsUrl(self.username.clone()))?,
+            auth_token: map.get(Dependency::AuthToken(self.username.clone()))?,
+            group_users_url: map.get(Dependency::GroupUsersUrl(self.group.clone()))?,
+            admin_token: map.get(Dependency::AdminToken)?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to add a user to a group if they are not already a member.
+///
+/// The user's own groups are listed using the user's [Dependency::AuthToken],
+/// whereas the [Dependency::AdminToken] is used to add the user
+/// to the group.
+pub(crate) struct GroupMemberExistsStep {
+    group: Group,
+    username: Username,
+    user_groups_url: Rc<String>,
+    auth_token: Rc<String>,
+    group_users_url: Rc<String>,
+    admin_token: Rc<String>,
+}
+
+impl Step for GroupMemberExistsStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = Url::parse(&self.user_groups_url).unwrap();
+        url.query_pairs_mut().append_pair("limit", "100");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: models::PaginatedGroupList = serde_json::from_slice(&body)?;
+        let check = data
+            .results
+            .into_iter()
+            .find(|group| group.name == self.group.as_str())
+            .map(|group| {
+                Check::Exists(vec![(
+                    Dependency::GroupMember(self.group.clone(), self.username.clone()),
+                    group.id.to_string(), // arbitrary placeholder value
+                )])
+            })
+            .unwrap_or(Check::DoesNotExist);
+        Ok(check)
+    }
+
+    fn create(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(CreateGroupMemberRequest {
+            group: self.group.clone(),
+            username: self.username.clone(),
+            group_users_url: Rc::clone(&self.group_users_url),
+            admin_token: Rc::clone(&self.admin_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![Dependency::GroupMember(
+            self.group.clone(),
+            self.username.clone()
+        )]
+    }
+}
+
+pub(crate) struct CreateGroupMemberRequest {
+    group: Group,
+    username: Username,
+    group_users_url: Rc<String>,
+    admin_token: Rc<String>,
+}
+
+impl StepRequest for CreateGroupMemberRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = Url::parse(&self.group_users_url).unwrap();
+        let body = models::GroupUserRequest {
+            username: self.username.to_string(),
+        };
+        Request::new(Method::POST, url)
+            .auth_token(self.admin_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let membership: models::GroupUser = serde_json::from_slice(&body)?;
+        let outputs = vec![(
+            Dependency::GroupMember(self.group.clone(), self.username.clone()),
+            membership.id.to_string(), // arbitrary placeholder value
+        )];
+        Ok(outputs)
+    }
+}
+
+/// A [PendingStep] to make sure that a group has no members other than the
+/// specified ones. See [GroupExclusiveStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct GroupExclusive {
+    pub(crate) group: Group,
+    pub(crate) members: Rc<HashSet<Username>>,
+}
+
+impl PendingStep for GroupExclusive {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let step = GroupExclusiveStep {
+            group: self.group.clone(),
+            members: Rc::clone(&self.members),
+            group_users_url: map.get(Dependency::GroupUsersUrl(self.group.clone()))?,
+            admin_token: map.get(Dependency::AdminToken)?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to list the members of a group using the admin API, removing
+/// every member who is not specified.
+pub(crate) struct GroupExclusiveStep {
+    group: Group,
+    members: Rc<HashSet<Username>>,
+    group_users_url: Rc<String>,
+    admin_token: Rc<String>,
+}
+
+impl Step for GroupExclusiveStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = Url::parse(&self.group_users_url).unwrap();
+        url.query_pairs_mut().append_pair("limit", "100");
+        Request::new(Method::GET, url)
+            .auth_token(self.admin_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: models::PaginatedGroupUserList = serde_json::from_slice(&body)?;
+        let requests: Vec<_> = data
+            .results
+            .into_iter()
+            .filter(|membership| {
+                let username = Username::new(CompactString::from(membership.username.as_str()));
+                !self.members.contains(&username)
+            })
+            .map(|membership| {
+                Box::new(RemoveGroupMemberRequest {
+                    membership_url: membership.url,
+                    admin_token: Rc::clone(&self.admin_token),
+                }) as Box<dyn StepRequest>
+            })
+            .collect();
+        let entries = vec![(
+            Dependency::GroupExclusive(self.group.clone()),
+            self.members.len().to_string(), // arbitrary placeholder value
+        )];
+        let check = if requests.is_empty() {
+            Check::Exists(entries)
+        } else {
+            Check::NeedsRemoval { requests, entries }
+        };
+        Ok(check)
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![Dependency::GroupExclusive(self.group.clone())]
+    }
+}
+
+pub(crate) struct RemoveGroupMemberRequest {
+    membership_url: String,
+    admin_token: Rc<String>,
+}
+
+impl StepRequest for RemoveGroupMemberRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = Url::parse(&self.membership_url).unwrap();
+        Request::new(Method::DELETE, url).auth_token(self.admin_token.as_str())
+    }
+
+    fn deserialize(&self, _body: bytes::Bytes) -> serde_json::Result<Entries> {
+        Ok(vec![])
+    }
+}
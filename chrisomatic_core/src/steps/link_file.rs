@@ -0,0 +1,130 @@
+use crate::extra_models::{LinkFileBrief, LinkFileCreateRequest, LinkFileList};
+use crate::request_builder::RequestBuilder;
+use chrisomatic_spec::*;
+use chrisomatic_step::*;
+use chrisomatic_step_macro::AsRefPendingStep;
+use nonempty::{NonEmpty, nonempty};
+use reqwest::{Method, Request, Url};
+use std::rc::Rc;
+
+/// A [PendingStep] to make sure that a link file exists. See [LinkFileExistsStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct LinkFileExists {
+    pub(crate) owner: Username,
+    /// Path of the link file, including its `.chrislink` suffix.
+    pub(crate) path: String,
+    /// Path which is linked to.
+    pub(crate) target: String,
+}
+
+impl PendingStep for LinkFileExists {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let (folder, _) = split_link_path(&self.path);
+        let step = LinkFileExistsStep {
+            link: self.clone(),
+            link_files_url: map.get(Dependency::FolderLinkFilesUrl(folder.to_string()))?,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to search for a link file among the link files of its folder. If
+/// not found, the link file is created by its owner.
+///
+/// A link file which exists but links to a different path is reported as
+/// [Check::Invalid] rather than being replaced.
+pub(crate) struct LinkFileExistsStep {
+    link: LinkFileExists,
+    link_files_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for LinkFileExistsStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = Url::parse(&self.link_files_url).unwrap();
+        url.query_pairs_mut().append_pair("limit", "100");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: LinkFileList = serde_json::from_slice(&body)?;
+        let check = data
+            .results
+            .into_iter()
+            .find(|link| link.fname == self.link.path)
+            .map(|link| self.check_found(link))
+            .unwrap_or(Check::DoesNotExist);
+        Ok(check)
+    }
+
+    fn create(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(CreateLinkFileRequest {
+            target: self.target(),
+            link: self.link.clone(),
+            link_files_url: Rc::clone(&self.link_files_url),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl LinkFileExistsStep {
+    fn target(&self) -> Dependency {
+        Dependency::LinkFileUrl(self.link.path.clone())
+    }
+
+    fn check_found(&self, link: LinkFileBrief) -> Check {
+        if link.path == self.link.target {
+            Check::Exists(vec![(self.target(), link.url)])
+        } else {
+            Check::Invalid(format!(
+                "link file \"{}\" links to \"{}\" instead of \"{}\"",
+                self.link.path, link.path, self.link.target
+            ))
+        }
+    }
+}
+
+/// Create a link file in its folder.
+pub(crate) struct CreateLinkFileRequest {
+    target: Dependency,
+    link: LinkFileExists,
+    link_files_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for CreateLinkFileRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = Url::parse(&self.link_files_url).unwrap();
+        let (_, name) = split_link_path(&self.link.path);
+        let body = LinkFileCreateRequest {
+            path: &self.link.target,
+            name,
+        };
+        Request::new(Method::POST, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let link: LinkFileBrief = serde_json::from_slice(&body)?;
+        Ok(vec![(self.target.clone(), link.url)])
+    }
+}
+
+/// Split the path of a link file into the path of its folder and its name.
+pub(crate) fn split_link_path(path: &str) -> (&str, &str) {
+    path.rsplit_once('/').unwrap_or(("", path))
+}
@@ -1,7 +1,35 @@
 //! Naming convention: Noun before verb (like French)
 
+mod admin;
+mod comment;
+mod compute_resource;
+mod feed;
+mod file_permission;
+mod folder;
+mod group;
+mod link_file;
+mod pacs;
+mod pipeline;
+mod plugin;
+mod plugin_instance;
+mod tag;
 mod user;
-// mod feed;
+mod userfile;
+mod workflow;
 
+pub(crate) use admin::*;
+pub(crate) use comment::*;
+pub(crate) use compute_resource::*;
+pub(crate) use feed::*;
+pub(crate) use file_permission::*;
+pub(crate) use folder::*;
+pub(crate) use group::*;
+pub(crate) use link_file::*;
+pub(crate) use pacs::*;
+pub(crate) use pipeline::*;
+pub(crate) use plugin::*;
+pub(crate) use plugin_instance::*;
+pub(crate) use tag::*;
 pub(crate) use user::*;
-// pub(crate) use feed::*;
+pub(crate) use userfile::*;
+pub(crate) use workflow::*;
@@ -0,0 +1,365 @@
+use crate::{
+    extra_models::{
+        PacsCreateRequest, PacsList, PacsQueryCreateRequest, PacsQueryList,
+        PacsSeriesCreateRequest, PacsSeriesList,
+    },
+    request_builder::RequestBuilder,
+};
+use chrisomatic_spec::*;
+use chrisomatic_step::*;
+use chrisomatic_step_macro::AsRefPendingStep;
+use nonempty::{NonEmpty, nonempty};
+use reqwest::{Method, Request, Url};
+use std::rc::Rc;
+
+/// A [PendingStep] to make sure that a PACS exists. See [PacsExistsStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct PacsExists {
+    pub(crate) identifier: String,
+    pub(crate) spec: Rc<PacsSpec>,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for PacsExists {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let step = PacsExistsStep {
+            pacs: self.clone(),
+            auth_token: map.get(Dependency::AdminToken)?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to search for a PACS by identifier, creating it as the admin
+/// user if necessary.
+pub(crate) struct PacsExistsStep {
+    pacs: PacsExists,
+    auth_token: Rc<String>,
+}
+
+impl Step for PacsExistsStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = self.pacs.url.to_url().join("pacs/search/").unwrap();
+        url.query_pairs_mut()
+            .append_pair("identifier", &self.pacs.identifier)
+            .append_pair("limit", "100");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: PacsList = serde_json::from_slice(&body)?;
+        let check = data
+            .results
+            .into_iter()
+            .find(|pacs| pacs.identifier == self.pacs.identifier)
+            .map(|pacs| Check::Exists(vec![(self.target(), pacs.url)]))
+            .unwrap_or(Check::DoesNotExist);
+        Ok(check)
+    }
+
+    fn create(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(CreatePacsRequest {
+            target: self.target(),
+            pacs: self.pacs.clone(),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl PacsExistsStep {
+    fn target(&self) -> Dependency {
+        Dependency::PacsUrl(self.pacs.identifier.clone())
+    }
+}
+
+pub(crate) struct CreatePacsRequest {
+    target: Dependency,
+    pacs: PacsExists,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for CreatePacsRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = self.pacs.url.to_url().join("pacs/").unwrap();
+        let body = PacsCreateRequest {
+            identifier: &self.pacs.identifier,
+            active: self.pacs.spec.active,
+        };
+        Request::new(Method::POST, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let pacs: ResourceUrl = serde_json::from_slice(&body)?;
+        Ok(vec![(self.target.clone(), pacs.url)])
+    }
+}
+
+/// A [PendingStep] to make sure that a user has a PACS query.
+/// See [PacsQueryExistsStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct PacsQueryExists {
+    pub(crate) owner: Username,
+    pub(crate) query: Rc<PacsQuerySpec>,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for PacsQueryExists {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let step = PacsQueryExistsStep {
+            query: self.clone(),
+            pacs_url: map.get(Dependency::PacsUrl(self.query.pacs.clone()))?,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to search for a PACS query of a user by title, creating it as
+/// the user if necessary. An existing query is not changed.
+pub(crate) struct PacsQueryExistsStep {
+    query: PacsQueryExists,
+    pacs_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for PacsQueryExistsStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = self
+            .query
+            .url
+            .to_url()
+            .join("pacs/queries/search/")
+            .unwrap();
+        url.query_pairs_mut()
+            .append_pair("title", &self.query.query.title)
+            .append_pair("owner_username", self.query.owner.as_str())
+            .append_pair("pacs_identifier", &self.query.query.pacs)
+            .append_pair("limit", "100");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: PacsQueryList = serde_json::from_slice(&body)?;
+        let check = data
+            .results
+            .into_iter()
+            .find(|query| {
+                query.title == self.query.query.title
+                    && query.owner_username == self.query.owner.as_str()
+                    && query.pacs_identifier == self.query.query.pacs
+            })
+            .map(|query| Check::Exists(vec![(self.target(), query.url)]))
+            .unwrap_or(Check::DoesNotExist);
+        Ok(check)
+    }
+
+    fn create(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(CreatePacsQueryRequest {
+            target: self.target(),
+            query: Rc::clone(&self.query.query),
+            pacs_url: Rc::clone(&self.pacs_url),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl PacsQueryExistsStep {
+    fn target(&self) -> Dependency {
+        Dependency::PacsQueryUrl(self.query.owner.clone(), self.query.query.title.clone())
+    }
+}
+
+pub(crate) struct CreatePacsQueryRequest {
+    target: Dependency,
+    query: Rc<PacsQuerySpec>,
+    pacs_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for CreatePacsQueryRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = Url::parse(&self.pacs_url)
+            .unwrap()
+            .join("queries/")
+            .unwrap();
+        let body = PacsQueryCreateRequest {
+            title: &self.query.title,
+            query: &self.query.query,
+            description: self.query.description.as_deref(),
+        };
+        Request::new(Method::POST, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let query: ResourceUrl = serde_json::from_slice(&body)?;
+        Ok(vec![(self.target.clone(), query.url)])
+    }
+}
+
+/// A [PendingStep] to make sure that a series of DICOM files is registered
+/// as a PACS series. See [PacsSeriesExistsStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct PacsSeriesExists {
+    pub(crate) pacs_identifier: String,
+    /// Path of the folder of the series' files in _ChRIS_ storage.
+    pub(crate) path: String,
+    /// Header of (any) one of the series' files.
+    pub(crate) header: Rc<DicomHeader>,
+    /// Number of files in the series.
+    pub(crate) ndicom: usize,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for PacsSeriesExists {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let step = PacsSeriesExistsStep {
+            series: self.clone(),
+            auth_token: map.get(Dependency::AdminToken)?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to search for a PACS series by PACS and SeriesInstanceUID. If not
+/// found, the series is registered by the admin user, after its files were
+/// uploaded into the storage of the PACS.
+pub(crate) struct PacsSeriesExistsStep {
+    series: PacsSeriesExists,
+    auth_token: Rc<String>,
+}
+
+impl Step for PacsSeriesExistsStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = self
+            .series
+            .url
+            .to_url()
+            .join("pacs/series/search/")
+            .unwrap();
+        url.query_pairs_mut()
+            .append_pair("SeriesInstanceUID", &self.series.header.series_instance_uid)
+            .append_pair("pacs_identifier", &self.series.pacs_identifier)
+            .append_pair("limit", "100");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: PacsSeriesList = serde_json::from_slice(&body)?;
+        let check = data
+            .results
+            .into_iter()
+            .find(|series| series.series_instance_uid == self.series.header.series_instance_uid)
+            .map(|series| Check::Exists(vec![(self.target(), series.url)]))
+            .unwrap_or(Check::DoesNotExist);
+        Ok(check)
+    }
+
+    fn create(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(RegisterPacsSeriesRequest {
+            target: self.target(),
+            series: self.series.clone(),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl PacsSeriesExistsStep {
+    fn target(&self) -> Dependency {
+        Dependency::PacsSeriesUrl(
+            self.series.pacs_identifier.clone(),
+            self.series.header.series_instance_uid.clone(),
+        )
+    }
+}
+
+/// Register a series of DICOM files using the admin-only PACS series API.
+pub(crate) struct RegisterPacsSeriesRequest {
+    target: Dependency,
+    series: PacsSeriesExists,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for RegisterPacsSeriesRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = self.series.url.to_url().join("pacs/series/").unwrap();
+        let header = self.series.header.as_ref();
+        let body = PacsSeriesCreateRequest {
+            path: &self.series.path,
+            ndicom: self.series.ndicom,
+            pacs_name: &self.series.pacs_identifier,
+            patient_id: &header.patient_id,
+            patient_name: header.patient_name.as_deref(),
+            study_date: header.study_date.as_deref().map(iso_date),
+            study_instance_uid: &header.study_instance_uid,
+            study_description: header.study_description.as_deref(),
+            series_instance_uid: &header.series_instance_uid,
+            series_description: header.series_description.as_deref(),
+            modality: header.modality.as_deref(),
+        };
+        Request::new(Method::POST, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let series: ResourceUrl = serde_json::from_slice(&body)?;
+        Ok(vec![(self.target.clone(), series.url)])
+    }
+}
+
+/// The only part of a PACS, PACS query, or PACS series API response which
+/// is needed.
+#[derive(serde::Deserialize)]
+struct ResourceUrl {
+    url: String,
+}
+
+/// Convert a DICOM date (`YYYYMMDD`) to `YYYY-MM-DD`. Other values are
+/// returned as-is.
+fn iso_date(date: &str) -> String {
+    if date.len() == 8 && date.bytes().all(|b| b.is_ascii_digit()) {
+        format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..])
+    } else {
+        date.to_string()
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{extra_models::PipelineCreateRequest, request_builder::RequestBuilder};
+use chris_oag::models;
+use chrisomatic_spec::*;
+use chrisomatic_step::*;
+use chrisomatic_step_macro::AsRefPendingStep;
+use nonempty::{NonEmpty, nonempty};
+use reqwest::{Method, Request};
+use std::rc::Rc;
+
+/// A [PendingStep] to make sure that a pipeline exists. See [PipelineExistsStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct PipelineExists {
+    pub(crate) owner: Username,
+    pub(crate) definition: Rc<PipelineDefinition>,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for PipelineExists {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        // plugins of the pipeline must be registered first
+        for piece in &self.definition.plugin_tree {
+            map.get(Dependency::PluginUrl(piece.plugin()))?;
+        }
+        let step = PipelineExistsStep {
+            pipeline: self.clone(),
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to search for a pipeline by name. If not found, the pipeline is
+/// created by its owner.
+pub(crate) struct PipelineExistsStep {
+    pipeline: PipelineExists,
+    auth_token: Rc<String>,
+}
+
+impl Step for PipelineExistsStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = self
+            .pipeline
+            .url
+            .to_url()
+            .join("pipelines/search/")
+            .unwrap();
+        url.query_pairs_mut()
+            .append_pair("name_exact", &self.pipeline.definition.name)
+            .append_pair("limit", "1");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: models::PaginatedPipelineList = serde_json::from_slice(&body)?;
+        let check = data
+            .results
+            .into_iter()
+            .find(|pipeline| pipeline.name == self.pipeline.definition.name)
+            .map(|pipeline| Check::Exists(vec![(self.target(), pipeline.url)]))
+            .unwrap_or(Check::DoesNotExist);
+        Ok(check)
+    }
+
+    fn create(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(CreatePipelineRequest {
+            target: self.target(),
+            pipeline: self.pipeline.clone(),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl PipelineExistsStep {
+    fn target(&self) -> Dependency {
+        Dependency::PipelineUrl(self.pipeline.definition.name.clone())
+    }
+}
+
+/// Create a pipeline.
+pub(crate) struct CreatePipelineRequest {
+    target: Dependency,
+    pipeline: PipelineExists,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for CreatePipelineRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = self.pipeline.url.to_url().join("pipelines/").unwrap();
+        let definition = &self.pipeline.definition;
+        let body = PipelineCreateRequest {
+            name: &definition.name,
+            authors: &definition.authors,
+            category: &definition.category,
+            description: &definition.description,
+            locked: definition.locked,
+            plugin_tree: serde_json::to_string(&definition.plugin_tree).unwrap(),
+        };
+        Request::new(Method::POST, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let pipeline: models::Pipeline = serde_json::from_slice(&body)?;
+        Ok(vec![(self.target.clone(), pipeline.url)])
+    }
+}
@@ -0,0 +1,301 @@
+use crate::{
+    extra_models::{PluginAdminRequest, PluginComputeNamesRequest},
+    request_builder::RequestBuilder,
+};
+use chris_oag::models;
+use chrisomatic_spec::*;
+use chrisomatic_step::*;
+use chrisomatic_step_macro::AsRefPendingStep;
+use nonempty::{NonEmpty, nonempty};
+use reqwest::{Method, Request, Url};
+use std::rc::Rc;
+
+use super::admin_api_url;
+
+/// Name of the compute resource which plugins are assigned to by default.
+const DEFAULT_COMPUTE_RESOURCE: &str = "host";
+
+/// A [PendingStep] to make sure that a plugin is registered. See [PluginExistsStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct PluginExists {
+    pub(crate) plugin: PluginSpec,
+    pub(crate) compute_resources: Rc<Vec<ComputeResourceName>>,
+    /// Descriptor to register the plugin with, which must be
+    /// [PluginDescriptor::Json]. If [None], the plugin's descriptor is copied
+    /// from the public CUBE.
+    pub(crate) descriptor: Option<Rc<PluginDescriptor>>,
+    pub(crate) url: CubeUrl,
+    pub(crate) public_cube: CubeUrl,
+}
+
+impl PendingStep for PluginExists {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let step = PluginExistsStep {
+            plugin: self.clone(),
+            auth_token: map.get(Dependency::AdminToken)?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to search for a plugin by name (and version, if specified).
+/// If not found, the plugin is registered by the admin user, either from
+/// its specified descriptor or with its descriptor being copied from the
+/// public CUBE.
+pub(crate) struct PluginExistsStep {
+    plugin: PluginExists,
+    auth_token: Rc<String>,
+}
+
+impl Step for PluginExistsStep {
+    fn search(&self) -> reqwest::Request {
+        let spec = &self.plugin.plugin;
+        let mut url = self.plugin.url.to_url().join("plugins/search/").unwrap();
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("name_exact", spec.name());
+            if let Some(version) = spec.version() {
+                query.append_pair("version", version);
+            }
+            query.append_pair("limit", "1");
+        }
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let spec = &self.plugin.plugin;
+        let data: models::PaginatedPluginList = serde_json::from_slice(&body)?;
+        let check = data
+            .results
+            .into_iter()
+            .find(|plugin| {
+                plugin.name == spec.name()
+                    && spec
+                        .version()
+                        .is_none_or(|version| plugin.version == version)
+            })
+            .map(|plugin| Check::Exists(plugin_entries(spec, &self.plugin.url, plugin)))
+            .unwrap_or_else(|| match self.plugin.descriptor.as_deref() {
+                // descriptors given as files or images are loaded by the CLI
+                Some(PluginDescriptor::File(_) | PluginDescriptor::Image { .. }) => {
+                    Check::Invalid(format!(
+                        "Plugin \"{spec}\" cannot be registered because its descriptor was not loaded."
+                    ))
+                }
+                _ => Check::DoesNotExist,
+            });
+        Ok(check)
+    }
+
+    fn create(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(RegisterPluginRequest {
+            plugin: self.plugin.clone(),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        let spec = &self.plugin.plugin;
+        nonempty![
+            Dependency::PluginUrl(spec.clone()),
+            Dependency::PluginAdminUrl(spec.clone()),
+            Dependency::PluginComputeResourcesUrl(spec.clone()),
+            Dependency::PluginInstancesUrl(spec.clone())
+        ]
+    }
+}
+
+/// Register a plugin using the admin API. A specified descriptor is uploaded
+/// as a file, so that registering the plugin does not need internet access.
+pub(crate) struct RegisterPluginRequest {
+    plugin: PluginExists,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for RegisterPluginRequest {
+    fn request(&self) -> reqwest::Request {
+        let spec = &self.plugin.plugin;
+        let compute_names = compute_names_of(&self.plugin.compute_resources);
+        if let Some(PluginDescriptor::Json { json }) = self.plugin.descriptor.as_deref() {
+            let filename = format!("{}.json", spec.name());
+            return Request::new(Method::POST, admin_api_url(&self.plugin.url))
+                .auth_token(self.auth_token.as_str())
+                .multipart_file(
+                    &[("compute_names", &compute_names)],
+                    "fname",
+                    &filename,
+                    json.as_bytes(),
+                )
+                .accept_json();
+        }
+        let body = PluginAdminRequest {
+            plugin_name: spec.name().to_string(),
+            plugin_version: spec.version().map(|v| v.to_string()),
+            plugin_store_url: plugin_store_url(&self.plugin.public_cube).to_string(),
+            compute_names,
+        };
+        Request::new(Method::POST, admin_api_url(&self.plugin.url))
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let plugin: models::Plugin = serde_json::from_slice(&body)?;
+        Ok(plugin_entries(
+            &self.plugin.plugin,
+            &self.plugin.url,
+            plugin,
+        ))
+    }
+}
+
+fn plugin_entries(spec: &PluginSpec, url: &CubeUrl, plugin: models::Plugin) -> Entries {
+    let admin_url = admin_api_url(url).join(&format!("{}/", plugin.id)).unwrap();
+    vec![
+        (Dependency::PluginUrl(spec.clone()), plugin.url),
+        (
+            Dependency::PluginAdminUrl(spec.clone()),
+            admin_url.to_string(),
+        ),
+        (
+            Dependency::PluginComputeResourcesUrl(spec.clone()),
+            plugin.compute_resources,
+        ),
+        (
+            Dependency::PluginInstancesUrl(spec.clone()),
+            plugin.instances,
+        ),
+    ]
+}
+
+/// Get the URL of the plugins of the public CUBE, which is where the admin API
+/// looks up plugins to register by name and version.
+fn plugin_store_url(public_cube: &CubeUrl) -> Url {
+    public_cube.to_url().join("plugins/").unwrap()
+}
+
+/// Value of `compute_names` for the admin API, which is a comma-separated list.
+fn compute_names_of(compute_resources: &[ComputeResourceName]) -> String {
+    if compute_resources.is_empty() {
+        DEFAULT_COMPUTE_RESOURCE.to_string()
+    } else {
+        compute_resources
+            .iter()
+            .map(|name| name.as_str())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// A [PendingStep] to make sure that a registered plugin is assigned to the
+/// specified compute resources. See [PluginComputeResourcesFinalizeStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct PluginComputeResourcesFinalize {
+    pub(crate) plugin: PluginSpec,
+    pub(crate) compute_resources: Rc<Vec<ComputeResourceName>>,
+}
+
+impl PendingStep for PluginComputeResourcesFinalize {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let step = PluginComputeResourcesFinalizeStep {
+            plugin: self.plugin.clone(),
+            compute_resources: Rc::clone(&self.compute_resources),
+            compute_resources_url: map
+                .get(Dependency::PluginComputeResourcesUrl(self.plugin.clone()))?,
+            admin_url: map.get(Dependency::PluginAdminUrl(self.plugin.clone()))?,
+            auth_token: map.get(Dependency::AdminToken)?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to assign a plugin to compute resources it is not yet assigned to.
+///
+/// The admin API adds the given compute resources to the plugin, it does not
+/// remove the plugin from other compute resources.
+pub(crate) struct PluginComputeResourcesFinalizeStep {
+    plugin: PluginSpec,
+    compute_resources: Rc<Vec<ComputeResourceName>>,
+    compute_resources_url: Rc<String>,
+    admin_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl PluginComputeResourcesFinalizeStep {
+    fn entries(&self) -> Entries {
+        vec![(
+            Dependency::PluginComputeResources(self.plugin.clone()),
+            compute_names_of(&self.compute_resources),
+        )]
+    }
+}
+
+impl Step for PluginComputeResourcesFinalizeStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = Url::parse(&self.compute_resources_url).unwrap();
+        url.query_pairs_mut().append_pair("limit", "100");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: models::PaginatedComputeResourceList = serde_json::from_slice(&body)?;
+        let is_assigned = self.compute_resources.iter().all(|name| {
+            data.results
+                .iter()
+                .any(|resource| resource.name == name.as_str())
+        });
+        if is_assigned {
+            Ok(Check::Exists(self.entries()))
+        } else {
+            Ok(Check::NeedsModification)
+        }
+    }
+
+    fn modify(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(AssignComputeResourcesRequest {
+            admin_url: Rc::clone(&self.admin_url),
+            auth_token: Rc::clone(&self.auth_token),
+            entries: self.entries(),
+            compute_names: compute_names_of(&self.compute_resources),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![Dependency::PluginComputeResources(self.plugin.clone())]
+    }
+}
+
+pub(crate) struct AssignComputeResourcesRequest {
+    admin_url: Rc<String>,
+    auth_token: Rc<String>,
+    entries: Entries,
+    compute_names: String,
+}
+
+impl StepRequest for AssignComputeResourcesRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = Url::parse(&self.admin_url).unwrap();
+        let body = PluginComputeNamesRequest {
+            compute_names: self.compute_names.clone(),
+        };
+        Request::new(Method::PUT, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, _body: bytes::Bytes) -> serde_json::Result<Entries> {
+        Ok(self.entries.clone())
+    }
+}
@@ -0,0 +1,667 @@
+use crate::deadline::Deadline;
+use crate::extra_models::{
+    PluginInstanceParameterBrief, PluginInstanceParameterList, PluginInstanceStatus,
+    PluginParameterBrief, PluginParameterList,
+};
+use crate::request_builder::RequestBuilder;
+use chris_oag::models;
+use chrisomatic_spec::*;
+use chrisomatic_step::*;
+use chrisomatic_step_macro::AsRefPendingStep;
+use compact_str::CompactString;
+use nonempty::{NonEmpty, nonempty};
+use reqwest::{Method, Request, Url};
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use std::time::Duration;
+
+use super::PluginParams;
+
+/// A [PendingStep] to find a plugin instance of a feed. See [PluginInstanceFindStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct PluginInstanceFind {
+    pub(crate) owner: Username,
+    pub(crate) feed: String,
+    /// Title of the plugin instance, or [None] for the root plugin instance.
+    pub(crate) title: Option<String>,
+    /// Name of the _fs_-type plugin which created the feed.
+    pub(crate) root_plugin: String,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for PluginInstanceFind {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let step = PluginInstanceFindStep {
+            instance: self.clone(),
+            feed_id: map.get(Dependency::FeedId(self.owner.clone(), self.feed.clone()))?,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to search for a plugin instance of a feed by its title. The root
+/// plugin instance is found by its plugin instead, since the feed might not
+/// have been created by _chrisomatic_.
+///
+/// This step does not create plugin instances.
+pub(crate) struct PluginInstanceFindStep {
+    instance: PluginInstanceFind,
+    feed_id: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for PluginInstanceFindStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = self
+            .instance
+            .url
+            .to_url()
+            .join("plugins/instances/search/")
+            .unwrap();
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("feed_id", &self.feed_id);
+            if let Some(title) = &self.instance.title {
+                query.append_pair("title", title);
+            } else {
+                query.append_pair("plugin_name", &self.instance.root_plugin);
+            }
+            // title and plugin_name match substrings, so the exact match
+            // might not be on the first page
+            query.append_pair("limit", "100");
+        }
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: models::PaginatedPluginInstanceList = serde_json::from_slice(&body)?;
+        let check = data
+            .results
+            .into_iter()
+            .find(|instance| match &self.instance.title {
+                Some(title) => &instance.title == title,
+                None => instance.plugin_name == self.instance.root_plugin,
+            })
+            .map(|instance| Check::Exists(vec![(self.target(), instance.id.to_string())]))
+            .unwrap_or(Check::DoesNotExist);
+        Ok(check)
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl PluginInstanceFindStep {
+    fn target(&self) -> Dependency {
+        Dependency::PluginInstanceId(
+            self.instance.owner.clone(),
+            self.instance.feed.clone(),
+            self.instance.title.clone(),
+        )
+    }
+}
+
+/// A [PendingStep] to make sure that a _ds_-type plugin instance exists in a
+/// feed. See [PluginInstanceExistsStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct PluginInstanceExists {
+    pub(crate) owner: Username,
+    pub(crate) feed: String,
+    pub(crate) plugin: PluginSpec,
+    pub(crate) title: String,
+    /// Title of the previous plugin instance, or [None] for the root plugin instance.
+    pub(crate) previous: Option<String>,
+    pub(crate) params: Rc<PluginParams>,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for PluginInstanceExists {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let previous = Dependency::PluginInstanceId(
+            self.owner.clone(),
+            self.feed.clone(),
+            self.previous.clone(),
+        );
+        map.get(Dependency::PluginArgsValid(
+            self.owner.clone(),
+            self.feed.clone(),
+            Some(self.title.clone()),
+        ))?;
+        let step = PluginInstanceExistsStep {
+            instance: self.clone(),
+            previous_id: map.get(previous)?,
+            feed_id: map.get(Dependency::FeedId(self.owner.clone(), self.feed.clone()))?,
+            instances_url: map.get(Dependency::PluginInstancesUrl(self.plugin.clone()))?,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to search for a plugin instance in a feed by its title, plugin,
+/// and previous plugin instance. If not found, the plugin instance is created.
+///
+/// The arguments of a plugin instance which was found are compared by
+/// [PluginInstanceArgsFinalizeStep].
+pub(crate) struct PluginInstanceExistsStep {
+    instance: PluginInstanceExists,
+    previous_id: Rc<String>,
+    feed_id: Rc<String>,
+    instances_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for PluginInstanceExistsStep {
+    fn search(&self) -> reqwest::Request {
+        let plugin = &self.instance.plugin;
+        let mut url = self
+            .instance
+            .url
+            .to_url()
+            .join("plugins/instances/search/")
+            .unwrap();
+        {
+            let mut query = url.query_pairs_mut();
+            query
+                .append_pair("feed_id", &self.feed_id)
+                .append_pair("previous_id", &self.previous_id)
+                .append_pair("title", &self.instance.title)
+                .append_pair("plugin_name", plugin.name());
+            if let Some(version) = plugin.version() {
+                query.append_pair("plugin_version", version);
+            }
+            // title and plugin_name match substrings, so the exact match
+            // might not be on the first page
+            query.append_pair("limit", "100");
+        }
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let plugin = &self.instance.plugin;
+        let data: models::PaginatedPluginInstanceList = serde_json::from_slice(&body)?;
+        let check = data
+            .results
+            .into_iter()
+            .find(|instance| {
+                instance.title == self.instance.title
+                    && instance.plugin_name == plugin.name()
+                    && plugin
+                        .version()
+                        .is_none_or(|version| instance.plugin_version == version)
+            })
+            .map(|instance| Check::Exists(vec![(self.target(), instance.id.to_string())]))
+            .unwrap_or(Check::DoesNotExist);
+        Ok(check)
+    }
+
+    fn create(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(CreatePluginInstanceRequest {
+            target: self.target(),
+            title: self.instance.title.clone(),
+            previous_id: Rc::clone(&self.previous_id),
+            params: Rc::clone(&self.instance.params),
+            instances_url: Rc::clone(&self.instances_url),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl PluginInstanceExistsStep {
+    fn target(&self) -> Dependency {
+        Dependency::PluginInstanceId(
+            self.instance.owner.clone(),
+            self.instance.feed.clone(),
+            Some(self.instance.title.clone()),
+        )
+    }
+}
+
+/// Create a plugin instance of a _ds_-type plugin.
+pub(crate) struct CreatePluginInstanceRequest {
+    target: Dependency,
+    title: String,
+    previous_id: Rc<String>,
+    params: Rc<PluginParams>,
+    instances_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for CreatePluginInstanceRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = Url::parse(&self.instances_url).unwrap();
+        let previous_id: u32 = self.previous_id.parse().unwrap();
+        let mut body = (*self.params).clone();
+        body.insert("title".to_string(), self.title.clone().into());
+        body.insert("previous_id".to_string(), previous_id.into());
+        Request::new(Method::POST, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let instance: models::PluginInstance = serde_json::from_slice(&body)?;
+        Ok(vec![(self.target.clone(), instance.id.to_string())])
+    }
+}
+
+/// A [PendingStep] to make sure that a plugin instance was run with its
+/// specified arguments. See [PluginInstanceArgsFinalizeStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct PluginInstanceArgsFinalize {
+    pub(crate) owner: Username,
+    pub(crate) feed: String,
+    pub(crate) title: String,
+    pub(crate) params: Rc<PluginParams>,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for PluginInstanceArgsFinalize {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let id = Dependency::PluginInstanceId(
+            self.owner.clone(),
+            self.feed.clone(),
+            Some(self.title.clone()),
+        );
+        let step = PluginInstanceArgsFinalizeStep {
+            finalize: self.clone(),
+            id: map.get(id)?,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to compare the parameter values of a plugin instance with its
+/// specified arguments. A plugin instance which was run with different
+/// arguments is invalid, since plugin instances cannot be modified, and
+/// a plugin instance is found by its title.
+pub(crate) struct PluginInstanceArgsFinalizeStep {
+    finalize: PluginInstanceArgsFinalize,
+    id: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for PluginInstanceArgsFinalizeStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = self
+            .finalize
+            .url
+            .to_url()
+            .join(&format!("plugins/instances/{}/parameters/", self.id))
+            .unwrap();
+        url.query_pairs_mut().append_pair("limit", "100");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: PluginInstanceParameterList = serde_json::from_slice(&body)?;
+        let check = match compare_params(&self.finalize.params, &data.results) {
+            Ok(()) => Check::Exists(vec![(self.target(), self.id.to_string())]),
+            Err(reason) => {
+                let f = &self.finalize;
+                Check::Invalid(format!(
+                    "plugin instance \"{}\" of feed \"{}\" (owned by {}) was run with different arguments: {reason}. \
+                    Change its title to run the plugin again.",
+                    f.title, f.feed, f.owner
+                ))
+            }
+        };
+        Ok(check)
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl PluginInstanceArgsFinalizeStep {
+    fn target(&self) -> Dependency {
+        Dependency::PluginInstanceArgs(
+            self.finalize.owner.clone(),
+            self.finalize.feed.clone(),
+            self.finalize.title.clone(),
+        )
+    }
+}
+
+/// Compare the parameter values of a plugin instance with the specified ones.
+fn compare_params(
+    params: &PluginParams,
+    values: &[PluginInstanceParameterBrief],
+) -> Result<(), String> {
+    // sorted for a deterministic error message
+    let params: BTreeMap<_, _> = params.iter().collect();
+    for (name, expected) in params {
+        let actual = values
+            .iter()
+            .find(|v| &v.param_name == name)
+            .map(|v| &v.value);
+        let same = match (actual, expected) {
+            (Some(serde_json::Value::Number(a)), serde_json::Value::Number(b)) => {
+                a.as_f64() == b.as_f64()
+            }
+            (Some(actual), expected) => actual == expected,
+            (None, _) => false,
+        };
+        if !same {
+            let actual = actual
+                .map(|v| v.to_string())
+                .unwrap_or_else(|| "not given".to_string());
+            return Err(format!("\"{name}\" is {actual} instead of {expected}"));
+        }
+    }
+    Ok(())
+}
+
+/// A [PendingStep] to validate the arguments of a plugin instance before it
+/// is created. See [PluginArgsValidateStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct PluginArgsValidate {
+    pub(crate) owner: Username,
+    pub(crate) feed: String,
+    /// Title of the plugin instance, or [None] for the root plugin instance.
+    pub(crate) title: Option<String>,
+    pub(crate) plugin: PluginSpec,
+    pub(crate) args: Rc<HashMap<CompactString, ArgValue>>,
+}
+
+impl PendingStep for PluginArgsValidate {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let step = PluginArgsValidateStep {
+            validate: self.clone(),
+            plugin_url: map.get(Dependency::PluginUrl(self.plugin.clone()))?,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to get the parameters of a plugin and check that every argument
+/// is a parameter of the right type, and that every required parameter
+/// is given.
+///
+/// Validating arguments before creating the plugin instance makes for a
+/// precise error, whereas _CUBE_ would only reject the plugin instance.
+pub(crate) struct PluginArgsValidateStep {
+    validate: PluginArgsValidate,
+    plugin_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for PluginArgsValidateStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = self.parameters_url();
+        // plugins do not have anywhere near this many parameters
+        url.query_pairs_mut().append_pair("limit", "100");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: PluginParameterList = serde_json::from_slice(&body)?;
+        let check = match validate_args(&self.validate.args, &data.results) {
+            Ok(()) => Check::Exists(vec![(self.target(), self.parameters_url().to_string())]),
+            Err(reason) => {
+                let v = &self.validate;
+                let instance = describe_instance(v.title.as_deref());
+                Check::Invalid(format!(
+                    "{instance} of feed \"{}\" (owned by {}) has invalid arguments for plugin {}: {reason}",
+                    v.feed, v.owner, v.plugin
+                ))
+            }
+        };
+        Ok(check)
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl PluginArgsValidateStep {
+    fn target(&self) -> Dependency {
+        Dependency::PluginArgsValid(
+            self.validate.owner.clone(),
+            self.validate.feed.clone(),
+            self.validate.title.clone(),
+        )
+    }
+
+    fn parameters_url(&self) -> Url {
+        Url::parse(&self.plugin_url)
+            .unwrap()
+            .join("parameters/")
+            .unwrap()
+    }
+}
+
+/// Check plugin arguments against the plugin's parameters.
+fn validate_args(
+    args: &HashMap<CompactString, ArgValue>,
+    parameters: &[PluginParameterBrief],
+) -> Result<(), String> {
+    // sorted for a deterministic error message
+    let args: BTreeMap<_, _> = args.iter().collect();
+    for (name, value) in &args {
+        let parameter = parameters
+            .iter()
+            .find(|p| p.name == name.as_str())
+            .ok_or_else(|| format!("unknown parameter \"{name}\""))?;
+        if !accepts(&parameter.param_type, value) {
+            return Err(format!(
+                "parameter \"{name}\" is of type {}, but was given {}",
+                parameter.param_type,
+                serde_json::to_string(value).unwrap()
+            ));
+        }
+    }
+    let missing = parameters
+        .iter()
+        .filter(|p| !p.optional && p.default.as_ref().is_none_or(|d| d.is_null()))
+        .find(|p| !args.keys().any(|name| name.as_str() == p.name));
+    if let Some(parameter) = missing {
+        return Err(format!("missing required parameter \"{}\"", parameter.name));
+    }
+    Ok(())
+}
+
+/// Returns `true` if a value can be given to a parameter of the type.
+fn accepts(param_type: &str, value: &ArgValue) -> bool {
+    match value {
+        ArgValue::Stringish(_) => matches!(param_type, "string" | "path" | "unextpath"),
+        ArgValue::Boolean(_) => param_type == "boolean",
+        ArgValue::Integer(_) => matches!(param_type, "integer" | "float"),
+        ArgValue::Float(_) => param_type == "float",
+    }
+}
+
+/// A [PendingStep] to wait for a plugin instance to finish. See [PluginInstanceWaitStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct PluginInstanceWait {
+    pub(crate) owner: Username,
+    pub(crate) feed: String,
+    /// Title of the plugin instance, or [None] for the root plugin instance.
+    pub(crate) title: Option<String>,
+    pub(crate) deadline: Rc<Deadline>,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for PluginInstanceWait {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let id =
+            Dependency::PluginInstanceId(self.owner.clone(), self.feed.clone(), self.title.clone());
+        let step = PluginInstanceWaitStep {
+            wait: self.clone(),
+            id: map.get(id)?,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to get the status of a plugin instance until it has finished.
+/// A plugin instance which finished with an error or was cancelled is an error.
+pub(crate) struct PluginInstanceWaitStep {
+    wait: PluginInstanceWait,
+    id: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for PluginInstanceWaitStep {
+    fn search(&self) -> reqwest::Request {
+        let url = self
+            .wait
+            .url
+            .to_url()
+            .join(&format!("plugins/instances/{}/", self.id))
+            .unwrap();
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let instance: PluginInstanceStatus = serde_json::from_slice(&body)?;
+        let check = match instance.status.as_str() {
+            "finishedSuccessfully" => Check::Exists(vec![(self.target(), instance.status)]),
+            "finishedWithError" | "cancelled" => Check::Failed(format!(
+                "{} of feed \"{}\" (owned by {}) has status {}",
+                describe_instance(self.wait.title.as_deref()),
+                self.wait.feed,
+                self.wait.owner,
+                instance.status
+            )),
+            _ => Check::Pending,
+        };
+        Ok(check)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.wait.deadline.remaining()
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl PluginInstanceWaitStep {
+    fn target(&self) -> Dependency {
+        Dependency::PluginInstanceFinished(
+            self.wait.owner.clone(),
+            self.wait.feed.clone(),
+            self.wait.title.clone(),
+        )
+    }
+}
+
+/// Describe a plugin instance by its title for error messages.
+fn describe_instance(title: Option<&str>) -> String {
+    title
+        .map(|title| format!("plugin instance \"{title}\""))
+        .unwrap_or_else(|| "root plugin instance".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    /// Parameters of a plugin with one parameter of each type, where `--name`
+    /// is required.
+    #[fixture]
+    fn parameters() -> Vec<PluginParameterBrief> {
+        let parameter = |name: &str, param_type: &str, optional: bool| PluginParameterBrief {
+            name: name.to_string(),
+            param_type: param_type.to_string(),
+            optional,
+            default: None,
+        };
+        vec![
+            parameter("name", "string", false),
+            parameter("count", "integer", true),
+            parameter("ratio", "float", true),
+            parameter("verbose", "boolean", true),
+            parameter("inputdir", "path", true),
+        ]
+    }
+
+    #[rstest]
+    #[case(&[("name", ArgValue::Stringish("a".to_string()))], None)]
+    #[case(
+        &[("name", ArgValue::Stringish("a".to_string())), ("ratio", ArgValue::Integer(1))],
+        None
+    )]
+    #[case(
+        &[("name", ArgValue::Stringish("a".to_string())), ("nope", ArgValue::Boolean(true))],
+        Some("unknown parameter \"nope\"")
+    )]
+    #[case(
+        &[("name", ArgValue::Stringish("a".to_string())), ("count", ArgValue::Float(1.5))],
+        Some("parameter \"count\" is of type integer, but was given 1.5")
+    )]
+    #[case(
+        &[("name", ArgValue::Stringish("a".to_string())), ("count", ArgValue::Stringish("1".to_string()))],
+        Some("parameter \"count\" is of type integer, but was given \"1\"")
+    )]
+    #[case(
+        &[("name", ArgValue::Stringish("a".to_string())), ("ratio", ArgValue::Boolean(false))],
+        Some("parameter \"ratio\" is of type float, but was given false")
+    )]
+    #[case(
+        &[("name", ArgValue::Stringish("a".to_string())), ("verbose", ArgValue::Integer(1))],
+        Some("parameter \"verbose\" is of type boolean, but was given 1")
+    )]
+    #[case(
+        &[("name", ArgValue::Boolean(true))],
+        Some("parameter \"name\" is of type string, but was given true")
+    )]
+    #[case(
+        &[("inputdir", ArgValue::Stringish("home/alice".to_string()))],
+        Some("missing required parameter \"name\"")
+    )]
+    fn test_validate_args(
+        parameters: Vec<PluginParameterBrief>,
+        #[case] args: &[(&str, ArgValue)],
+        #[case] expected: Option<&str>,
+    ) {
+        let args: HashMap<_, _> = args
+            .iter()
+            .map(|(name, value)| (CompactString::new(name), value.clone()))
+            .collect();
+        let actual = validate_args(&args, &parameters);
+        assert_eq!(actual, expected.map(|s| s.to_string()).map_or(Ok(()), Err));
+    }
+}
@@ -0,0 +1,214 @@
+use crate::extra_models::{TagCreateRequest, TaggingCreateRequest};
+use crate::request_builder::RequestBuilder;
+use chris_oag::models;
+use chrisomatic_spec::*;
+use chrisomatic_step::*;
+use chrisomatic_step_macro::AsRefPendingStep;
+use nonempty::{NonEmpty, nonempty};
+use reqwest::{Method, Request, Url};
+use std::rc::Rc;
+
+/// Color of tags created by _chrisomatic_.
+const TAG_COLOR: &str = "#808080";
+
+/// A [PendingStep] to make sure that a tag exists. See [TagExistsStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct TagExists {
+    pub(crate) owner: Username,
+    pub(crate) name: String,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for TagExists {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let step = TagExistsStep {
+            tag: self.clone(),
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to search for a tag by name and owner. If not found, the tag is
+/// created by its owner.
+pub(crate) struct TagExistsStep {
+    tag: TagExists,
+    auth_token: Rc<String>,
+}
+
+impl Step for TagExistsStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = self.tag.url.to_url().join("tags/search/").unwrap();
+        url.query_pairs_mut()
+            .append_pair("name", &self.tag.name)
+            .append_pair("owner_username", self.tag.owner.as_str())
+            // name matches substrings, so the exact match might not be on the first page
+            .append_pair("limit", "100");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: models::PaginatedTagList = serde_json::from_slice(&body)?;
+        let check = data
+            .results
+            .into_iter()
+            .find(|tag| tag.name == self.tag.name && tag.owner_username == self.tag.owner.as_str())
+            .map(|tag| Check::Exists(vec![(self.target(), tag.id.to_string())]))
+            .unwrap_or(Check::DoesNotExist);
+        Ok(check)
+    }
+
+    fn create(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(CreateTagRequest {
+            target: self.target(),
+            tag: self.tag.clone(),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl TagExistsStep {
+    fn target(&self) -> Dependency {
+        Dependency::TagId(self.tag.owner.clone(), self.tag.name.clone())
+    }
+}
+
+/// Create a tag.
+pub(crate) struct CreateTagRequest {
+    target: Dependency,
+    tag: TagExists,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for CreateTagRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = self.tag.url.to_url().join("tags/").unwrap();
+        let body = TagCreateRequest {
+            name: &self.tag.name,
+            color: TAG_COLOR,
+        };
+        Request::new(Method::POST, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let tag: models::Tag = serde_json::from_slice(&body)?;
+        Ok(vec![(self.target.clone(), tag.id.to_string())])
+    }
+}
+
+/// A [PendingStep] to make sure that a feed is tagged with a tag of the
+/// feed's owner. See [FeedTagStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct FeedTag {
+    pub(crate) owner: Username,
+    pub(crate) name: String,
+    pub(crate) tag: String,
+}
+
+impl PendingStep for FeedTag {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let (owner, name) = (self.owner.clone(), self.name.clone());
+        let step = FeedTagStep {
+            tagging: self.clone(),
+            taggings_url: map.get(Dependency::FeedTaggingsUrl(owner, name))?,
+            tag_id: map.get(Dependency::TagId(self.owner.clone(), self.tag.clone()))?,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to search for the tagging of a feed with a tag. If not found,
+/// the feed is tagged.
+pub(crate) struct FeedTagStep {
+    tagging: FeedTag,
+    taggings_url: Rc<String>,
+    tag_id: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for FeedTagStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = Url::parse(&self.taggings_url).unwrap();
+        url.query_pairs_mut().append_pair("limit", "100");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: models::PaginatedTaggingList = serde_json::from_slice(&body)?;
+        let check = data
+            .results
+            .into_iter()
+            .find(|tagging| tagging.tag_id.to_string() == *self.tag_id)
+            .map(|tagging| Check::Exists(vec![(self.target(), tagging.url)]))
+            .unwrap_or(Check::DoesNotExist);
+        Ok(check)
+    }
+
+    fn create(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(CreateTaggingRequest {
+            target: self.target(),
+            taggings_url: Rc::clone(&self.taggings_url),
+            tag_id: Rc::clone(&self.tag_id),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl FeedTagStep {
+    fn target(&self) -> Dependency {
+        let t = &self.tagging;
+        Dependency::FeedTagged(t.owner.clone(), t.name.clone(), t.tag.clone())
+    }
+}
+
+/// Tag a feed.
+pub(crate) struct CreateTaggingRequest {
+    target: Dependency,
+    taggings_url: Rc<String>,
+    tag_id: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for CreateTaggingRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = Url::parse(&self.taggings_url).unwrap();
+        let body = TaggingCreateRequest {
+            tag_id: self.tag_id.parse().unwrap(),
+        };
+        Request::new(Method::POST, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let tagging: models::Tagging = serde_json::from_slice(&body)?;
+        Ok(vec![(self.target.clone(), tagging.url)])
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{extra_models::RootResponse, request_builder::RequestBuilder};
+use crate::{
+    extra_models::{GroupUserList, RootResponse, UserIsStaff},
+    request_builder::RequestBuilder,
+};
 use chris_oag::models;
 use chrisomatic_spec::*;
 use chrisomatic_step::*;
@@ -12,6 +15,8 @@ use std::rc::Rc;
 pub(crate) struct UserExists {
     pub(crate) username: Username,
     pub(crate) details: Rc<UserDetails>,
+    /// Whether the user should be created as a staff user by the admin user.
+    pub(crate) staff: bool,
     pub(crate) url: CubeUrl,
 }
 
@@ -22,7 +27,16 @@ impl PendingStep for UserExists {
             "Duplicate UserExists step for \"{}\"",
             &self.username
         );
-        ok_step(UserExistsStep(self.clone()))
+        let admin_token = if self.staff {
+            Some(map.get(Dependency::AdminToken)?)
+        } else {
+            None
+        };
+        let step = UserExistsStep {
+            user: self.clone(),
+            admin_token,
+        };
+        ok_step(step)
     }
 }
 
@@ -31,14 +45,19 @@ impl PendingStep for UserExists {
 /// - Obtains the user's [Dependency::AuthToken]
 /// - Creates the user, producing the [Dependency::UserUrl], [Dependency::UserGroupsUrl],
 ///   and [Dependency::UserEmail].
-pub(crate) struct UserExistsStep(UserExists);
+///
+/// A staff user is created using the admin user's token.
+pub(crate) struct UserExistsStep {
+    user: UserExists,
+    admin_token: Option<Rc<String>>,
+}
 
 impl Step for UserExistsStep {
     fn search(&self) -> reqwest::Request {
-        let url = self.0.url.to_url().join("auth-token/").unwrap();
+        let url = self.user.url.to_url().join("auth-token/").unwrap();
         let body = models::AuthTokenRequest {
-            username: self.0.username.to_string(),
-            password: self.0.details.password.to_string(),
+            username: self.user.username.to_string(),
+            password: self.user.details.password.to_string(),
         };
         Request::new(Method::POST, url)
             .json(&body)
@@ -57,32 +76,29 @@ impl Step for UserExistsStep {
     }
 
     fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
-        deserialize_auth_token(&self.0.username, body).map(Check::Exists)
+        deserialize_auth_token(&self.user.username, body).map(Check::Exists)
     }
 
     fn create(&self) -> Option<Box<dyn StepRequest>> {
-        Some(Box::new(CreateUserRequest::from(self.0.clone())))
+        Some(Box::new(CreateUserRequest {
+            url: self.user.url.clone(),
+            username: self.user.username.clone(),
+            details: Rc::clone(&self.user.details),
+            admin_token: self.admin_token.clone(),
+        }))
     }
 
     fn provides(&self) -> NonEmpty<Dependency> {
-        nonempty![Dependency::UserExists(self.0.username.clone())]
+        nonempty![Dependency::UserExists(self.user.username.clone())]
     }
 }
 
+/// Create a user. If an admin token is given, the user is created as a staff user.
 pub(crate) struct CreateUserRequest {
     url: CubeUrl,
     username: Username,
     details: Rc<UserDetails>,
-}
-
-impl From<UserExists> for CreateUserRequest {
-    fn from(value: UserExists) -> Self {
-        CreateUserRequest {
-            username: value.username,
-            url: value.url,
-            details: value.details,
-        }
-    }
+    admin_token: Option<Rc<String>>,
 }
 
 impl StepRequest for CreateUserRequest {
@@ -92,12 +108,34 @@ impl StepRequest for CreateUserRequest {
             username: Some(self.username.to_string()),
             email: self.details.email.to_string(),
             password: self.details.password.to_string(),
-            is_staff: None, // very bad bad bad bad bad
+            // only honored when the request is made by an admin
+            is_staff: self.admin_token.as_ref().map(|_| true),
         };
-        Request::new(Method::POST, url)
-            .json(&body)
-            .unwrap()
-            .accept_json()
+        let request = Request::new(Method::POST, url);
+        let request = if let Some(admin_token) = &self.admin_token {
+            request.auth_token(admin_token.as_str())
+        } else {
+            request
+        };
+        request.json(&body).unwrap().accept_json()
+    }
+
+    fn invalid_status(&self, status: StatusCode, body: &[u8]) -> Option<String> {
+        if status != StatusCode::BAD_REQUEST {
+            return None;
+        }
+        let reason = String::from_utf8_lossy(body);
+        let reason = if is_duplicate_username(body) {
+            // the user was not found by UserExistsStep because its password is wrong
+            format!(
+                "user \"{}\" cannot be created, it probably exists with a different \
+                password (set `global.reset_password = true` to reset it): {reason}",
+                self.username
+            )
+        } else {
+            format!("user \"{}\" cannot be created: {reason}", self.username)
+        };
+        Some(reason)
     }
 
     fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
@@ -105,6 +143,15 @@ impl StepRequest for CreateUserRequest {
     }
 }
 
+/// Returns `true` if the body of a response to creating a user says that
+/// its username is taken, e.g. `{"username": ["This field must be unique."]}`.
+fn is_duplicate_username(body: &[u8]) -> bool {
+    serde_json::from_slice::<serde_json::Value>(body)
+        .ok()
+        .and_then(|errors| errors.get("username").map(|e| e.to_string()))
+        .is_some_and(|e| e.contains("unique") || e.contains("already exists"))
+}
+
 fn deserialize_user_response(
     username: &Username,
     body: bytes::Bytes,
@@ -189,11 +236,15 @@ impl PendingStep for UserGetUrl {
             };
             ok_step(step)
         } else {
-            let step = UserExistsStep(UserExists {
-                username: self.username.clone(),
-                details: Rc::clone(&self.details),
-                url: self.url.clone(),
-            });
+            let step = UserExistsStep {
+                user: UserExists {
+                    username: self.username.clone(),
+                    details: Rc::clone(&self.details),
+                    staff: false,
+                    url: self.url.clone(),
+                },
+                admin_token: None,
+            };
             ok_step(step)
         }
     }
@@ -231,6 +282,7 @@ impl Step for UserGetUrlStep {
             url: self.url.clone(),
             username: self.username.clone(),
             details: Rc::clone(&self.details),
+            admin_token: None,
         }))
     }
 
@@ -298,6 +350,7 @@ impl Step for UserGetDetailsStep {
             url: self.url.clone(),
             username: self.username.clone(),
             details: Rc::clone(&self.details),
+            admin_token: None,
         }))
     }
 
@@ -373,7 +426,8 @@ impl Step for UserDetailsFinalizeStep {
             username: None,
             email: self.email.to_string(),
             password: self.password.to_string(),
-            is_staff: None, // very bad bad bad bad bad
+            // users cannot promote themselves, see UserStaffFinalizeStep
+            is_staff: None,
         };
         Request::new(Method::PUT, url)
             .auth_token(self.auth_token.as_str())
@@ -393,3 +447,283 @@ impl Step for UserDetailsFinalizeStep {
         ]
     }
 }
+
+/// A [PendingStep] to make sure that a user is a staff user. See [UserStaffFinalizeStep].
+#[derive(Clone, Debug, AsRefPendingStep)]
+pub(crate) struct UserStaffFinalize {
+    pub(crate) username: Username,
+    pub(crate) details: Rc<UserDetails>,
+}
+
+impl PendingStep for UserStaffFinalize {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let step = UserStaffFinalizeStep {
+            user: self.clone(),
+            user_url: map.get(Dependency::UserUrl(self.username.clone()))?,
+            admin_token: map.get(Dependency::AdminToken)?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to check whether a user is a staff user, promoting the user
+/// using the admin user's token if not.
+///
+/// Staff users are never demoted.
+pub(crate) struct UserStaffFinalizeStep {
+    user: UserStaffFinalize,
+    user_url: Rc<String>,
+    admin_token: Rc<String>,
+}
+
+impl Step for UserStaffFinalizeStep {
+    fn search(&self) -> reqwest::Request {
+        let url = Url::parse(&self.user_url).unwrap();
+        Request::new(Method::GET, url)
+            .auth_token(self.admin_token.as_str())
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let user: UserIsStaff = serde_json::from_slice(&body)?;
+        let check = if user.is_staff {
+            Check::Exists(vec![(self.target(), "true".to_string())])
+        } else {
+            Check::NeedsModification
+        };
+        Ok(check)
+    }
+
+    fn modify(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(PromoteUserRequest {
+            target: self.target(),
+            details: Rc::clone(&self.user.details),
+            user_url: Rc::clone(&self.user_url),
+            admin_token: Rc::clone(&self.admin_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl UserStaffFinalizeStep {
+    fn target(&self) -> Dependency {
+        Dependency::UserStaff(self.user.username.clone())
+    }
+}
+
+/// Make an existing user a staff user.
+pub(crate) struct PromoteUserRequest {
+    target: Dependency,
+    details: Rc<UserDetails>,
+    user_url: Rc<String>,
+    admin_token: Rc<String>,
+}
+
+impl StepRequest for PromoteUserRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = Url::parse(&self.user_url).unwrap();
+        let body = models::UserRequest {
+            username: None,
+            email: self.details.email.to_string(),
+            password: self.details.password.to_string(),
+            is_staff: Some(true),
+        };
+        Request::new(Method::PUT, url)
+            .auth_token(self.admin_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let user: UserIsStaff = serde_json::from_slice(&body)?;
+        Ok(vec![(self.target.clone(), user.is_staff.to_string())])
+    }
+}
+
+/// A [PendingStep] to find the URL of a user using the admin user's privileges,
+/// in case its password is not as specified. See [UserFindStep].
+#[derive(Clone, Debug, AsRefPendingStep)]
+pub(crate) struct UserFind {
+    pub(crate) username: Username,
+    /// The group which every user is a member of.
+    pub(crate) all_users: Group,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for UserFind {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let step = UserFindStep {
+            user: self.clone(),
+            group_users_url: map.get(Dependency::GroupUsersUrl(self.all_users.clone()))?,
+            admin_token: map.get(Dependency::AdminToken)?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to search for a user in the group of all users, producing its
+/// [Dependency::UserFound]. If not found, the user does not exist, so its
+/// password does not need to be reset.
+pub(crate) struct UserFindStep {
+    user: UserFind,
+    group_users_url: Rc<String>,
+    admin_token: Rc<String>,
+}
+
+impl Step for UserFindStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = Url::parse(&self.group_users_url)
+            .unwrap()
+            .join("search/")
+            .unwrap();
+        url.query_pairs_mut()
+            .append_pair("user_username", self.user.username.as_str())
+            .append_pair("limit", "1");
+        Request::new(Method::GET, url)
+            .auth_token(self.admin_token.as_str())
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: GroupUserList = serde_json::from_slice(&body)?;
+        let username = &self.user.username;
+        let entry = data
+            .results
+            .into_iter()
+            .find(|member| member.user_username == username.as_str())
+            .map(|member| {
+                let url = self.user.url.to_url();
+                let user_url = url.join(&format!("users/{}/", member.user_id)).unwrap();
+                user_url.to_string()
+            })
+            .unwrap_or_else(|| USER_NOT_FOUND.to_string());
+        Ok(Check::Exists(vec![(
+            Dependency::UserFound(username.clone()),
+            entry,
+        )]))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![Dependency::UserFound(self.user.username.clone())]
+    }
+}
+
+/// Value of [Dependency::UserFound] for a user which does not exist.
+const USER_NOT_FOUND: &str = "none";
+
+/// A [PendingStep] to reset the password of an existing user if it is not as
+/// specified. See [UserPasswordResetStep].
+#[derive(Clone, Debug, AsRefPendingStep)]
+pub(crate) struct UserPasswordReset {
+    pub(crate) username: Username,
+    pub(crate) details: Rc<UserDetails>,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for UserPasswordReset {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let user_url = map.get(Dependency::UserFound(self.username.clone()))?;
+        if user_url.as_str() == USER_NOT_FOUND {
+            return Ok(None);
+        }
+        let step = UserPasswordResetStep {
+            user: self.clone(),
+            user_url,
+            admin_token: map.get(Dependency::AdminToken)?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to try authenticating as an existing user with the specified
+/// password. If unsuccessful, the password is reset using the admin user's
+/// privileges.
+pub(crate) struct UserPasswordResetStep {
+    user: UserPasswordReset,
+    user_url: Rc<String>,
+    admin_token: Rc<String>,
+}
+
+impl Step for UserPasswordResetStep {
+    fn search(&self) -> reqwest::Request {
+        let url = self.user.url.to_url().join("auth-token/").unwrap();
+        let body = models::AuthTokenRequest {
+            username: self.user.username.to_string(),
+            password: self.user.details.password.to_string(),
+        };
+        Request::new(Method::POST, url)
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn check_status(&self, status: reqwest::StatusCode) -> StatusCheck {
+        if status.is_success() || status == StatusCode::BAD_REQUEST {
+            StatusCheck::Exists
+        } else {
+            StatusCheck::Error
+        }
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        // the body of a 400 response is an error message instead of a token
+        let check = if serde_json::from_slice::<models::AuthToken>(&body).is_ok() {
+            Check::Exists(vec![(self.target(), "ok".to_string())])
+        } else {
+            Check::NeedsModification
+        };
+        Ok(check)
+    }
+
+    fn modify(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(ResetPasswordRequest {
+            target: self.target(),
+            details: Rc::clone(&self.user.details),
+            user_url: Rc::clone(&self.user_url),
+            admin_token: Rc::clone(&self.admin_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl UserPasswordResetStep {
+    fn target(&self) -> Dependency {
+        Dependency::UserPassword(self.user.username.clone())
+    }
+}
+
+/// Set the password of an existing user.
+pub(crate) struct ResetPasswordRequest {
+    target: Dependency,
+    details: Rc<UserDetails>,
+    user_url: Rc<String>,
+    admin_token: Rc<String>,
+}
+
+impl StepRequest for ResetPasswordRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = Url::parse(&self.user_url).unwrap();
+        let body = models::UserRequest {
+            username: None,
+            email: self.details.email.to_string(),
+            password: self.details.password.to_string(),
+            is_staff: None,
+        };
+        Request::new(Method::PUT, url)
+            .auth_token(self.admin_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, _body: bytes::Bytes) -> serde_json::Result<Entries> {
+        Ok(vec![(self.target.clone(), "reset".to_string())])
+    }
+}
@@ -0,0 +1,316 @@
+use crate::request_builder::RequestBuilder;
+use chris_oag::models;
+use chrisomatic_spec::*;
+use chrisomatic_step::*;
+use chrisomatic_step_macro::AsRefPendingStep;
+use nonempty::{NonEmpty, nonempty};
+use reqwest::{Method, Request, Url};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// Content of a single user file to upload. Unlike [UserFileContent], it
+/// cannot be a directory, which is expanded into its files by the plan.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum UploadContent {
+    /// Text content written inline.
+    Text(String),
+    /// Path of a local file.
+    Source(PathBuf),
+}
+
+/// A [PendingStep] to make sure that a user file exists. See [UserFileExistsStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct UserFileExists {
+    pub(crate) owner: Username,
+    pub(crate) path: String,
+    pub(crate) content: Rc<UploadContent>,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for UserFileExists {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let step = UserFileExistsStep {
+            userfile: self.clone(),
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+            content: RefCell::new(None),
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to search for a user file by path. If not found, the file is
+/// uploaded by its owner.
+pub(crate) struct UserFileExistsStep {
+    userfile: UserFileExists,
+    auth_token: Rc<String>,
+    /// Content to upload, read when the file is not found.
+    content: RefCell<Option<LocalContent>>,
+}
+
+impl Step for UserFileExistsStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = self
+            .userfile
+            .url
+            .to_url()
+            .join("userfiles/search/")
+            .unwrap();
+        url.query_pairs_mut()
+            .append_pair("fname_exact", &self.userfile.path)
+            .append_pair("limit", "1");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: models::PaginatedUserFileList = serde_json::from_slice(&body)?;
+        if let Some(file) = data
+            .results
+            .into_iter()
+            .find(|file| file.fname == self.userfile.path)
+        {
+            return Ok(Check::Exists(found_entries(&self.userfile.path, file)));
+        }
+        match read_content(&self.userfile.content) {
+            Ok(content) => {
+                self.content.replace(Some(content));
+                Ok(Check::DoesNotExist)
+            }
+            Err(e) => Ok(Check::Invalid(e)),
+        }
+    }
+
+    fn create(&self) -> Option<Box<dyn StepRequest>> {
+        self.content.take().map(|content| {
+            Box::new(UploadUserFileRequest {
+                path: self.userfile.path.clone(),
+                url: self.userfile.url.clone(),
+                auth_token: Rc::clone(&self.auth_token),
+                content,
+            }) as Box<dyn StepRequest>
+        })
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        let path = &self.userfile.path;
+        nonempty![
+            Dependency::UserFileUrl(path.clone()),
+            Dependency::UserFileResourceUrl(path.clone()),
+            Dependency::UserFileSize(path.clone())
+        ]
+    }
+}
+
+fn found_entries(path: &str, file: models::UserFile) -> Entries {
+    vec![
+        (Dependency::UserFileUrl(path.to_string()), file.url),
+        (
+            Dependency::UserFileResourceUrl(path.to_string()),
+            file.file_resource,
+        ),
+        (
+            Dependency::UserFileSize(path.to_string()),
+            file.fsize.to_string(),
+        ),
+    ]
+}
+
+/// Upload a user file.
+pub(crate) struct UploadUserFileRequest {
+    path: String,
+    url: CubeUrl,
+    auth_token: Rc<String>,
+    content: LocalContent,
+}
+
+impl StepRequest for UploadUserFileRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = self.url.to_url().join("userfiles/").unwrap();
+        let path = self.path.as_str();
+        Request::new(Method::POST, url)
+            .auth_token(self.auth_token.as_str())
+            .multipart_file(
+                &[("upload_path", path)],
+                "fname",
+                filename_of(path),
+                &self.content.bytes,
+            )
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let file: models::UserFile = serde_json::from_slice(&body)?;
+        // the file was just uploaded, so its content is known to be correct.
+        let content = (
+            Dependency::UserFileContent(self.path.clone()),
+            self.content.digest.clone(),
+        );
+        let mut entries = found_entries(&self.path, file);
+        entries.push(content);
+        Ok(entries)
+    }
+}
+
+/// A [PendingStep] to make sure that the content of a user file is the same
+/// as what is specified. See [UserFileContentFinalizeStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct UserFileContentFinalize {
+    pub(crate) owner: Username,
+    pub(crate) path: String,
+    pub(crate) content: Rc<UploadContent>,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for UserFileContentFinalize {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        if map.contains_key(&Dependency::UserFileContent(self.path.clone())) {
+            return Ok(None);
+        }
+        let step = UserFileContentFinalizeStep {
+            userfile: self.clone(),
+            userfile_url: map.get(Dependency::UserFileUrl(self.path.clone()))?,
+            file_resource: map.get(Dependency::UserFileResourceUrl(self.path.clone()))?,
+            fsize: map.get(Dependency::UserFileSize(self.path.clone()))?,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to compare the content of an existing user file with what is
+/// specified, replacing the file if different. CUBE does not accept new
+/// content for an existing user file, so it is replaced by deleting it and
+/// uploading it again, which gives it a new URL.
+///
+/// The size of the file found by [UserFileExistsStep] is compared with the
+/// size of the specified content first. Only if they are the same is the file
+/// downloaded to compare SHA-256 digests, otherwise its metadata is fetched
+/// just to confirm that it still exists.
+pub(crate) struct UserFileContentFinalizeStep {
+    userfile: UserFileContentFinalize,
+    userfile_url: Rc<String>,
+    file_resource: Rc<String>,
+    fsize: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for UserFileContentFinalizeStep {
+    fn search(&self) -> reqwest::Request {
+        if self.same_size() == Ok(true) {
+            let url = Url::parse(&self.file_resource).unwrap();
+            Request::new(Method::GET, url).auth_token(self.auth_token.as_str())
+        } else {
+            let url = Url::parse(&self.userfile_url).unwrap();
+            Request::new(Method::GET, url)
+                .auth_token(self.auth_token.as_str())
+                .accept_json()
+        }
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let same_size = match self.same_size() {
+            Ok(same_size) => same_size,
+            Err(e) => return Ok(Check::Invalid(e)),
+        };
+        let content = match read_content(&self.userfile.content) {
+            Ok(content) => content,
+            Err(e) => return Ok(Check::Invalid(e)),
+        };
+        if same_size && format!("{:x}", Sha256::digest(&body)) == content.digest {
+            return Ok(Check::Exists(vec![(self.target(), content.digest)]));
+        }
+        let delete = DeleteUserFileRequest {
+            userfile_url: Rc::clone(&self.userfile_url),
+            auth_token: Rc::clone(&self.auth_token),
+        };
+        let upload = UploadUserFileRequest {
+            path: self.userfile.path.clone(),
+            url: self.userfile.url.clone(),
+            auth_token: Rc::clone(&self.auth_token),
+            content,
+        };
+        Ok(Check::NeedsReplacement(vec![
+            Box::new(delete),
+            Box::new(upload),
+        ]))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        let path = &self.userfile.path;
+        nonempty![
+            self.target(),
+            Dependency::UserFileUrl(path.clone()),
+            Dependency::UserFileResourceUrl(path.clone()),
+            Dependency::UserFileSize(path.clone())
+        ]
+    }
+}
+
+impl UserFileContentFinalizeStep {
+    fn target(&self) -> Dependency {
+        Dependency::UserFileContent(self.userfile.path.clone())
+    }
+
+    /// Whether the specified content has the same size as the existing file.
+    fn same_size(&self) -> Result<bool, String> {
+        local_size(&self.userfile.content).map(|size| size.to_string() == *self.fsize)
+    }
+}
+
+/// Delete a user file.
+pub(crate) struct DeleteUserFileRequest {
+    userfile_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for DeleteUserFileRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = Url::parse(&self.userfile_url).unwrap();
+        Request::new(Method::DELETE, url).auth_token(self.auth_token.as_str())
+    }
+
+    fn deserialize(&self, _body: bytes::Bytes) -> serde_json::Result<Entries> {
+        Ok(vec![])
+    }
+}
+
+/// Specified content of a user file, read into memory to be uploaded.
+struct LocalContent {
+    bytes: bytes::Bytes,
+    /// Hex-encoded SHA-256 digest of `bytes`.
+    digest: String,
+}
+
+/// Read the specified content, hashing it as it is read.
+///
+/// Local files are checked to be readable by the CLI before anything is run,
+/// but may have changed since, so an error is returned as the reason why the
+/// step is invalid.
+fn read_content(content: &UploadContent) -> Result<LocalContent, String> {
+    let bytes = match content {
+        UploadContent::Text(text) => bytes::Bytes::from(text.clone()),
+        UploadContent::Source(source) => std::fs::read(source)
+            .map_err(|e| format!("Cannot read file \"{}\": {e}", source.display()))?
+            .into(),
+    };
+    let digest = format!("{:x}", Sha256::digest(&bytes));
+    Ok(LocalContent { bytes, digest })
+}
+
+/// Get the size of the specified content without reading it.
+fn local_size(content: &UploadContent) -> Result<u64, String> {
+    match content {
+        UploadContent::Text(text) => Ok(text.len() as u64),
+        UploadContent::Source(source) => std::fs::metadata(source)
+            .map(|metadata| metadata.len())
+            .map_err(|e| format!("Cannot read file \"{}\": {e}", source.display())),
+    }
+}
+
+/// Get the name of the file at the path.
+fn filename_of(path: &str) -> &str {
+    path.rsplit_once('/').map(|(_, name)| name).unwrap_or(path)
+}
@@ -0,0 +1,437 @@
+use crate::{
+    deadline::Deadline,
+    extra_models::{PluginInstanceWorkflowList, WorkflowCreateRequest, WorkflowJobCounts},
+    request_builder::RequestBuilder,
+};
+use chris_oag::models;
+use chrisomatic_spec::*;
+use chrisomatic_step::*;
+use chrisomatic_step_macro::AsRefPendingStep;
+use nonempty::{NonEmpty, nonempty};
+use reqwest::{Method, Request, Url};
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// A [PendingStep] to find the workflows which were run after a plugin
+/// instance. See [WorkflowsAfterStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct WorkflowsAfter {
+    pub(crate) owner: Username,
+    pub(crate) feed: String,
+    /// Title of the plugin instance, or [None] for the root plugin instance of the feed.
+    pub(crate) previous: Option<String>,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for WorkflowsAfter {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let previous = Dependency::PluginInstanceId(
+            self.owner.clone(),
+            self.feed.clone(),
+            self.previous.clone(),
+        );
+        let step = WorkflowsAfterStep {
+            after: self.clone(),
+            previous_id: map.get(previous)?,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to list the IDs of the workflows of the plugin instances which
+/// were run after a plugin instance, so that a workflow is only found if it
+/// was run after the same plugin instance.
+///
+/// This step does not create anything.
+pub(crate) struct WorkflowsAfterStep {
+    after: WorkflowsAfter,
+    previous_id: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for WorkflowsAfterStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = self
+            .after
+            .url
+            .to_url()
+            .join("plugins/instances/search/")
+            .unwrap();
+        url.query_pairs_mut()
+            .append_pair("previous_id", &self.previous_id)
+            .append_pair("limit", "100");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: PluginInstanceWorkflowList = serde_json::from_slice(&body)?;
+        let ids: Vec<_> = data
+            .results
+            .into_iter()
+            .filter_map(|instance| instance.workflow_id)
+            .map(|id| id.to_string())
+            .collect();
+        Ok(Check::Exists(vec![(self.target(), ids.join(","))]))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl WorkflowsAfterStep {
+    fn target(&self) -> Dependency {
+        Dependency::WorkflowsAfter(
+            self.after.owner.clone(),
+            self.after.feed.clone(),
+            self.after.previous.clone(),
+        )
+    }
+}
+
+/// A [PendingStep] to make sure that a pipeline was run on a feed. See [WorkflowExistsStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct WorkflowExists {
+    pub(crate) owner: Username,
+    pub(crate) feed: String,
+    pub(crate) pipeline: String,
+    pub(crate) title: String,
+    /// Title of the plugin instance to run the pipeline after, or [None] for
+    /// the root plugin instance of the feed.
+    pub(crate) previous: Option<String>,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for WorkflowExists {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let previous = Dependency::PluginInstanceId(
+            self.owner.clone(),
+            self.feed.clone(),
+            self.previous.clone(),
+        );
+        let after = Dependency::WorkflowsAfter(
+            self.owner.clone(),
+            self.feed.clone(),
+            self.previous.clone(),
+        );
+        let step = WorkflowExistsStep {
+            workflow: self.clone(),
+            previous_id: map.get(previous)?,
+            workflows_after: map.get(after)?,
+            pipeline_url: map.get(Dependency::PipelineUrl(self.pipeline.clone()))?,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to search for a workflow by title, pipeline, owner, and the
+/// plugin instance it was run after. If not found, the workflow is created,
+/// which creates plugin instances in the feed.
+pub(crate) struct WorkflowExistsStep {
+    workflow: WorkflowExists,
+    previous_id: Rc<String>,
+    /// IDs of the workflows which were run after the previous plugin instance,
+    /// separated by commas.
+    workflows_after: Rc<String>,
+    pipeline_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for WorkflowExistsStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = self
+            .workflow
+            .url
+            .to_url()
+            .join("pipelines/workflows/search/")
+            .unwrap();
+        url.query_pairs_mut()
+            .append_pair("title", &self.workflow.title)
+            .append_pair("pipeline_name", &self.workflow.pipeline)
+            .append_pair("owner_username", self.workflow.owner.as_str())
+            // title and pipeline_name match substrings, so the exact match
+            // might not be on the first page
+            .append_pair("limit", "100");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: models::PaginatedWorkflowList = serde_json::from_slice(&body)?;
+        let after: HashSet<_> = self.workflows_after.split(',').collect();
+        let check = data
+            .results
+            .into_iter()
+            .find(|workflow| {
+                workflow.title == self.workflow.title
+                    && workflow.pipeline_name == self.workflow.pipeline
+                    && workflow.owner_username == self.workflow.owner.as_str()
+                    && after.contains(workflow.id.to_string().as_str())
+            })
+            .map(|workflow| {
+                Check::Exists(vec![
+                    (self.target(), workflow.url),
+                    (self.id_target(), workflow.id.to_string()),
+                ])
+            })
+            .unwrap_or(Check::DoesNotExist);
+        Ok(check)
+    }
+
+    fn create(&self) -> Option<Box<dyn StepRequest>> {
+        Some(Box::new(CreateWorkflowRequest {
+            target: self.target(),
+            id_target: self.id_target(),
+            title: self.workflow.title.clone(),
+            previous_id: Rc::clone(&self.previous_id),
+            pipeline_url: Rc::clone(&self.pipeline_url),
+            auth_token: Rc::clone(&self.auth_token),
+        }))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target(), self.id_target()]
+    }
+}
+
+impl WorkflowExistsStep {
+    fn target(&self) -> Dependency {
+        Dependency::WorkflowUrl(
+            self.workflow.owner.clone(),
+            self.workflow.feed.clone(),
+            self.workflow.title.clone(),
+        )
+    }
+
+    fn id_target(&self) -> Dependency {
+        Dependency::WorkflowId(
+            self.workflow.owner.clone(),
+            self.workflow.feed.clone(),
+            self.workflow.title.clone(),
+        )
+    }
+}
+
+/// Create a workflow.
+pub(crate) struct CreateWorkflowRequest {
+    target: Dependency,
+    id_target: Dependency,
+    title: String,
+    previous_id: Rc<String>,
+    pipeline_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl StepRequest for CreateWorkflowRequest {
+    fn request(&self) -> reqwest::Request {
+        let url = Url::parse(&self.pipeline_url)
+            .unwrap()
+            .join("workflows/")
+            .unwrap();
+        let body = WorkflowCreateRequest {
+            previous_plugin_inst_id: self.previous_id.parse().unwrap(),
+            title: &self.title,
+        };
+        Request::new(Method::POST, url)
+            .auth_token(self.auth_token.as_str())
+            .json(&body)
+            .unwrap()
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries> {
+        let workflow: models::Workflow = serde_json::from_slice(&body)?;
+        Ok(vec![
+            (self.target.clone(), workflow.url),
+            (self.id_target.clone(), workflow.id.to_string()),
+        ])
+    }
+}
+
+/// A [PendingStep] to find the plugin instances which were created by a
+/// workflow. See [WorkflowPluginInstancesStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct WorkflowPluginInstances {
+    pub(crate) owner: Username,
+    pub(crate) feed: String,
+    pub(crate) title: String,
+    /// Titles of the plugins of the workflow's pipeline.
+    pub(crate) pieces: Rc<NonEmpty<String>>,
+    pub(crate) url: CubeUrl,
+}
+
+impl PendingStep for WorkflowPluginInstances {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let workflow_id =
+            Dependency::WorkflowId(self.owner.clone(), self.feed.clone(), self.title.clone());
+        let step = WorkflowPluginInstancesStep {
+            workflow: self.clone(),
+            workflow_id: map.get(workflow_id)?,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to get the IDs of the plugin instances of a workflow by their
+/// titles, so that plugin instances and workflows can be run after them.
+///
+/// This step does not create plugin instances.
+pub(crate) struct WorkflowPluginInstancesStep {
+    workflow: WorkflowPluginInstances,
+    workflow_id: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for WorkflowPluginInstancesStep {
+    fn search(&self) -> reqwest::Request {
+        let mut url = self
+            .workflow
+            .url
+            .to_url()
+            .join("plugins/instances/search/")
+            .unwrap();
+        url.query_pairs_mut()
+            .append_pair("workflow_id", &self.workflow_id)
+            .append_pair("limit", "100");
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn paginated(&self) -> bool {
+        true
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let data: models::PaginatedPluginInstanceList = serde_json::from_slice(&body)?;
+        let mut entries = Vec::with_capacity(self.workflow.pieces.len());
+        for title in self.workflow.pieces.iter() {
+            let Some(instance) = data
+                .results
+                .iter()
+                .find(|instance| &instance.title == title)
+            else {
+                return Ok(Check::Invalid(format!(
+                    "plugin instance \"{title}\" of workflow \"{}\" of feed \"{}\" (owned by {}) was not found",
+                    self.workflow.title, self.workflow.feed, self.workflow.owner
+                )));
+            };
+            entries.push((self.instance_id(title), instance.id.to_string()));
+        }
+        Ok(Check::Exists(entries))
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        self.workflow
+            .pieces
+            .as_ref()
+            .clone()
+            .map(|title| self.instance_id(&title))
+    }
+}
+
+impl WorkflowPluginInstancesStep {
+    fn instance_id(&self, title: &str) -> Dependency {
+        Dependency::PluginInstanceId(
+            self.workflow.owner.clone(),
+            self.workflow.feed.clone(),
+            Some(title.to_string()),
+        )
+    }
+}
+
+/// A [PendingStep] to wait for all the jobs of a workflow to finish. See [WorkflowWaitStep].
+#[derive(Debug, Clone, AsRefPendingStep)]
+pub(crate) struct WorkflowWait {
+    pub(crate) owner: Username,
+    pub(crate) feed: String,
+    pub(crate) title: String,
+    pub(crate) deadline: Rc<Deadline>,
+}
+
+impl PendingStep for WorkflowWait {
+    fn build(&self, map: &dyn DependencyMap) -> PendingStepResult {
+        let workflow_url =
+            Dependency::WorkflowUrl(self.owner.clone(), self.feed.clone(), self.title.clone());
+        let step = WorkflowWaitStep {
+            wait: self.clone(),
+            workflow_url: map.get(workflow_url)?,
+            auth_token: map.get(Dependency::AuthToken(self.owner.clone()))?,
+        };
+        ok_step(step)
+    }
+}
+
+/// A [Step] to get the job counts of a workflow until all of its jobs have
+/// finished. A workflow with any errored or cancelled jobs is an error.
+pub(crate) struct WorkflowWaitStep {
+    wait: WorkflowWait,
+    workflow_url: Rc<String>,
+    auth_token: Rc<String>,
+}
+
+impl Step for WorkflowWaitStep {
+    fn search(&self) -> reqwest::Request {
+        let url = Url::parse(&self.workflow_url).unwrap();
+        Request::new(Method::GET, url)
+            .auth_token(self.auth_token.as_str())
+            .accept_json()
+    }
+
+    fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Check> {
+        let counts: WorkflowJobCounts = serde_json::from_slice(&body)?;
+        let unfinished = counts.created_jobs
+            + counts.waiting_jobs
+            + counts.scheduled_jobs
+            + counts.started_jobs
+            + counts.registering_jobs;
+        let check = if counts.errored_jobs > 0 || counts.cancelled_jobs > 0 {
+            Check::Failed(format!(
+                "workflow \"{}\" of feed \"{}\" (owned by {}) has {} errored and {} cancelled jobs",
+                self.wait.title,
+                self.wait.feed,
+                self.wait.owner,
+                counts.errored_jobs,
+                counts.cancelled_jobs
+            ))
+        } else if unfinished > 0 {
+            Check::Pending
+        } else {
+            Check::Exists(vec![(self.target(), counts.finished_jobs.to_string())])
+        };
+        Ok(check)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.wait.deadline.remaining()
+    }
+
+    fn provides(&self) -> NonEmpty<Dependency> {
+        nonempty![self.target()]
+    }
+}
+
+impl WorkflowWaitStep {
+    fn target(&self) -> Dependency {
+        Dependency::WorkflowFinished(
+            self.wait.owner.clone(),
+            self.wait.feed.clone(),
+            self.wait.title.clone(),
+        )
+    }
+}
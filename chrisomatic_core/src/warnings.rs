@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+use chrisomatic_spec::Username;
+use chrisomatic_step::Dependency;
+
+use crate::exec_step::StepEffect;
+
+/// Describe the effects which deserve the attention of the user: users who
+/// were made staff users, and groups which had undeclared members removed.
+///
+/// `staff` are the users declared as staff users (see
+/// [chrisomatic_spec::UserDetails::is_staff]).
+pub fn warnings_of(effects: &HashMap<Dependency, StepEffect>, staff: &[Username]) -> Vec<String> {
+    let mut warnings: Vec<_> = effects
+        .iter()
+        .filter_map(|(target, effect)| match (target, effect) {
+            (Dependency::UserExists(username), StepEffect::Created) if staff.contains(username) => {
+                Some(format!("created staff user \"{username}\""))
+            }
+            (Dependency::UserStaff(username), StepEffect::Modified) => {
+                Some(format!("promoted user \"{username}\" to staff"))
+            }
+            (Dependency::GroupExclusive(group), StepEffect::Removed) => {
+                Some(format!("removed undeclared members from group \"{group}\""))
+            }
+            _ => None,
+        })
+        .collect();
+    warnings.sort_unstable();
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrisomatic_spec::Group;
+    use compact_str::CompactString;
+
+    #[test]
+    fn test_warnings_of() {
+        let alice = Username::from("alice");
+        let bob = Username::from("bob");
+        let group = Group::new(CompactString::const_new("friends"));
+        let effects = HashMap::from([
+            (Dependency::UserExists(alice.clone()), StepEffect::Created),
+            (Dependency::UserExists(bob.clone()), StepEffect::Created),
+            (Dependency::UserStaff(bob.clone()), StepEffect::Modified),
+            (
+                Dependency::GroupExclusive(group.clone()),
+                StepEffect::Removed,
+            ),
+            (Dependency::GroupUrl(group), StepEffect::Unmodified),
+        ]);
+        let expected = vec![
+            "created staff user \"alice\"".to_string(),
+            "promoted user \"bob\" to staff".to_string(),
+            "removed undeclared members from group \"friends\"".to_string(),
+        ];
+        assert_eq!(warnings_of(&effects, &[alice]), expected);
+    }
+}
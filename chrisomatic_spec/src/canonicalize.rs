@@ -1,8 +1,15 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::path::PathBuf;
 
 use compact_str::CompactString;
 
-use crate::{spec::*, types::*};
+use crate::{
+    plugin_spec::PluginSpec,
+    share_target::{PermissionTarget, ShareTarget},
+    spec::*,
+    types::*,
+};
 
 /// Merge multiple [GivenManifest] into one.
 pub fn reduce(
@@ -14,7 +21,19 @@ pub fn reduce(
 fn merge(a: GivenManifest, b: GivenManifest) -> Result<GivenManifest, ManifestError> {
     Ok(GivenManifest {
         global: merge_global(a.global, b.global)?,
-        user: merge_users(a.user, b.user)?,
+        user: merge_maps(a.user, b.user, ManifestError::DuplicateUser)?,
+        group: merge_maps(a.group, b.group, ManifestError::DuplicateGroup)?,
+        plugins: concat(a.plugins, b.plugins),
+        compute_resource: merge_maps(
+            a.compute_resource,
+            b.compute_resource,
+            ManifestError::DuplicateComputeResource,
+        )?,
+        userfiles: concat(a.userfiles, b.userfiles),
+        pacs: merge_maps(a.pacs, b.pacs, ManifestError::DuplicatePacs)?,
+        pacs_files: concat(a.pacs_files, b.pacs_files),
+        pipeline: concat(a.pipeline, b.pipeline),
+        feed: concat(a.feed, b.feed),
     })
 }
 
@@ -24,6 +43,14 @@ fn merge_global(a: GivenGlobal, b: GivenGlobal) -> Result<GivenGlobal, ManifestE
         admin: merge_credentials(a.admin, b.admin)?,
         email_domain: none_xor(a.email_domain, b.email_domain, "global.email_domain")?,
         public_cube: none_xor(a.public_cube, b.public_cube, "global.public_cube")?,
+        upload_concurrency: none_xor(
+            a.upload_concurrency,
+            b.upload_concurrency,
+            "global.upload_concurrency",
+        )?,
+        wait: none_xor(a.wait, b.wait, "global.wait")?,
+        wait_timeout: none_xor(a.wait_timeout, b.wait_timeout, "global.wait_timeout")?,
+        reset_password: none_xor(a.reset_password, b.reset_password, "global.reset_password")?,
     })
 }
 
@@ -46,8 +73,13 @@ fn merge_credentials(
     }
 }
 
+fn concat<T>(mut a: Vec<T>, b: Vec<T>) -> Vec<T> {
+    a.extend(b);
+    a
+}
+
 /// Return the value of `a` or `b`, but produce an error if both are [Some].
-fn none_xor<T: Into<String>>(
+fn none_xor<T: ToString>(
     a: Option<T>,
     b: Option<T>,
     key: &'static str,
@@ -56,8 +88,8 @@ fn none_xor<T: Into<String>>(
         if let Some(b) = b {
             Err(ManifestError::DuplicateValue {
                 key,
-                a: a.into(),
-                b: b.into(),
+                a: a.to_string(),
+                b: b.to_string(),
             })
         } else {
             Ok(Some(a))
@@ -67,13 +99,14 @@ fn none_xor<T: Into<String>>(
     }
 }
 
-fn merge_users<T>(
-    mut a: HashMap<Username, T>,
-    b: HashMap<Username, T>,
-) -> Result<HashMap<Username, T>, ManifestError> {
-    for (username, details) in b.into_iter() {
-        if a.insert(username.clone(), details).is_some() {
-            return Err(ManifestError::DuplicateUser(username));
+fn merge_maps<K: Clone + Eq + std::hash::Hash, V>(
+    mut a: HashMap<K, V>,
+    b: HashMap<K, V>,
+    duplicate: impl Fn(K) -> ManifestError,
+) -> Result<HashMap<K, V>, ManifestError> {
+    for (key, value) in b.into_iter() {
+        if a.insert(key.clone(), value).is_some() {
+            return Err(duplicate(key));
         }
     }
     Ok(a)
@@ -86,6 +119,39 @@ pub enum ManifestError {
     Missing(&'static [&'static str]),
     #[error("Username specified more than once: \"{0}\"")]
     DuplicateUser(Username),
+    #[error("Group specified more than once: \"{0}\"")]
+    DuplicateGroup(Group),
+    #[error("Compute resource specified more than once: \"{0}\"")]
+    DuplicateComputeResource(ComputeResourceName),
+    #[error("PACS specified more than once: \"{0}\"")]
+    DuplicatePacs(String),
+    #[error("User file path specified more than once: \"{0}\"")]
+    DuplicateUserFile(String),
+    #[error("Pipeline name specified more than once: \"{0}\"")]
+    DuplicatePipeline(String),
+    #[error("User \"{username}\" of `{key}` is neither declared in `user` nor `global.admin`")]
+    UndeclaredUser {
+        key: &'static str,
+        username: Username,
+    },
+    #[error("Pipeline \"{0}\" of `feed.workflows` is not declared in `pipeline`")]
+    UndeclaredPipeline(String),
+    #[error("PACS \"{0}\" of `user.pacs_queries` is not declared in `pacs`")]
+    UndeclaredPacs(String),
+    #[error("Pipeline file was not loaded: \"{}\"", .0.display())]
+    UnloadedPipelineFile(PathBuf),
+    #[error("Descriptor of plugin \"{0}\" was not loaded")]
+    UnloadedPluginDescriptor(PluginSpec),
+    #[error("Directory of user files \"{0}\" was not listed")]
+    UnlistedDirectory(String),
+    #[error("Directory of PACS files of \"{0}\" was not listed")]
+    UnlistedPacsFiles(String),
+    #[error("Feed \"{0}\" is shared with \"public\" but has `public = false`")]
+    ContradictoryPublic(String),
+    #[error("User files \"{0}\" are shared with \"public\" but have `public = false`")]
+    ContradictoryPublicFolder(String),
+    #[error("User file \"{0}\" cannot be shared with \"public\", only a `directory` can be")]
+    PublicUserFile(String),
     #[error("Duplicate value for `{key}` (first: \"{a}\", second: \"{b}\")")]
     DuplicateValue {
         key: &'static str,
@@ -109,10 +175,16 @@ impl TryFrom<GivenManifest> for Manifest {
                         password,
                         email,
                         groups,
+                        admin,
+                        links,
+                        pacs_queries,
                     },
                 )| {
                     let details = UserDetails {
                         groups,
+                        admin,
+                        links,
+                        pacs_queries,
                         password,
                         email: email
                             .unwrap_or_else(|| format!("{}@{}", &username, &global.email_domain)),
@@ -121,13 +193,223 @@ impl TryFrom<GivenManifest> for Manifest {
                 },
             )
             .collect();
-        Ok(Manifest {
+        let pipeline = value
+            .pipeline
+            .into_iter()
+            .map(|GivenPipelineSpec { owner, source }| match source {
+                PipelineSource::Inline(definition) => Ok(PipelineSpec { owner, definition }),
+                PipelineSource::File { file } => Err(ManifestError::UnloadedPipelineFile(file)),
+            })
+            .collect::<Result<_, _>>()?;
+        let feed = value
+            .feed
+            .into_iter()
+            .map(canonicalize_feed)
+            .collect::<Result<_, _>>()?;
+        let userfiles = value
+            .userfiles
+            .into_iter()
+            .map(canonicalize_userfile)
+            .collect::<Result<_, _>>()?;
+        check_plugin_descriptors_loaded(&value.plugins)?;
+        check_directories_listed(&userfiles, &value.pacs_files)?;
+        let manifest = Manifest {
             global,
             user,
-            // userfiles: value.userfiles,
-            // feeds: value.feeds,
-        })
+            group: value.group,
+            plugins: value.plugins,
+            compute_resource: value.compute_resource,
+            userfiles,
+            pacs: value.pacs,
+            pacs_files: value.pacs_files,
+            pipeline,
+            feed,
+        };
+        check_userfiles_unique(&manifest)?;
+        check_pipelines_unique(&manifest)?;
+        check_users_declared(&manifest)?;
+        check_pipelines_declared(&manifest)?;
+        check_pacs_declared(&manifest)?;
+        Ok(manifest)
+    }
+}
+
+/// Check that plugin descriptors given as files or images were loaded.
+fn check_plugin_descriptors_loaded(plugins: &[PluginEntry]) -> Result<(), ManifestError> {
+    for entry in plugins {
+        if let Some(PluginDescriptor::File(_) | PluginDescriptor::Image { .. }) = &entry.descriptor
+        {
+            return Err(ManifestError::UnloadedPluginDescriptor(
+                entry.plugin.clone(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Check that the files of local directories were listed.
+fn check_directories_listed(
+    userfiles: &[UserFileSpec],
+    pacs_files: &[PacsFilesSpec],
+) -> Result<(), ManifestError> {
+    for userfile in userfiles {
+        if let UserFileContent::Directory { files: None, .. } = &userfile.content {
+            return Err(ManifestError::UnlistedDirectory(userfile.path.clone()));
+        }
+    }
+    if let Some(pacs_files) = pacs_files.iter().find(|p| p.files.is_none()) {
+        return Err(ManifestError::UnlistedPacsFiles(
+            pacs_files.pacs_identifier.clone(),
+        ));
+    }
+    Ok(())
+}
+
+/// Replace sharing with [ShareTarget::Public] by `public = true`.
+fn canonicalize_feed(feed: FeedSpec<ShareTarget>) -> Result<FeedSpec, ManifestError> {
+    let mut public = feed.public;
+    let mut share = Vec::with_capacity(feed.share.len());
+    for target in feed.share {
+        match target {
+            ShareTarget::User(username) => share.push(PermissionTarget::User(username)),
+            ShareTarget::Group(group) => share.push(PermissionTarget::Group(group)),
+            ShareTarget::Public if feed.public == Some(false) => {
+                return Err(ManifestError::ContradictoryPublic(feed.name));
+            }
+            ShareTarget::Public => public = Some(true),
+        }
+    }
+    Ok(FeedSpec {
+        name: feed.name,
+        owner: feed.owner,
+        share,
+        public,
+        note: feed.note,
+        tags: feed.tags,
+        comments: feed.comments,
+        plugin_instances: feed.plugin_instances,
+        workflows: feed.workflows,
+        plugin: feed.plugin,
+    })
+}
+
+/// Replace sharing a directory with [ShareTarget::Public] by `public = true`.
+fn canonicalize_userfile(
+    userfile: UserFileSpec<ShareTarget>,
+) -> Result<UserFileSpec, ManifestError> {
+    let mut content = userfile.content;
+    let mut share = Vec::with_capacity(userfile.share.len());
+    for FileShare { target, permission } in userfile.share {
+        let target = match target {
+            ShareTarget::User(username) => PermissionTarget::User(username),
+            ShareTarget::Group(group) => PermissionTarget::Group(group),
+            ShareTarget::Public => {
+                let UserFileContent::Directory { public, .. } = &mut content else {
+                    return Err(ManifestError::PublicUserFile(userfile.path));
+                };
+                if *public == Some(false) {
+                    return Err(ManifestError::ContradictoryPublicFolder(userfile.path));
+                }
+                *public = Some(true);
+                continue;
+            }
+        };
+        share.push(FileShare { target, permission });
+    }
+    Ok(UserFileSpec {
+        path: userfile.path,
+        owner: userfile.owner,
+        share,
+        content,
+    })
+}
+
+/// Make sure that no two user files have the same path.
+fn check_userfiles_unique(manifest: &Manifest) -> Result<(), ManifestError> {
+    let mut paths = HashSet::new();
+    for userfile in &manifest.userfiles {
+        if !paths.insert(userfile.path.as_str()) {
+            return Err(ManifestError::DuplicateUserFile(userfile.path.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// Make sure that no two pipelines have the same name.
+fn check_pipelines_unique(manifest: &Manifest) -> Result<(), ManifestError> {
+    let mut names = HashSet::new();
+    for pipeline in &manifest.pipeline {
+        if !names.insert(pipeline.definition.name.as_str()) {
+            return Err(ManifestError::DuplicatePipeline(
+                pipeline.definition.name.clone(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Make sure that users referenced by resources are declared in the manifest.
+fn check_users_declared(manifest: &Manifest) -> Result<(), ManifestError> {
+    let is_declared = |username: &Username| {
+        manifest.user.contains_key(username) || &manifest.global.admin.username == username
+    };
+    let userfile_owners = manifest
+        .userfiles
+        .iter()
+        .filter_map(|userfile| userfile.owner.as_ref())
+        .map(|owner| ("userfiles.owner", owner));
+    let pipeline_owners = manifest
+        .pipeline
+        .iter()
+        .filter_map(|pipeline| pipeline.owner.as_ref())
+        .map(|owner| ("pipeline.owner", owner));
+    let feed_owners = manifest
+        .feed
+        .iter()
+        .filter_map(|feed| feed.owner.as_ref())
+        .map(|owner| ("feed.owner", owner));
+    for (key, username) in userfile_owners.chain(pipeline_owners).chain(feed_owners) {
+        if !is_declared(username) {
+            return Err(ManifestError::UndeclaredUser {
+                key,
+                username: username.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Make sure that pipelines referenced by workflows are declared in the manifest.
+fn check_pipelines_declared(manifest: &Manifest) -> Result<(), ManifestError> {
+    let workflow_pipelines = manifest
+        .feed
+        .iter()
+        .flat_map(|feed| &feed.workflows)
+        .map(|workflow| &workflow.pipeline);
+    for name in workflow_pipelines {
+        if !manifest
+            .pipeline
+            .iter()
+            .any(|pipeline| &pipeline.definition.name == name)
+        {
+            return Err(ManifestError::UndeclaredPipeline(name.clone()));
+        }
+    }
+    Ok(())
+}
+
+fn check_pacs_declared(manifest: &Manifest) -> Result<(), ManifestError> {
+    let queried_pacs = manifest
+        .user
+        .values()
+        .flat_map(|details| &details.pacs_queries)
+        .map(|query| &query.pacs);
+    for identifier in queried_pacs {
+        if !manifest.pacs.contains_key(identifier) {
+            return Err(ManifestError::UndeclaredPacs(identifier.clone()));
+        }
     }
+    Ok(())
 }
 
 impl TryFrom<GivenGlobal> for Global {
@@ -146,6 +428,12 @@ impl TryFrom<GivenGlobal> for Global {
             public_cube: value.public_cube.unwrap_or_else(|| {
                 CubeUrl::try_new("https://cube.chrisproject.org/api/v1/").unwrap()
             }),
+            upload_concurrency: value
+                .upload_concurrency
+                .unwrap_or(NonZeroUsize::new(4).unwrap()),
+            wait: value.wait.unwrap_or(false),
+            wait_timeout: value.wait_timeout.unwrap_or(NonZeroU64::new(3600).unwrap()),
+            reset_password: value.reset_password.unwrap_or(false),
         })
     }
 }
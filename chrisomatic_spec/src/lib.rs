@@ -6,6 +6,7 @@ mod spec;
 mod types;
 
 pub use canonicalize::*;
-pub use plugin_spec::PluginSpec;
+pub use plugin_spec::{PluginSpec, PluginSpecError};
+pub use share_target::{PermissionTarget, ShareTarget};
 pub use spec::*;
 pub use types::*;
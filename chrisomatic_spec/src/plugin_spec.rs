@@ -1,18 +1,79 @@
-use compact_str::CompactString;
+use std::{fmt::Display, str::FromStr};
+
+use compact_str::{CompactString, ToCompactString};
 
 /// A specified [_ChRIS_ plugin](https://chrisproject.org/docs/plugins).
+///
+/// Written as `name` or `name@version`, e.g. `"pl-dircopy"` or `"pl-dcm2niix@1.0.0"`.
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct PluginSpec {
     name: CompactString,
     version: Option<CompactString>,
 }
 
+impl PluginSpec {
+    pub fn new(name: impl Into<CompactString>, version: Option<CompactString>) -> Self {
+        Self {
+            name: name.into(),
+            version,
+        }
+    }
+
+    /// Plugin name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Plugin version, if specified.
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+}
+
+impl Display for PluginSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(version) = &self.version {
+            write!(f, "{}@{}", self.name, version)
+        } else {
+            f.write_str(&self.name)
+        }
+    }
+}
+
+/// Error parsing a [PluginSpec].
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum PluginSpecError {
+    #[error("Plugin name is empty")]
+    EmptyName,
+    #[error("Plugin version is empty")]
+    EmptyVersion,
+}
+
+impl FromStr for PluginSpec {
+    type Err = PluginSpecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, version) = if let Some((name, version)) = s.split_once('@') {
+            if version.is_empty() {
+                return Err(PluginSpecError::EmptyVersion);
+            }
+            (name, Some(version.to_compact_string()))
+        } else {
+            (s, None)
+        };
+        if name.is_empty() {
+            return Err(PluginSpecError::EmptyName);
+        }
+        Ok(Self::new(name, version))
+    }
+}
+
 impl serde::ser::Serialize for PluginSpec {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        todo!()
+        serializer.serialize_str(&self.to_string())
     }
 }
 
@@ -21,6 +82,57 @@ impl<'de> serde::de::Deserialize<'de> for PluginSpec {
     where
         D: serde::Deserializer<'de>,
     {
-        todo!()
+        deserializer.deserialize_str(StringVisitor)
+    }
+}
+
+struct StringVisitor;
+
+impl<'de> serde::de::Visitor<'de> for StringVisitor {
+    type Value = PluginSpec;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("plugin name, optionally followed by '@' and version")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        v.parse()
+            .map_err(|_| E::invalid_value(serde::de::Unexpected::Str(v), &self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[rstest]
+    #[case("pl-dircopy", PluginSpec::new("pl-dircopy", None))]
+    #[case("pl-dcm2niix@1.0.0", PluginSpec::new("pl-dcm2niix", Some("1.0.0".into())))]
+    fn test_deserialize(#[case] input: &str, #[case] expected: PluginSpec) {
+        let value = toml::Value::String(input.to_string());
+        let actual = value.try_into();
+        assert_eq!(actual, Ok(expected))
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("@1.0.0")]
+    #[case("pl-dircopy@")]
+    fn test_deserialize_error(#[case] input: &str) {
+        let value = toml::Value::String(input.to_string());
+        let actual: Result<PluginSpec, _> = value.try_into();
+        assert!(actual.is_err())
+    }
+
+    #[rstest]
+    #[case(PluginSpec::new("pl-dircopy", None), "pl-dircopy")]
+    #[case(PluginSpec::new("pl-dcm2niix", Some("1.0.0".into())), "pl-dcm2niix@1.0.0")]
+    fn test_serialize(#[case] input: PluginSpec, #[case] expected: &str) {
+        let actual = toml::Value::try_from(input);
+        assert_eq!(actual, Ok(toml::Value::String(expected.to_string())))
     }
 }
@@ -2,13 +2,49 @@ use compact_str::ToCompactString;
 
 use crate::types::{Group, Username};
 
-/// User or group to share a ChRIS resource with.
-#[derive(Debug, Clone, PartialEq)]
+/// User or group to share a ChRIS resource with, or everyone.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ShareTarget {
     /// Username to share with
     User(Username),
     /// Group to share with
     Group(Group),
+    /// Make public, i.e. share with everyone including anonymous visitors.
+    /// (A user called "public" must be written as "user:public")
+    Public,
+}
+
+/// User or group which a ChRIS resource is shared with, i.e. a [ShareTarget]
+/// other than [ShareTarget::Public]. Making a resource public is not a
+/// permission, so it is specified by `public = true` in [crate::Manifest].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "ShareTarget", into = "ShareTarget")]
+pub enum PermissionTarget {
+    /// Username to share with
+    User(Username),
+    /// Group to share with
+    Group(Group),
+}
+
+impl TryFrom<ShareTarget> for PermissionTarget {
+    type Error = &'static str;
+
+    fn try_from(value: ShareTarget) -> Result<Self, Self::Error> {
+        match value {
+            ShareTarget::User(username) => Ok(PermissionTarget::User(username)),
+            ShareTarget::Group(group) => Ok(PermissionTarget::Group(group)),
+            ShareTarget::Public => Err("\"public\" is not a user or group"),
+        }
+    }
+}
+
+impl From<PermissionTarget> for ShareTarget {
+    fn from(value: PermissionTarget) -> Self {
+        match value {
+            PermissionTarget::User(username) => ShareTarget::User(username),
+            PermissionTarget::Group(group) => ShareTarget::Group(group),
+        }
+    }
 }
 
 impl serde::ser::Serialize for ShareTarget {
@@ -17,8 +53,12 @@ impl serde::ser::Serialize for ShareTarget {
         S: serde::Serializer,
     {
         match self {
+            ShareTarget::User(username) if username.as_str() == PUBLIC => {
+                serializer.serialize_str(&format!("user:{username}"))
+            }
             ShareTarget::User(username) => serializer.serialize_str(username.as_str()),
             ShareTarget::Group(group) => serializer.serialize_str(&format!("group:{group}")),
+            ShareTarget::Public => serializer.serialize_str(PUBLIC),
         }
     }
 }
@@ -32,13 +72,15 @@ impl<'de> serde::de::Deserialize<'de> for ShareTarget {
     }
 }
 
+const PUBLIC: &str = "public";
+
 struct StringVisitor;
 
 impl<'de> serde::de::Visitor<'de> for StringVisitor {
     type Value = ShareTarget;
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-        formatter.write_str("prefix \"user\" or \"group\" before ':' character")
+        formatter.write_str("\"public\", or prefix \"user\" or \"group\" before ':' character")
     }
 
     fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
@@ -51,6 +93,8 @@ impl<'de> serde::de::Visitor<'de> for StringVisitor {
                 "user" => Ok(ShareTarget::User(r.to_compact_string().into())),
                 l => Err(E::invalid_value(serde::de::Unexpected::Str(l), &self)),
             }
+        } else if v == PUBLIC {
+            Ok(ShareTarget::Public)
         } else {
             Ok(ShareTarget::User(v.to_compact_string().into()))
         }
@@ -66,6 +110,8 @@ mod tests {
     #[case("alice", ShareTarget::User("alice".to_compact_string().into()))]
     #[case("user:alice", ShareTarget::User("alice".to_compact_string().into()))]
     #[case("group:friends", ShareTarget::Group("friends".to_compact_string().into()))]
+    #[case("public", ShareTarget::Public)]
+    #[case("user:public", ShareTarget::User("public".to_compact_string().into()))]
     fn test_deserialize(#[case] input: &str, #[case] expected: ShareTarget) {
         let value = toml::Value::String(input.to_string());
         let actual = value.try_into();
@@ -79,9 +125,22 @@ mod tests {
         assert!(actual.is_err())
     }
 
+    #[rstest]
+    fn test_deserialize_permission_target() {
+        let value = toml::Value::String("group:friends".to_string());
+        let actual: Result<PermissionTarget, _> = value.try_into();
+        let expected = PermissionTarget::Group("friends".to_compact_string().into());
+        assert_eq!(actual, Ok(expected));
+        let value = toml::Value::String("public".to_string());
+        let actual: Result<PermissionTarget, _> = value.try_into();
+        assert!(actual.is_err())
+    }
+
     #[rstest]
     #[case(ShareTarget::User("alice".to_compact_string().into()), "alice")]
     #[case(ShareTarget::Group("friends".to_compact_string().into()), "group:friends")]
+    #[case(ShareTarget::Public, "public")]
+    #[case(ShareTarget::User("public".to_compact_string().into()), "user:public")]
     fn test_serialize(#[case] input: ShareTarget, #[case] expected: &str) {
         let actual = toml::Value::try_from(input);
         assert_eq!(actual, Ok(toml::Value::String(expected.to_string())))
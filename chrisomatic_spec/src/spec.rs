@@ -1,8 +1,13 @@
 use std::collections::HashMap;
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::path::PathBuf;
 
 use compact_str::CompactString;
+use globset::Glob;
 use serde::{Deserialize, Serialize};
 
+use crate::plugin_spec::PluginSpec;
+use crate::share_target::{PermissionTarget, ShareTarget};
 use crate::types::*;
 
 /// User-supplied input chrisomatic manifest. Similar to [Manifest], but with
@@ -18,10 +23,22 @@ pub struct GivenManifest {
     pub global: GivenGlobal,
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub user: HashMap<Username, GivenUserDetails>,
-    // #[serde(skip_serializing_if = "Vec::is_empty")]
-    // pub userfiles: Vec<UserFileSpec>,
-    // #[serde(skip_serializing_if = "Vec::is_empty")]
-    // pub feeds: Vec<FeedSpec>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub group: HashMap<Group, GroupSpec>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plugins: Vec<PluginEntry>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub compute_resource: HashMap<ComputeResourceName, ComputeResourceSpec>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub userfiles: Vec<UserFileSpec<ShareTarget>>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub pacs: HashMap<String, PacsSpec>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pacs_files: Vec<PacsFilesSpec>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pipeline: Vec<GivenPipelineSpec>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub feed: Vec<FeedSpec<ShareTarget>>,
 }
 
 /// User-supplied input for global configuration.
@@ -41,6 +58,24 @@ pub struct GivenGlobal {
     /// (Default: "https://cube.chrisproject.org/api/v1/")
     #[serde(skip_serializing_if = "Option::is_none")]
     pub public_cube: Option<CubeUrl>,
+    /// Maximum number of user files to upload at the same time.
+    /// (Default: 4)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_concurrency: Option<NonZeroUsize>,
+    /// Wait for plugin instances and workflows to finish.
+    /// (Default: false)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wait: Option<bool>,
+    /// Maximum number of seconds to wait for all plugin instances and workflows
+    /// to finish, when `wait = true`. (Default: 3600)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wait_timeout: Option<NonZeroU64>,
+    /// Reset the passwords of existing users whose password is not as
+    /// specified, using the admin user's privileges. Passwords of compute
+    /// resources cannot be read back, so they are set on every run.
+    /// (Default: false)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reset_password: Option<bool>,
 }
 
 impl GivenGlobal {
@@ -49,6 +84,10 @@ impl GivenGlobal {
             && self.admin.is_none()
             && self.email_domain.is_none()
             && self.public_cube.is_none()
+            && self.upload_concurrency.is_none()
+            && self.wait.is_none()
+            && self.wait_timeout.is_none()
+            && self.reset_password.is_none()
     }
 }
 
@@ -63,6 +102,15 @@ pub struct Global {
     pub email_domain: CompactString,
     /// Public CUBE from where to get plugins from.
     pub public_cube: CubeUrl,
+    /// Maximum number of user files to upload at the same time.
+    pub upload_concurrency: NonZeroUsize,
+    /// Wait for plugin instances and workflows to finish.
+    pub wait: bool,
+    /// Maximum number of seconds to wait for each plugin instance or workflow to finish.
+    pub wait_timeout: NonZeroU64,
+    /// Reset the passwords of existing users whose password is not as specified,
+    /// and set the passwords of compute resources.
+    pub reset_password: bool,
 }
 
 /// Username and password/token.
@@ -94,8 +142,28 @@ pub enum PasswordOrToken {
 pub struct Manifest {
     pub global: Global,
     pub user: HashMap<Username, UserDetails>,
-    // pub userfiles: Vec<UserFileSpec>,
-    // pub feeds: Vec<FeedSpec>,
+    /// Options of groups. Groups are also created for the `groups` of users.
+    pub group: HashMap<Group, GroupSpec>,
+    /// Plugins to register from the public CUBE.
+    pub plugins: Vec<PluginEntry>,
+    pub compute_resource: HashMap<ComputeResourceName, ComputeResourceSpec>,
+    pub userfiles: Vec<UserFileSpec>,
+    /// PACS servers, keyed by their identifier.
+    pub pacs: HashMap<String, PacsSpec>,
+    pub pacs_files: Vec<PacsFilesSpec>,
+    pub pipeline: Vec<PipelineSpec>,
+    pub feed: Vec<FeedSpec>,
+}
+
+impl Manifest {
+    /// Usernames of the staff users other than the admin user.
+    pub fn staff_users(&self) -> Vec<Username> {
+        self.user
+            .iter()
+            .filter(|(username, details)| details.is_staff(username, &self.global.admin.username))
+            .map(|(username, _)| username.clone())
+            .collect()
+    }
 }
 
 /// Given user details.
@@ -105,7 +173,16 @@ pub struct GivenUserDetails {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub email: Option<String>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
-    pub groups: Vec<String>,
+    pub groups: Vec<Group>,
+    /// Whether the user should be a staff (admin) user. (Default: false)
+    #[serde(default, alias = "staff", skip_serializing_if = "std::ops::Not::not")]
+    pub admin: bool,
+    /// Link files to create in the user's space.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<LinkSpec>,
+    /// PACS queries to create as the user.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pacs_queries: Vec<PacsQuerySpec>,
 }
 
 /// Chrisomatic user details.
@@ -114,56 +191,527 @@ pub struct UserDetails {
     pub password: String,
     pub email: String,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
-    pub groups: Vec<String>,
-}
-
-// /// Specification to create a user file.
-// #[derive(Serialize, Deserialize, Debug, Clone)]
-// pub struct UserFileSpec {
-//     pub path: String,
-//     pub owner: Option<Username>,
-//     pub text: Option<String>,
-//     pub share: Vec<ShareTarget>,
-// }
-
-// /// Specification to create a feed.
-// #[derive(Serialize, Deserialize, Debug, Clone)]
-// pub struct FeedSpec {
-//     name: String,
-//     #[serde(skip_serializing_if = "Option::is_none")]
-//     owner: Option<Username>,
-//     #[serde(skip_serializing_if = "Vec::is_empty")]
-//     share: Vec<ShareTarget>,
-//     #[serde(flatten)]
-//     plugin: FsPluginSpec,
-// }
-
-// /// Specification to run a _ts_-type plugin.
-// #[derive(Serialize, Deserialize, Debug, Clone)]
-// #[serde(untagged)]
-// pub enum FsPluginSpec {
-//     Dircopy {
-//         #[serde(rename = "dircopy")]
-//         path: String,
-//     },
-//     Other {
-//         plugin: PluginSpec,
-//         args: HashMap<CompactString, ArgValue>,
-//     },
-// }
-
-// /// Plugin argument values.
-// ///
-// /// Ref: <https://github.com/FNNDSC/ChRIS_ultron_backEnd/blob/v6.4.0/chris_backend/plugins/enums.py#L3-L5>
-// #[derive(Serialize, Deserialize, Debug, Clone)]
-// #[serde(untagged)]
-// pub enum ArgValue {
-//     /// string, path, or unextpath type
-//     Stringish(String),
-//     /// float type
-//     Float(f64),
-//     /// boolean type
-//     Boolean(bool),
-//     /// integer type
-//     Integer(i64),
-// }
+    pub groups: Vec<Group>,
+    /// Whether the user should be a staff (admin) user. (Default: false)
+    #[serde(default, alias = "staff", skip_serializing_if = "std::ops::Not::not")]
+    pub admin: bool,
+    /// Link files to create in the user's space.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<LinkSpec>,
+    /// PACS queries to create as the user.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pacs_queries: Vec<PacsQuerySpec>,
+}
+
+impl UserDetails {
+    /// Whether the user `username` is a staff user other than the `admin` user.
+    pub fn is_staff(&self, username: &Username, admin: &Username) -> bool {
+        self.admin && username != admin
+    }
+}
+
+/// Options of a group.
+#[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GroupSpec {
+    /// Whether users who are not declared as members of the group in `user`
+    /// are removed from the group. (Default: false)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub exclusive: bool,
+}
+
+/// A _ChRIS_ link file, which makes a path of _ChRIS_ storage appear in
+/// another folder without copying its files.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LinkSpec {
+    /// Path which is linked to, e.g. `"home/chris/datasets/reference"`.
+    pub target: String,
+    /// Path of the link file, e.g. `"home/alice/reference.chrislink"`.
+    /// The `.chrislink` suffix is added if missing. Its folder must already
+    /// exist or be created by uploading `userfiles` into it. An existing link
+    /// file which links to a different path is an error.
+    pub path: String,
+}
+
+/// A PACS query, found by its title among the queries of its owner.
+///
+/// Its owner must be a member of the `pacs_users` group.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PacsQuerySpec {
+    pub title: String,
+    /// Identifier of the PACS to query, which must be declared in `pacs`.
+    pub pacs: String,
+    /// Query parameters, e.g. `{ PatientID = "1449c1d", Modality = "MR" }`.
+    pub query: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// A plugin to register, either written as just a [PluginSpec] string,
+/// or as a table which also specifies compute resources.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(from = "PluginEntryRepr", into = "PluginEntryRepr")]
+pub struct PluginEntry {
+    pub plugin: PluginSpec,
+    /// Compute resources to assign the plugin to.
+    /// (Default: "host" for newly registered plugins)
+    pub compute_resources: Vec<ComputeResourceName>,
+    /// Descriptor to register the plugin with, if not registering the plugin
+    /// from the public CUBE.
+    pub descriptor: Option<PluginDescriptor>,
+}
+
+impl From<PluginSpec> for PluginEntry {
+    fn from(plugin: PluginSpec) -> Self {
+        Self {
+            plugin,
+            compute_resources: vec![],
+            descriptor: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+enum PluginEntryRepr {
+    Spec(PluginSpec),
+    Table {
+        plugin: PluginSpec,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        compute_resources: Vec<ComputeResourceName>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        descriptor: Option<PluginDescriptor>,
+    },
+}
+
+impl From<PluginEntryRepr> for PluginEntry {
+    fn from(value: PluginEntryRepr) -> Self {
+        match value {
+            PluginEntryRepr::Spec(plugin) => plugin.into(),
+            PluginEntryRepr::Table {
+                plugin,
+                compute_resources,
+                descriptor,
+            } => Self {
+                plugin,
+                compute_resources,
+                descriptor,
+            },
+        }
+    }
+}
+
+impl From<PluginEntry> for PluginEntryRepr {
+    fn from(value: PluginEntry) -> Self {
+        if value.compute_resources.is_empty() && value.descriptor.is_none() {
+            Self::Spec(value.plugin)
+        } else {
+            Self::Table {
+                plugin: value.plugin,
+                compute_resources: value.compute_resources,
+                descriptor: value.descriptor,
+            }
+        }
+    }
+}
+
+/// Where to get the descriptor of a plugin from, for registering the plugin
+/// without access to the public CUBE.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum PluginDescriptor {
+    /// Path of a plugin descriptor JSON file. It must be loaded (by the CLI)
+    /// before [GivenManifest] is converted to [Manifest].
+    File(PathBuf),
+    /// Container image of the plugin, which prints its descriptor when running
+    /// `chris_plugin_info`. It must be run (by the CLI) before [GivenManifest]
+    /// is converted to [Manifest].
+    Image { image: String },
+    /// Plugin descriptor JSON.
+    Json { json: String },
+}
+
+/// Specification of a compute resource, i.e. a [pfcon](https://github.com/FNNDSC/pfcon) instance.
+///
+/// CUBE does not limit the files of a compute resource, so the only limit is
+/// [ComputeResourceSpec::max_job_exec_seconds].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ComputeResourceSpec {
+    /// URL of pfcon API, e.g. `"http://pfcon.local:5005/api/v1/"`.
+    pub url: String,
+    /// pfcon username.
+    pub username: String,
+    /// pfcon password.
+    pub password: String,
+    /// Description of the compute resource.
+    #[serde(default)]
+    pub description: String,
+    /// Whether pfcon is configured for "in-network" mode, i.e. it fetches input files from storage itself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub innetwork: Option<bool>,
+    /// Maximum number of seconds a job may run for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_job_exec_seconds: Option<u64>,
+}
+
+/// Specification to create a user file.
+///
+/// A `directory` of a [GivenManifest] can be shared with [ShareTarget::Public],
+/// which is replaced by `public = true` in the user files of a [Manifest].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(bound(serialize = "T: Serialize + Clone"))]
+pub struct UserFileSpec<T = PermissionTarget> {
+    /// Path of the file in _ChRIS_ storage, e.g. `"home/alice/uploads/README.txt"`.
+    pub path: String,
+    /// Owner of the file. (Default: the admin user)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Username>,
+    /// Users and groups to share the file with. For a `directory`, the folder
+    /// at `path` is shared instead of each file.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub share: Vec<FileShare<T>>,
+    #[serde(flatten)]
+    pub content: UserFileContent,
+}
+
+/// A user or group to share a user file or folder with, either written as
+/// just a target string (with read permission) or as a table which also
+/// specifies the permission.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(
+    from = "FileShareRepr<T>",
+    into = "FileShareRepr<T>",
+    bound(serialize = "T: Serialize + Clone")
+)]
+pub struct FileShare<T = PermissionTarget> {
+    pub target: T,
+    pub permission: FilePermission,
+}
+
+impl<T> From<T> for FileShare<T> {
+    fn from(target: T) -> Self {
+        Self {
+            target,
+            permission: Default::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+enum FileShareRepr<T> {
+    Target(T),
+    Table {
+        target: T,
+        #[serde(default)]
+        permission: FilePermission,
+    },
+}
+
+impl<T> From<FileShareRepr<T>> for FileShare<T> {
+    fn from(value: FileShareRepr<T>) -> Self {
+        match value {
+            FileShareRepr::Target(target) => target.into(),
+            FileShareRepr::Table { target, permission } => Self { target, permission },
+        }
+    }
+}
+
+impl<T> From<FileShare<T>> for FileShareRepr<T> {
+    fn from(value: FileShare<T>) -> Self {
+        if value.permission == FilePermission::default() {
+            Self::Target(value.target)
+        } else {
+            Self::Table {
+                target: value.target,
+                permission: value.permission,
+            }
+        }
+    }
+}
+
+/// Permission which a user or group is given on a shared user file or folder.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FilePermission {
+    /// Read-only access.
+    #[default]
+    #[serde(alias = "r")]
+    Read,
+    /// Read and write access.
+    #[serde(alias = "w")]
+    Write,
+}
+
+/// Content of a user file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum UserFileContent {
+    /// Text content written inline.
+    Text { text: String },
+    /// Path of a local file to upload. Relative paths are resolved by the
+    /// CLI relative to the manifest file which specifies them.
+    Source { source: PathBuf },
+    /// Path of a local directory to upload recursively. The relative paths
+    /// of its files are preserved under the user file's `path`.
+    Directory {
+        directory: PathBuf,
+        /// Only upload files matching any of these globs. (Default: all files)
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        include: Vec<Glob>,
+        /// Do not upload files matching any of these globs.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        exclude: Vec<Glob>,
+        /// Whether the folder at the user file's `path` is visible to everyone.
+        /// (Default: unchanged)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        public: Option<bool>,
+        /// Paths of the files to upload relative to `directory`, which are
+        /// listed by the CLI. Always [Some] in a [Manifest].
+        #[serde(skip)]
+        files: Option<Vec<String>>,
+    },
+}
+
+/// A PACS server known to _ChRIS_.
+#[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PacsSpec {
+    /// Whether the PACS can be queried. Only applied when the PACS is
+    /// created. (Default: CUBE's default)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active: Option<bool>,
+}
+
+/// Specification to add DICOM files to the storage of a PACS, as if they
+/// were retrieved from the PACS.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PacsFilesSpec {
+    /// Identifier of the PACS, e.g. `"MINICHRISORTHANC"`.
+    pub pacs_identifier: String,
+    /// Path of a local directory of DICOM files to upload recursively. Relative
+    /// paths are resolved by the CLI relative to the manifest file which
+    /// specifies them. Every file must be a DICOM file which identifies its
+    /// series. Cannot be used from the web.
+    pub directory: PathBuf,
+    /// The files of `directory`, which are listed and read by the CLI.
+    /// Always [Some] in a [Manifest].
+    #[serde(skip)]
+    pub files: Option<Vec<DicomFile>>,
+}
+
+/// A DICOM file of [PacsFilesSpec::directory].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DicomFile {
+    /// Path of the file relative to the directory.
+    pub relative: String,
+    pub header: DicomHeader,
+}
+
+/// Attributes of a DICOM file which identify its series.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DicomHeader {
+    pub patient_id: String,
+    pub patient_name: Option<String>,
+    pub study_date: Option<String>,
+    pub study_instance_uid: String,
+    pub study_description: Option<String>,
+    pub series_instance_uid: String,
+    pub series_description: Option<String>,
+    pub modality: Option<String>,
+}
+
+/// User-supplied pipeline, which is either defined inline or in a file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GivenPipelineSpec {
+    /// Owner of the pipeline. (Default: the admin user)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Username>,
+    #[serde(flatten)]
+    pub source: PipelineSource,
+}
+
+/// Where a pipeline is defined.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum PipelineSource {
+    /// Path of a pipeline YAML or JSON file. It must be loaded (by the CLI)
+    /// before [GivenManifest] is converted to [Manifest].
+    File { file: PathBuf },
+    /// Pipeline defined inline.
+    Inline(PipelineDefinition),
+}
+
+/// Specification to create a pipeline.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PipelineSpec {
+    /// Owner of the pipeline. (Default: the admin user)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Username>,
+    #[serde(flatten)]
+    pub definition: PipelineDefinition,
+}
+
+/// Definition of a pipeline, in the same format as how pipelines are
+/// represented by the CUBE API.
+///
+/// Ref: <https://github.com/FNNDSC/ChRIS_ultron_backEnd/blob/v6.4.0/chris_backend/pipelines/serializers.py>
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PipelineDefinition {
+    /// Name of the pipeline, which is unique.
+    pub name: String,
+    #[serde(default)]
+    pub authors: String,
+    #[serde(default)]
+    pub category: String,
+    #[serde(default)]
+    pub description: String,
+    /// Whether the pipeline is locked from further modification. (Default: true)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locked: Option<bool>,
+    pub plugin_tree: Vec<PipelinePiece>,
+}
+
+/// A plugin of a pipeline.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PipelinePiece {
+    pub title: String,
+    pub plugin_name: String,
+    pub plugin_version: String,
+    /// Index of the parent piece in the pipeline's `plugin_tree`.
+    /// Only the root piece does not have a parent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_index: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plugin_parameter_defaults: Vec<ParameterDefault>,
+}
+
+impl PipelinePiece {
+    /// The plugin of this piece.
+    pub fn plugin(&self) -> PluginSpec {
+        PluginSpec::new(
+            self.plugin_name.as_str(),
+            Some(self.plugin_version.as_str().into()),
+        )
+    }
+}
+
+/// Default value of a plugin parameter in a pipeline.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ParameterDefault {
+    pub name: String,
+    pub default: ArgValue,
+}
+
+/// Specification to create a feed.
+///
+/// The feed of a [GivenManifest] can be shared with [ShareTarget::Public],
+/// which is replaced by `public = true` in the feed of a [Manifest].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FeedSpec<T = PermissionTarget> {
+    pub name: String,
+    /// Owner of the feed. (Default: the admin user)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Username>,
+    /// Users and groups to share the feed with.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub share: Vec<T>,
+    /// Whether the feed is visible to everyone. `share = ["public"]` is the
+    /// same as `public = true`. (Default: unchanged)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public: Option<bool>,
+    /// Content of the feed's note, which describes the feed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Names of tags to tag the feed with. Tags are owned by the feed's owner,
+    /// and are created if they do not exist.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Comments on the feed by its owner.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub comments: Vec<CommentSpec>,
+    /// _ds_-type plugin instances to create in the feed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plugin_instances: Vec<PluginInstanceSpec>,
+    /// Pipelines to run on the feed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub workflows: Vec<WorkflowSpec>,
+    #[serde(flatten)]
+    pub plugin: FsPluginSpec,
+}
+
+/// Specification of a comment on a feed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CommentSpec {
+    /// Title of the comment. A comment is found by its title and feed.
+    pub title: String,
+    pub content: String,
+}
+
+/// Specification to create a _ds_-type plugin instance in a feed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PluginInstanceSpec {
+    pub plugin: PluginSpec,
+    /// Title of the plugin instance. A plugin instance is found by its title,
+    /// plugin, and previous plugin instance. It is an error if it was run with
+    /// different arguments, so running the plugin with different arguments
+    /// needs a different title. (Default: the name of the plugin)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Title of the plugin instance to run the plugin after, which must be
+    /// declared before this one if it is also declared in `plugin_instances`.
+    /// (Default: the root plugin instance of the feed)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub args: HashMap<CompactString, ArgValue>,
+}
+
+/// Specification to run a pipeline on a feed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WorkflowSpec {
+    /// Name of the pipeline, which must be declared in `pipeline`.
+    pub pipeline: String,
+    /// Title of the plugin instance in the feed to run the pipeline after.
+    /// (Default: the root plugin instance of the feed)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<String>,
+    /// Title of the workflow. A workflow is found by its title, pipeline,
+    /// owner, and previous plugin instance. The plugin instances which it
+    /// creates can be the `previous` of workflows declared after it.
+    /// (Default: "{feed name}: {pipeline}")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// Specification to run a _fs_-type plugin.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum FsPluginSpec {
+    /// Run [pl-dircopy](https://github.com/FNNDSC/pl-dircopy) to copy files
+    /// from the path in _ChRIS_ storage.
+    Dircopy {
+        #[serde(rename = "dircopy")]
+        path: String,
+    },
+    /// Run any other _fs_-type plugin.
+    Other {
+        plugin: PluginSpec,
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        args: HashMap<CompactString, ArgValue>,
+    },
+}
+
+/// Plugin argument values.
+///
+/// Ref: <https://github.com/FNNDSC/ChRIS_ultron_backEnd/blob/v6.4.0/chris_backend/plugins/enums.py#L3-L5>
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ArgValue {
+    /// string, path, or unextpath type
+    Stringish(String),
+    /// boolean type
+    Boolean(bool),
+    /// integer type (must come before [ArgValue::Float] for `untagged` to deserialize integers as such)
+    Integer(i64),
+    /// float type
+    Float(f64),
+}
@@ -72,3 +72,21 @@ pub struct Group(CompactString);
     Into,
 ))]
 pub struct EmailDomain(CompactString);
+
+/// ChRIS compute resource name.
+#[nutype(derive(
+    Display,
+    Debug,
+    Clone,
+    Eq,
+    PartialEq,
+    Hash,
+    FromStr,
+    AsRef,
+    Deref,
+    Serialize,
+    Deserialize,
+    From,
+    Into,
+))]
+pub struct ComputeResourceName(CompactString);
@@ -1,8 +1,10 @@
 use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
 
 use chrisomatic_spec::*;
 use compact_str::CompactString;
 use pretty_assertions::assert_eq;
+use rstest::rstest;
 
 #[test]
 fn test_convert_empty() {
@@ -20,6 +22,10 @@ fn test_convert_no_cube() {
             admin: Some(UserCredentials::basic_auth("chris", "chris1234")),
             email_domain: None,
             public_cube: None,
+            upload_concurrency: None,
+            wait: None,
+            wait_timeout: None,
+            reset_password: None,
         },
         ..Default::default()
     };
@@ -36,10 +42,12 @@ fn test_reduce_duplicate_user() {
             ..Default::default()
         },
         user: create_users(["alice", "bobby"]),
+        ..Default::default()
     };
     let manifest2 = GivenManifest {
         global: Default::default(),
         user: create_users(["bobby", "samuel"]),
+        ..Default::default()
     };
     let actual = reduce([manifest1, manifest2]);
     let duplicate = Username::new(CompactString::const_new("bobby"));
@@ -47,6 +55,23 @@ fn test_reduce_duplicate_user() {
     assert_eq!(actual, Err(expected));
 }
 
+#[test]
+fn test_reduce_duplicate_group() {
+    let pacs_users = Group::new(CompactString::const_new("pacs_users"));
+    let exclusive = GroupSpec { exclusive: true };
+    let manifest1 = GivenManifest {
+        group: HashMap::from([(pacs_users.clone(), exclusive.clone())]),
+        ..Default::default()
+    };
+    let manifest2 = GivenManifest {
+        group: HashMap::from([(pacs_users.clone(), exclusive)]),
+        ..Default::default()
+    };
+    let actual = reduce([manifest1, manifest2]);
+    let expected = ManifestError::DuplicateGroup(pacs_users);
+    assert_eq!(actual, Err(expected));
+}
+
 #[test]
 fn test_reduce_multiple_users() {
     let manifest1 = GivenManifest {
@@ -56,10 +81,12 @@ fn test_reduce_multiple_users() {
             ..Default::default()
         },
         user: create_users(["alice", "bobby"]),
+        ..Default::default()
     };
     let manifest2 = GivenManifest {
         global: Default::default(),
         user: create_users(["samuel", "washington"]),
+        ..Default::default()
     };
     let actual: HashSet<_> = reduce([manifest1, manifest2])
         .unwrap()
@@ -72,6 +99,401 @@ fn test_reduce_multiple_users() {
     assert_eq!(actual, expected)
 }
 
+#[test]
+fn test_reduce_plugins() {
+    let manifest1 = GivenManifest {
+        plugins: vec![PluginSpec::from_str("pl-dircopy").unwrap().into()],
+        ..Default::default()
+    };
+    let manifest2 = GivenManifest {
+        plugins: vec![PluginEntry {
+            plugin: PluginSpec::from_str("pl-dcm2niix@1.0.0").unwrap(),
+            compute_resources: vec![ComputeResourceName::new(CompactString::const_new("gpu"))],
+            descriptor: None,
+        }],
+        ..Default::default()
+    };
+    let actual: Vec<_> = reduce([manifest1, manifest2])
+        .unwrap()
+        .plugins
+        .into_iter()
+        .map(|entry| entry.plugin)
+        .collect();
+    let expected = vec![
+        PluginSpec::new("pl-dircopy", None),
+        PluginSpec::new("pl-dcm2niix", Some(CompactString::const_new("1.0.0"))),
+    ];
+    assert_eq!(actual, expected)
+}
+
+#[test]
+fn test_convert_undeclared_feed_owner() {
+    let manifest = GivenManifest {
+        global: GivenGlobal {
+            cube: Some(CubeUrl::try_new("https://cube.example.org/api/v1/").unwrap()),
+            admin: Some(UserCredentials::basic_auth("chris", "chris1234")),
+            ..Default::default()
+        },
+        user: create_users(["alice"]),
+        feed: vec![FeedSpec {
+            name: "Example feed".to_string(),
+            owner: Some(Username::from("bobby")),
+            share: vec![],
+            public: None,
+            note: None,
+            tags: vec![],
+            comments: vec![],
+            plugin_instances: vec![],
+            workflows: vec![],
+            plugin: FsPluginSpec::Dircopy {
+                path: "home/alice/uploads".to_string(),
+            },
+        }],
+        ..Default::default()
+    };
+    let actual: Result<Manifest, _> = manifest.try_into();
+    let expected = ManifestError::UndeclaredUser {
+        key: "feed.owner",
+        username: Username::from("bobby"),
+    };
+    assert_eq!(actual, Err(expected));
+}
+
+#[test]
+fn test_convert_undeclared_userfile_owner() {
+    let manifest = GivenManifest {
+        global: GivenGlobal {
+            cube: Some(CubeUrl::try_new("https://cube.example.org/api/v1/").unwrap()),
+            admin: Some(UserCredentials::basic_auth("chris", "chris1234")),
+            ..Default::default()
+        },
+        userfiles: vec![UserFileSpec {
+            path: "home/bobby/README.txt".to_string(),
+            owner: Some(Username::from("bobby")),
+            share: vec![],
+            content: UserFileContent::Text {
+                text: "hello".to_string(),
+            },
+        }],
+        ..Default::default()
+    };
+    let actual: Result<Manifest, _> = manifest.try_into();
+    let expected = ManifestError::UndeclaredUser {
+        key: "userfiles.owner",
+        username: Username::from("bobby"),
+    };
+    assert_eq!(actual, Err(expected));
+}
+
+#[test]
+fn test_convert_duplicate_userfile() {
+    let userfile = UserFileSpec {
+        path: "home/chris/README.txt".to_string(),
+        owner: None,
+        share: vec![],
+        content: UserFileContent::Text {
+            text: "hello".to_string(),
+        },
+    };
+    let manifest = GivenManifest {
+        global: GivenGlobal {
+            cube: Some(CubeUrl::try_new("https://cube.example.org/api/v1/").unwrap()),
+            admin: Some(UserCredentials::basic_auth("chris", "chris1234")),
+            ..Default::default()
+        },
+        userfiles: vec![userfile.clone(), userfile],
+        ..Default::default()
+    };
+    let actual: Result<Manifest, _> = manifest.try_into();
+    let expected = ManifestError::DuplicateUserFile("home/chris/README.txt".to_string());
+    assert_eq!(actual, Err(expected));
+}
+
+#[test]
+fn test_convert_duplicate_pipeline() {
+    let pipeline = GivenPipelineSpec {
+        owner: None,
+        source: PipelineSource::Inline(PipelineDefinition {
+            name: "Example pipeline".to_string(),
+            authors: String::new(),
+            category: String::new(),
+            description: String::new(),
+            locked: None,
+            plugin_tree: vec![PipelinePiece {
+                title: "copy".to_string(),
+                plugin_name: "pl-dircopy".to_string(),
+                plugin_version: "2.1.2".to_string(),
+                previous_index: None,
+                plugin_parameter_defaults: vec![],
+            }],
+        }),
+    };
+    let manifest = GivenManifest {
+        global: GivenGlobal {
+            cube: Some(CubeUrl::try_new("https://cube.example.org/api/v1/").unwrap()),
+            admin: Some(UserCredentials::basic_auth("chris", "chris1234")),
+            ..Default::default()
+        },
+        pipeline: vec![pipeline.clone(), pipeline],
+        ..Default::default()
+    };
+    let actual: Result<Manifest, _> = manifest.try_into();
+    let expected = ManifestError::DuplicatePipeline("Example pipeline".to_string());
+    assert_eq!(actual, Err(expected));
+}
+
+#[test]
+fn test_convert_unloaded_pipeline_file() {
+    let manifest = GivenManifest {
+        global: GivenGlobal {
+            cube: Some(CubeUrl::try_new("https://cube.example.org/api/v1/").unwrap()),
+            admin: Some(UserCredentials::basic_auth("chris", "chris1234")),
+            ..Default::default()
+        },
+        pipeline: vec![GivenPipelineSpec {
+            owner: None,
+            source: PipelineSource::File {
+                file: "pipeline.yml".into(),
+            },
+        }],
+        ..Default::default()
+    };
+    let actual: Result<Manifest, _> = manifest.try_into();
+    let expected = ManifestError::UnloadedPipelineFile("pipeline.yml".into());
+    assert_eq!(actual, Err(expected));
+}
+
+#[test]
+fn test_convert_unloaded_plugin_descriptor() {
+    let plugin = PluginSpec::from_str("pl-local").unwrap();
+    let manifest = GivenManifest {
+        global: GivenGlobal {
+            cube: Some(CubeUrl::try_new("https://cube.example.org/api/v1/").unwrap()),
+            admin: Some(UserCredentials::basic_auth("chris", "chris1234")),
+            ..Default::default()
+        },
+        plugins: vec![PluginEntry {
+            plugin: plugin.clone(),
+            compute_resources: vec![],
+            descriptor: Some(PluginDescriptor::File("pl-local.json".into())),
+        }],
+        ..Default::default()
+    };
+    let actual: Result<Manifest, _> = manifest.try_into();
+    let expected = ManifestError::UnloadedPluginDescriptor(plugin);
+    assert_eq!(actual, Err(expected));
+}
+
+#[test]
+fn test_convert_unlisted_directory() {
+    let mut manifest = manifest_with_userfiles(true, None);
+    manifest.userfiles[0].share = vec![];
+    let UserFileContent::Directory { files, .. } = &mut manifest.userfiles[0].content else {
+        panic!("expected a directory");
+    };
+    *files = None;
+    let actual: Result<Manifest, _> = manifest.try_into();
+    let expected = ManifestError::UnlistedDirectory("home/chris/data".to_string());
+    assert_eq!(actual, Err(expected));
+}
+
+#[test]
+fn test_convert_undeclared_pipeline() {
+    let manifest = GivenManifest {
+        global: GivenGlobal {
+            cube: Some(CubeUrl::try_new("https://cube.example.org/api/v1/").unwrap()),
+            admin: Some(UserCredentials::basic_auth("chris", "chris1234")),
+            ..Default::default()
+        },
+        feed: vec![FeedSpec {
+            name: "Example feed".to_string(),
+            owner: None,
+            share: vec![],
+            public: None,
+            note: None,
+            tags: vec![],
+            comments: vec![],
+            plugin_instances: vec![],
+            workflows: vec![WorkflowSpec {
+                pipeline: "Example pipeline".to_string(),
+                previous: None,
+                title: None,
+            }],
+            plugin: FsPluginSpec::Dircopy {
+                path: "home/chris/uploads".to_string(),
+            },
+        }],
+        ..Default::default()
+    };
+    let actual: Result<Manifest, _> = manifest.try_into();
+    let expected = ManifestError::UndeclaredPipeline("Example pipeline".to_string());
+    assert_eq!(actual, Err(expected));
+}
+
+#[test]
+fn test_convert_undeclared_pacs() {
+    let mut user = create_users(["alice"]);
+    for details in user.values_mut() {
+        details.pacs_queries = vec![PacsQuerySpec {
+            title: "Brain MRIs".to_string(),
+            pacs: "MINICHRISORTHANC".to_string(),
+            query: HashMap::from([("Modality".to_string(), "MR".to_string())]),
+            description: None,
+        }];
+    }
+    let manifest = GivenManifest {
+        global: GivenGlobal {
+            cube: Some(CubeUrl::try_new("https://cube.example.org/api/v1/").unwrap()),
+            admin: Some(UserCredentials::basic_auth("chris", "chris1234")),
+            ..Default::default()
+        },
+        user,
+        ..Default::default()
+    };
+    let actual: Result<Manifest, _> = manifest.try_into();
+    let expected = ManifestError::UndeclaredPacs("MINICHRISORTHANC".to_string());
+    assert_eq!(actual, Err(expected));
+}
+
+#[test]
+fn test_convert_share_public() {
+    let manifest = manifest_with_feed(vec![ShareTarget::Public], None);
+    let actual: Manifest = manifest.try_into().unwrap();
+    assert_eq!(actual.feed[0].share, vec![]);
+    assert_eq!(actual.feed[0].public, Some(true));
+}
+
+#[test]
+fn test_convert_contradictory_public() {
+    let manifest = manifest_with_feed(vec![ShareTarget::Public], Some(false));
+    let actual: Result<Manifest, _> = manifest.try_into();
+    let expected = ManifestError::ContradictoryPublic("Example feed".to_string());
+    assert_eq!(actual, Err(expected));
+}
+
+#[test]
+fn test_convert_share_public_folder() {
+    let manifest = manifest_with_userfiles(true, Some(true));
+    let actual: Manifest = manifest.try_into().unwrap();
+    assert_eq!(actual.userfiles[0].share, vec![]);
+    let UserFileContent::Directory { public, .. } = &actual.userfiles[0].content else {
+        panic!("expected a directory");
+    };
+    assert_eq!(*public, Some(true));
+}
+
+#[test]
+fn test_convert_contradictory_public_folder() {
+    let manifest = manifest_with_userfiles(true, Some(false));
+    let actual: Result<Manifest, _> = manifest.try_into();
+    let expected = ManifestError::ContradictoryPublicFolder("home/chris/data".to_string());
+    assert_eq!(actual, Err(expected));
+}
+
+#[test]
+fn test_convert_share_public_userfile() {
+    let manifest = manifest_with_userfiles(false, None);
+    let actual: Result<Manifest, _> = manifest.try_into();
+    let expected = ManifestError::PublicUserFile("home/chris/data".to_string());
+    assert_eq!(actual, Err(expected));
+}
+
+#[rstest]
+#[case(r#"share = ["alice"]"#, FilePermission::Read)]
+#[case(r#"share = [{ target = "alice" }]"#, FilePermission::Read)]
+#[case(
+    r#"share = [{ target = "alice", permission = "write" }]"#,
+    FilePermission::Write
+)]
+#[case(
+    r#"share = [{ target = "alice", permission = "w" }]"#,
+    FilePermission::Write
+)]
+fn test_deserialize_file_share(#[case] share: &str, #[case] permission: FilePermission) {
+    let data = format!("path = \"home/chris/README.txt\"\ntext = \"hello\"\n{share}");
+    let actual: UserFileSpec = toml::from_str(&data).unwrap();
+    let expected = FileShare {
+        target: PermissionTarget::User(Username::from("alice")),
+        permission,
+    };
+    assert_eq!(actual.share, vec![expected]);
+}
+
+#[test]
+fn test_deserialize_file_share_public() {
+    let data = "path = \"home/chris/data\"\ndirectory = \"data\"\nshare = [\"public\"]";
+    let given: UserFileSpec<ShareTarget> = toml::from_str(data).unwrap();
+    assert_eq!(given.share, vec![ShareTarget::Public.into()]);
+    let canonical: Result<UserFileSpec, _> = toml::from_str(data);
+    assert!(canonical.is_err());
+}
+
+#[rstest]
+#[case("wait = true")]
+#[case("wait_timeout = 60")]
+#[case("reset_password = true")]
+fn test_global_without_cube(#[case] global: &str) {
+    let data = format!("[global]\n{global}\n");
+    let given: GivenManifest = toml::from_str(&data).unwrap();
+    assert!(!given.global.is_none());
+    assert_eq!(toml::to_string(&given).unwrap(), data);
+}
+
+fn manifest_with_userfiles(directory: bool, public: Option<bool>) -> GivenManifest {
+    let content = if directory {
+        UserFileContent::Directory {
+            directory: "data".into(),
+            include: vec![],
+            exclude: vec![],
+            public,
+            files: Some(vec!["README.txt".to_string()]),
+        }
+    } else {
+        UserFileContent::Text {
+            text: "hello".to_string(),
+        }
+    };
+    GivenManifest {
+        global: GivenGlobal {
+            cube: Some(CubeUrl::try_new("https://cube.example.org/api/v1/").unwrap()),
+            admin: Some(UserCredentials::basic_auth("chris", "chris1234")),
+            ..Default::default()
+        },
+        userfiles: vec![UserFileSpec {
+            path: "home/chris/data".to_string(),
+            owner: None,
+            share: vec![ShareTarget::Public.into()],
+            content,
+        }],
+        ..Default::default()
+    }
+}
+
+fn manifest_with_feed(share: Vec<ShareTarget>, public: Option<bool>) -> GivenManifest {
+    GivenManifest {
+        global: GivenGlobal {
+            cube: Some(CubeUrl::try_new("https://cube.example.org/api/v1/").unwrap()),
+            admin: Some(UserCredentials::basic_auth("chris", "chris1234")),
+            ..Default::default()
+        },
+        feed: vec![FeedSpec {
+            name: "Example feed".to_string(),
+            owner: None,
+            share,
+            public,
+            note: None,
+            tags: vec![],
+            comments: vec![],
+            plugin_instances: vec![],
+            workflows: vec![],
+            plugin: FsPluginSpec::Dircopy {
+                path: "home/chris/uploads".to_string(),
+            },
+        }],
+        ..Default::default()
+    }
+}
+
 fn create_users(
     usernames: impl IntoIterator<Item = &'static str>,
 ) -> HashMap<Username, GivenUserDetails> {
@@ -84,6 +506,9 @@ fn create_users(
                     password: format!("{username}1234"),
                     email: None,
                     groups: vec![],
+                    admin: false,
+                    links: vec![],
+                    pacs_queries: vec![],
                 },
             )
         })
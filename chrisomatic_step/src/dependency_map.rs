@@ -1,6 +1,6 @@
 use std::rc::Rc;
 
-use chrisomatic_spec::{PluginSpec, Username};
+use chrisomatic_spec::{ComputeResourceName, Group, PermissionTarget, PluginSpec, Username};
 
 /// [Dependency] and value pair.
 pub type Entry = (Dependency, String);
@@ -13,8 +13,120 @@ pub enum Dependency {
     UserUrl(Username),
     UserGroupsUrl(Username),
     UserEmail(Username),
+    /// A placeholder key which, if present, guarantees that the user does not
+    /// exist with a password different from what is specified.
+    UserPassword(Username),
+    /// A placeholder key which, if present, guarantees that the user is a staff user.
+    UserStaff(Username),
+    /// URL of a user found using the admin user's privileges, or `none` if
+    /// the user does not exist.
+    UserFound(Username),
     AuthToken(Username),
+    /// Authorization token of the admin user (`global.admin`), which is
+    /// checked to be valid.
+    AdminToken,
     PluginUrl(PluginSpec),
+    PluginAdminUrl(PluginSpec),
+    PluginComputeResourcesUrl(PluginSpec),
+    PluginInstancesUrl(PluginSpec),
+    /// A placeholder key which, if present, guarantees that the plugin is assigned to its specified compute resources.
+    PluginComputeResources(PluginSpec),
+    /// URL of a compute resource in the admin API.
+    ComputeResourceUrl(ComputeResourceName),
+    GroupUrl(Group),
+    GroupUsersUrl(Group),
+    /// A placeholder key which, if present, guarantees that the user is a member of the group.
+    GroupMember(Group, Username),
+    /// A placeholder key which, if present, guarantees that the group has no
+    /// members other than those specified.
+    GroupExclusive(Group),
+    /// URL of a user file, identified by its path.
+    UserFileUrl(String),
+    /// URL for downloading a user file, identified by its path.
+    UserFileResourceUrl(String),
+    /// Size in bytes of a user file, identified by its path.
+    UserFileSize(String),
+    /// A placeholder key which, if present, guarantees that the user file's content is as specified.
+    /// Its value is the SHA-256 digest of the content.
+    UserFileContent(String),
+    /// URL of a folder, identified by its path.
+    FolderUrl(String),
+    /// A placeholder key which, if present, guarantees that the folder (identified
+    /// by its path) is public or not as specified.
+    FolderPublic(String),
+    /// URL of the list of link files of a folder, identified by its path.
+    FolderLinkFilesUrl(String),
+    /// URL of a link file, identified by its path.
+    LinkFileUrl(String),
+    /// URL of the permission of a user or group on a user file or folder,
+    /// identified by the path of the user file or folder.
+    FilePermissionUrl(String, PermissionTarget),
+    /// A placeholder key which, if present, guarantees that the user file or
+    /// folder (identified by its path) is shared with the target with the
+    /// specified permission.
+    FileShared(String, PermissionTarget),
+    /// URL of a PACS, identified by its identifier.
+    PacsUrl(String),
+    /// URL of a PACS query, identified by its owner and title.
+    PacsQueryUrl(Username, String),
+    /// URL of a PACS series, identified by the identifier of its PACS and by
+    /// its SeriesInstanceUID.
+    PacsSeriesUrl(String, String),
+    /// URL of a pipeline, identified by its name.
+    PipelineUrl(String),
+    /// URL of a feed, identified by its owner and name.
+    FeedUrl(Username, String),
+    FeedId(Username, String),
+    FeedUserPermissionsUrl(Username, String),
+    FeedGroupPermissionsUrl(Username, String),
+    /// URL of the note of a feed, identified by the feed's owner and name.
+    FeedNoteUrl(Username, String),
+    /// URL of the list of taggings of a feed, identified by the feed's owner and name.
+    FeedTaggingsUrl(Username, String),
+    /// URL of the list of comments on a feed, identified by the feed's owner and name.
+    FeedCommentsUrl(Username, String),
+    /// A placeholder key which, if present, guarantees that the feed is shared with the target.
+    FeedShared(Username, String, PermissionTarget),
+    /// A placeholder key which, if present, guarantees that the feed is public or
+    /// not as specified.
+    FeedPublic(Username, String),
+    /// A placeholder key which, if present, guarantees that the note of the feed is as specified.
+    FeedNote(Username, String),
+    /// ID of a tag, identified by its owner and name.
+    TagId(Username, String),
+    /// A placeholder key which, if present, guarantees that the feed (identified by
+    /// its owner and name) is tagged with the tag of the feed's owner by the name.
+    FeedTagged(Username, String, String),
+    /// URL of a comment on a feed, identified by the owner and name of its feed, and by its title.
+    FeedCommentUrl(Username, String, String),
+    /// A placeholder key which, if present, guarantees that the content of the comment
+    /// (identified the same way as [Dependency::FeedCommentUrl]) is as specified.
+    FeedCommentContent(Username, String, String),
+    /// ID of a plugin instance, identified by the owner and name of its feed,
+    /// and by its title (or [None] for the root plugin instance of the feed).
+    PluginInstanceId(Username, String, Option<String>),
+    /// A placeholder key which, if present, guarantees that the arguments of a
+    /// plugin instance (identified the same way as [Dependency::PluginInstanceId])
+    /// are valid for its plugin. Its value is the URL of the plugin's parameters.
+    PluginArgsValid(Username, String, Option<String>),
+    /// A placeholder key which, if present, guarantees that the plugin instance
+    /// (identified by the owner and name of its feed, and by its title) was run
+    /// with the specified arguments.
+    PluginInstanceArgs(Username, String, String),
+    /// URL of a workflow, identified by the owner and name of its feed, and by its title.
+    WorkflowUrl(Username, String, String),
+    /// ID of a workflow, identified the same way as [Dependency::WorkflowUrl].
+    WorkflowId(Username, String, String),
+    /// IDs of the workflows which were run after a plugin instance (identified
+    /// the same way as [Dependency::PluginInstanceId]), separated by commas.
+    WorkflowsAfter(Username, String, Option<String>),
+    /// Status of a plugin instance (identified the same way as [Dependency::PluginInstanceId])
+    /// which has finished successfully.
+    PluginInstanceFinished(Username, String, Option<String>),
+    /// A placeholder key which, if present, guarantees that all the jobs of a workflow
+    /// (identified the same way as [Dependency::WorkflowUrl]) have finished successfully.
+    /// Its value is the number of finished jobs.
+    WorkflowFinished(Username, String, String),
 }
 
 pub trait DependencyMap {
@@ -1,4 +1,5 @@
 use std::rc::Rc;
+use std::time::Duration;
 
 use nonempty::NonEmpty;
 
@@ -36,15 +37,31 @@ pub fn ok_step(step: impl Step + 'static) -> PendingStepResult {
 /// 3. If [StatusCheck::DoesNotExist] is returned by [Step::check_status], call
 ///    [Step::create] and send the HTTP request to create the API resource.
 /// 4. Else if [StatusCheck::Exists] is returned by [Step::check_status], call
-///    [Step::deserialize] to decide what to do next.
+///    [Step::deserialize] to decide what to do next. (Or, if [StatusCheck::Invalid]
+///    is returned, the step fails.)
 /// 5. If [Check::DoesNotExist] is returned by [Step::deserialize], call
 ///    [Step::create] and send the HTTP request to create the API resource.
 /// 6. Else if [Check::NeedsModification] is returned by [Step::deserialize],
 ///    call [Step::modify] and send the HTTP request to modify the API resource.
+/// 7. Else if [Check::NeedsRemoval] is returned by [Step::deserialize], send
+///    each of its HTTP requests to remove what is not specified.
+/// 8. Else if [Check::NeedsReplacement] is returned by [Step::deserialize], send
+///    each of its HTTP requests in order to replace the API resource.
+/// 9. Else if [Check::Invalid] or [Check::Failed] is returned by [Step::deserialize],
+///    the step fails without creating nor modifying anything.
+/// 10. Else if [Check::Pending] is returned by [Step::deserialize], wait a while
+///    and go back to step 1, until [Step::timeout] returns zero.
 pub trait Step {
     /// Create an HTTP request which searches the API for this resource.
     fn search(&self) -> reqwest::Request;
 
+    /// Whether the response to [Step::search] is the first page of a paginated
+    /// list. If so, the `results` of its next pages are appended to its
+    /// `results` before [Step::deserialize] is called.
+    fn paginated(&self) -> bool {
+        false
+    }
+
     /// Check the HTTP response status code to the request of [Step::search].
     fn check_status(&self, status: reqwest::StatusCode) -> StatusCheck {
         if status.is_success() {
@@ -67,6 +84,12 @@ pub trait Step {
         None
     }
 
+    /// Maximum duration, from now, to keep searching for while
+    /// [Step::deserialize] returns [Check::Pending].
+    fn timeout(&self) -> Duration {
+        Duration::ZERO
+    }
+
     /// Returns keys of what this step provides unconditionally when successful.
     ///
     /// The first item returned is considered to be the "primary" API resource
@@ -88,6 +111,13 @@ pub trait StepRequest {
     /// Create the HTTP request.
     fn request(&self) -> reqwest::Request;
 
+    /// Explain an unsuccessful HTTP response, given its status and body, which
+    /// means that the spec is invalid, e.g. it conflicts with a resource which
+    /// exists in the API. Any other unsuccessful response is an error.
+    fn invalid_status(&self, _status: reqwest::StatusCode, _body: &[u8]) -> Option<String> {
+        None
+    }
+
     /// Deserialize the HTTP response body.
     fn deserialize(&self, body: bytes::Bytes) -> serde_json::Result<Entries>;
 }
@@ -102,6 +132,26 @@ pub enum Check {
     DoesNotExist,
     /// The resource exists but needs modification.
     NeedsModification,
+    /// The resource exists, but some of its parts which are not specified
+    /// need to be removed by the given requests (of which there should be at
+    /// least one). The entries are produced once all requests succeed, along
+    /// with the entries produced by the requests.
+    NeedsRemoval {
+        requests: Vec<Box<dyn StepRequest>>,
+        entries: Entries,
+    },
+    /// The resource exists but cannot be modified in place, so it needs to be
+    /// replaced by the given requests, which are sent in order (e.g. to delete
+    /// the resource and then create it again). The entries are those produced
+    /// by the requests.
+    NeedsReplacement(Vec<Box<dyn StepRequest>>),
+    /// The spec is incompatible with what exists in the API, so the resource
+    /// should be neither created nor modified. The reason is given.
+    Invalid(String),
+    /// The resource exists but is not ready yet, so it should be searched for again later.
+    Pending,
+    /// The resource exists but is in a failed state. The reason is given.
+    Failed(String),
 }
 
 /// Status conveyed by HTTP response status code.
@@ -112,4 +162,7 @@ pub enum StatusCheck {
     DoesNotExist,
     /// There was an error.
     Error,
+    /// The spec is incompatible with what exists in the API, e.g. the
+    /// credentials are wrong. The reason is given.
+    Invalid(String),
 }
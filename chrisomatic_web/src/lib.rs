@@ -1,4 +1,4 @@
-use chrisomatic_core::{Counts, StepEffect, fully_exec_tree, plan};
+use chrisomatic_core::{Counts, StepEffect, fully_exec_tree, plan, warnings_of};
 use chrisomatic_spec::*;
 use wasm_bindgen::prelude::*;
 
@@ -20,7 +20,8 @@ pub fn validate_manifest(
 /// TOML-formatted _chrisomatic_ manifest (hint: validate it with
 /// [validate_manifest]).
 ///
-/// Returns list of error messages describing failed steps.
+/// Returns list of error messages describing failed steps, followed by
+/// warnings about users made staff users and members removed from groups.
 #[wasm_bindgen]
 pub async fn run_chrisomatic(
     text: &str,
@@ -30,6 +31,7 @@ pub async fn run_chrisomatic(
     on_progress: &js_sys::Function,
 ) -> Result<Vec<String>, String> {
     let manifest = canonicalize_manifest(text, url, username, token).map_err(|e| e.to_string())?;
+    let staff = manifest.staff_users();
     let tree = plan(manifest);
     let client = reqwest::Client::new();
     let affected = fully_exec_tree(client, tree, |counts| {
@@ -37,7 +39,10 @@ pub async fn run_chrisomatic(
         let _ = on_progress.call1(&this, &counts_to_object(counts));
     })
     .await;
-    let error_messages = affected
+    let warnings = warnings_of(&affected, &staff)
+        .into_iter()
+        .map(|warning| format!("warning: {warning}"));
+    let messages = affected
         .into_iter()
         .filter_map(|(target, effect)| {
             if matches!(&effect, StepEffect::Unfulfilled(..) | StepEffect::Error(_)) {
@@ -46,8 +51,9 @@ pub async fn run_chrisomatic(
                 None
             }
         })
+        .chain(warnings)
         .collect();
-    Ok(error_messages)
+    Ok(messages)
 }
 
 #[allow(unused_must_use)]
@@ -55,6 +61,7 @@ fn counts_to_object(counts: Counts) -> js_sys::Object {
     let obj = js_sys::Object::new();
     js_sys::Reflect::set(&obj, &"created".into(), &counts.created.into());
     js_sys::Reflect::set(&obj, &"modified".into(), &counts.modified.into());
+    js_sys::Reflect::set(&obj, &"removed".into(), &counts.removed.into());
     js_sys::Reflect::set(&obj, &"unmodified".into(), &counts.unmodified.into());
     js_sys::Reflect::set(&obj, &"unfulfilled".into(), &counts.unfulfilled.into());
     js_sys::Reflect::set(&obj, &"error".into(), &counts.error.into());
@@ -81,6 +88,24 @@ fn canonicalize_manifest(
     token: &str,
 ) -> Result<Manifest, Box<dyn std::error::Error>> {
     let mut given: GivenManifest = toml::from_str(text)?;
+    if let Some(userfile) = given
+        .userfiles
+        .iter()
+        .find(|userfile| !matches!(userfile.content, UserFileContent::Text { .. }))
+    {
+        return Err(format!(
+            "Local files cannot be uploaded from the web, please use `text` for \"{}\"",
+            userfile.path
+        )
+        .into());
+    }
+    if let Some(pacs_files) = given.pacs_files.first() {
+        return Err(format!(
+            "Local files cannot be uploaded from the web, please remove `pacs_files` of \"{}\"",
+            pacs_files.pacs_identifier
+        )
+        .into());
+    }
     if given.global.cube.is_none() {
         given.global.cube = Some(CubeUrl::try_new(url)?);
     }